@@ -0,0 +1,163 @@
+//! Parsing options and a config, building the option tree and attaching the config to it,
+//! like `nixos-config-browser` does on startup.
+//!
+//! Uses a recorded host when `NIXOS_DRUID_BENCH_OPTIONS` and `NIXOS_DRUID_BENCH_CONFIG`
+//! point to the JSON `run::get_options_from` and `run::get_config` cache, e.g.
+//! `/tmp/nixos-options-<hostname>.json` and `/tmp/nixosConfig.json` after running
+//! `nixos-config-browser` once. Otherwise a config with many `users.users` and
+//! `systemd.services`-like entries is generated.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nixos_druid::node::{ExtraChildDisplay, OptionNode};
+use nixos_druid::parse::snapshot::{self, CacheKey};
+use nixos_druid::parse::{NixGuardedValue, NixValue};
+use serde_json::{json, Map, Value};
+
+fn option(type_name: &str) -> Value {
+    json!({
+        "_option": true,
+        "description": format!("A {type_name} option with a description of a realistic length, \
+            the descriptions of the submodule options are what used to be copied for every entry."),
+        "default": null,
+        "example": null,
+        "type": {
+            "_type": true,
+            "name": type_name,
+            "description": type_name,
+            "functorName": type_name,
+            "nestedTypes": {},
+        },
+    })
+}
+
+/// An `attrsOf (submodule ...)` option with `option_count` options,
+/// some of them submodules themselves
+fn attrs_of_submodule(option_count: usize) -> Value {
+    let mut options = Map::new();
+    for i in 0..option_count {
+        options.insert(format!("option{i}"), option("str"));
+    }
+    let nested: Map<String, Value> = (0..option_count / 4)
+        .map(|i| (format!("option{i}"), option("int")))
+        .collect();
+    let mut nested_option = option("submodule");
+    nested_option["type"] = json!({ "_submodule": true, "options": nested });
+    options.insert("nested".to_string(), nested_option);
+
+    json!({
+        "_option": true,
+        "description": "Entries",
+        "default": {},
+        "example": null,
+        "type": {
+            "_type": true,
+            "name": "attrsOf",
+            "description": "attribute set of submodules",
+            "functorName": "attrsOf",
+            "nestedTypes": {
+                "elemType": { "_submodule": true, "options": options },
+            },
+        },
+    })
+}
+
+/// `entries` entries setting a few of the options each
+fn entries(entries: usize) -> Value {
+    (0..entries)
+        .map(|i| {
+            let entry = json!({
+                "option0": format!("entry{i}"),
+                "option3": "value",
+                "nested": { "option1": i },
+            });
+            (format!("entry{i}"), entry)
+        })
+        .collect::<Map<String, Value>>()
+        .into()
+}
+
+/// JSON of the options and the config
+fn generated() -> (String, String) {
+    let options = json!({
+        "users": { "users": attrs_of_submodule(40) },
+        "systemd": { "services": attrs_of_submodule(120) },
+    });
+    let config = json!({
+        "users": { "users": entries(200) },
+        "systemd": { "services": entries(600) },
+    });
+
+    (options.to_string(), config.to_string())
+}
+
+fn recorded() -> Option<(String, String)> {
+    let read = |var: &str| -> Option<String> {
+        let path = std::env::var(var).ok()?;
+        Some(std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Reading `{path}`: {e}")))
+    };
+    Some((
+        read("NIXOS_DRUID_BENCH_OPTIONS")?,
+        read("NIXOS_DRUID_BENCH_CONFIG")?,
+    ))
+}
+
+fn build(options: NixValue, config: NixGuardedValue) -> OptionNode {
+    let mut tree = OptionNode::new("NixOS Configuration".to_string(), options);
+    tree.set_extra_child_display(ExtraChildDisplay::First);
+    tree.add_config(Some(config));
+    tree.mark_errors();
+    tree
+}
+
+fn tree(c: &mut Criterion) {
+    let (options_json, config_json) = recorded().unwrap_or_else(generated);
+
+    c.bench_function("parse options", |b| {
+        b.iter(|| serde_json::from_str::<NixValue>(black_box(&options_json)).unwrap())
+    });
+    c.bench_function("parse config", |b| {
+        b.iter(|| serde_json::from_str::<NixGuardedValue>(black_box(&config_json)).unwrap())
+    });
+
+    let options: NixValue = serde_json::from_str(&options_json).expect("Parsing options failed");
+    let config: NixGuardedValue =
+        serde_json::from_str(&config_json).expect("Parsing config failed");
+
+    let key = CacheKey {
+        len: 0,
+        modified: Default::default(),
+    };
+    let options_snapshot = snapshot::to_bytes(&options, key);
+    let config_snapshot = snapshot::to_bytes(&config, key);
+    c.bench_function("load options snapshot", |b| {
+        b.iter(|| snapshot::from_bytes::<NixValue>(black_box(&options_snapshot), key).unwrap())
+    });
+    c.bench_function("load config snapshot", |b| {
+        b.iter(|| {
+            snapshot::from_bytes::<NixGuardedValue>(black_box(&config_snapshot), key).unwrap()
+        })
+    });
+
+    c.bench_function("build option tree", |b| {
+        b.iter(|| OptionNode::new("NixOS".to_string(), black_box(options.clone())))
+    });
+    c.bench_function("build option tree and attach config", |b| {
+        b.iter(|| build(black_box(options.clone()), black_box(config.clone())))
+    });
+
+    let tree = build(options, config);
+    c.bench_function("toggle only errors", |b| {
+        b.iter_batched(
+            || tree.clone(),
+            |mut tree| {
+                tree.set_only_errors(true);
+                tree.set_only_errors(false);
+                tree
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, tree);
+criterion_main!(benches);
@@ -25,9 +25,6 @@
           buildInputs = with pkgs; [ gtk3 ];
           cargoBuildFlags = [ "--bin" "nixos-option-browser" "--bin" "nixos-config-browser" ];
 
-          # Tests fail at the moment because they test src/bin/node.rs alone as well
-          doCheck = false;
-
           meta = with pkgs.lib; {
             description = "WIP GUI for NixOS";
             homepage = "https://github.com/syberant/nixos-druid";
@@ -1,65 +1,99 @@
 # Evaluate this file with:
 # nix-instantiate --eval extract.nix --strict --json --argstr foo bar
 
-# Idea: Generate JSON schema for a webapp?
-# https://github.com/json-editor/json-editor
+# The output is also turned into a JSON Schema, see `src/schema.rs`
 
-{ utilities ? import ./utilities.nix }:
+{ utilities ? import ./utilities.nix
+  # The nixpkgs to take the NixOS modules from, a string avoids copying it to the store
+, nixpkgs ? toString <nixpkgs>
+  # Extra module files to evaluate together with the default NixOS modules
+, extraModules ? [ ]
+  # The options to export, e.g. `nixosConfigurations.<hostname>.options` of a flake
+, options ?
+  (import "${nixpkgs}/nixos" { configuration = { imports = extraModules; }; }).options
+, lib ? import "${nixpkgs}/lib" }:
 
 with builtins;
 
 let
-  inherit (import <nixpkgs> { }) lib;
-  nixosOptions = (import <nixpkgs/nixos> { configuration = { }; }).options;
+  nixosOptions = options;
 
-  inherit (utilities { inherit lib; }) catchJson isVisibleNameValue;
+  inherit (utilities { inherit lib; })
+    catchJson sameValue aliasKind aliasTarget removalInstructions removalMessage
+    isVisibleOrDeprecatedNameValue;
 in with lib;
 
 let
+  # Of the removed options, only known from their assertions, see `removalInstructions`
+  instructions = let try = tryEval (nixosOptions.assertions.value or [ ]);
+  in removalInstructions (if try.success then try.value else [ ]);
+
   # Properly export the type, arguments are as follows:
   # - shallow: boolean, if set nested suboptions (in submodule(s)) will not be exported
-  # - antiInfiniteRecursion: int, counter to prevent infinite recursion present in some types
+  # - ancestors: list of `{ type, id }`, the types this type is nested in
+  # - id: string, identity of this type in the exported JSON, the option path
+  #   followed by the names of the nested types, e.g. `users.users/elemType`
   # - type: NixOS type, actual type to export
-  fixTypes = shallow: antiInfiniteRecursion: type:
+  #
+  # Recursive types like `types.json` or a submodule containing itself are nested
+  # in themselves, they are exported once and referred to by `_typeRef` afterwards.
+  # Types rebuilt on every level, e.g. `attrsOf (submodule ./self.nix)` importing
+  # itself, are never the same value, they are cut off at the nearest ancestor with
+  # the same description once it was seen `maxRepeats` times.
+  fixTypes = shallow: ancestors: id: type:
     let
-      fixTypes' =
-        fixTypes shallow ([ type.description ] ++ antiInfiniteRecursion);
-      recurseAttrs' = recurseAttrs antiInfiniteRecursion;
+      maxRepeats = 3;
+      sameDescription = a: a.type.description == type.description;
+      enclosing = let
+        same = findFirst (a: sameValue a.type type) null ancestors;
+        similar = findFirst sameDescription null ancestors;
+      in if same != null then
+        same
+      else if count sameDescription ancestors >= maxRepeats then
+        trace "Cutting off recursive type `${type.description}` at ${id}" similar
+      else
+        null;
+      ancestors' = [{ inherit type id; }] ++ ancestors;
+      fixTypes' = name: fixTypes shallow ancestors' "${id}/${name}";
 
-      nestedTypes = mapAttrs (name: fixTypes') type.nestedTypes;
-      isInfinite = nested:
-        any (x: x ? "_infiniteRecursion") (attrValues nested);
+      nestedTypes = mapAttrs fixTypes' type.nestedTypes;
       mkType = {
         _type = true;
+        _id = id;
         inherit (type) name description;
         functorName = type.functor.name;
       };
-    in if count (x: x == type.description) antiInfiniteRecursion >= 3 then
-    # TODO: Handle infinite recursion types properly
-    # Currently counting how many times this description was seen before
-    # This method is prone to false positives
-      mkType // {
-        _infiniteRecursion = true;
+    in if enclosing != null then {
+      _typeRef = enclosing.id;
+      inherit (type) name description;
+    } else if type.name == "submodule" then
+      let
+        subOptions = type.getSubOptions [ ];
+        # Set for e.g. `settings` options (RFC 42) to allow undeclared attributes
+        freeformType = let
+          try = tryEval (subOptions._module.freeformType.value or null);
+        in if try.success then try.value else null;
+      in {
+        _submodule = true;
+        _id = id;
+        options = if shallow then { } else recurseAttrs ancestors' id subOptions;
+        freeformType = if freeformType == null then
+          null
+        else
+          fixTypes' "freeformType" freeformType;
       }
-
-      # Use this line to take a look at these infinite recursion types
-      # trace type.description (mkType // { _infiniteRecursion = true; })
-    else if type.name == "submodule" then {
-      _submodule = true;
-      options = if shallow then { } else recurseAttrs' (type.getSubOptions [ ]);
-    } else if elem type.description [
+    else if elem type.description [
       "JSON value"
       "Yaml value"
       "YAML value"
       "TOML value"
     ] then
-    # TODO: Special processing for these common infinite recursion types?
+    # The value types of `pkgs.formats`, shown as `OptionType::Structured`,
+    # their recursive structure is of no interest
       mkType // {
-        _infiniteRecursion = true;
-        _standard = true;
+        _structured = true;
+        nestedTypes = { };
       }
-    else if isInfinite nestedTypes then
-      mkType // { _infiniteRecursion = true; }
     else if type.name == "enum" then
       mkType // {
         # Extract the 'payload' of the enum
@@ -72,25 +106,31 @@ let
 
   # Export an option with the fields properly set.
   # In case this is not an option recurse into the nested options.
-  fixAttrs = antiInfiniteRecursion: name: opt:
+  fixAttrs = ancestors: id: opt:
     if isOption opt then {
       _option = true;
       description = opt.description or "";
       example = opt.example or null;
       default = opt.defaultText or (catchJson (opt.default or null));
+      declarations = map toString (opt.declarations or [ ]);
+      aliasKind = aliasKind opt;
+      aliasTarget = aliasTarget opt;
+      removalMessage = removalMessage instructions opt;
       type =
         fixTypes (if opt ? visible then opt.visible == "shallow" else false)
-        antiInfiniteRecursion opt.type;
+        ancestors id opt.type;
     } else
-      recurseAttrs antiInfiniteRecursion opt;
+      recurseAttrs ancestors id opt;
 
-  # Recursively visit all options, removes non-visible options
-  recurseAttrs = antiInfiniteRecursion: opt:
+  # Recursively visit all options, removes non-visible options except deprecated ones.
+  # `prefix` is the id of the enclosing submodule type or the path of the set.
+  recurseAttrs = ancestors: prefix: opt:
     let
-      visibleOptions = filterAttrs isVisibleNameValue opt;
-      fixAttrs' = fixAttrs antiInfiniteRecursion;
+      visibleOptions = filterAttrs isVisibleOrDeprecatedNameValue opt;
+      fixAttrs' = name:
+        fixAttrs ancestors (if prefix == "" then name else "${prefix}.${name}");
     in mapAttrs fixAttrs' visibleOptions;
 
-  # Start recursion with empty `antiInfiniteRecursion`
-  getOptionsInfo = recurseAttrs [ ];
+  # Start recursion without enclosing types
+  getOptionsInfo = recurseAttrs [ ] "";
 in getOptionsInfo nixosOptions
@@ -0,0 +1,26 @@
+{ utilities ? import ./utilities.nix, flakePath ? "/etc/nixos", hostname }:
+
+# Evaluate the `assertions` and `warnings` of a host, `extractConfig.nix` leaves these out.
+# Only the messages of failing assertions are returned, an assertion that can't
+# be evaluated counts as failing.
+
+with builtins;
+
+let
+  # Load flake
+  computer = (getFlake flakePath).nixosConfigurations.${hostname};
+
+  # Get necessary components
+  lib = computer.pkgs.lib;
+  config = computer.config;
+
+  inherit (utilities { inherit lib; }) catchErrors;
+
+  isFailing = a:
+    let try = tryEval a.assertion;
+    in !(try.success && try.value == true);
+in {
+  assertions =
+    catchErrors (map (a: a.message) (filter isFailing config.assertions));
+  warnings = catchErrors config.warnings;
+}
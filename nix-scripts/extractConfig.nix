@@ -1,4 +1,7 @@
-{ utilities ? import ./utilities.nix, flakePath ? "/etc/nixos", hostname }:
+{ utilities ? import ./utilities.nix, flakePath ? "/etc/nixos", hostname
+  # A JSON list of attribute names to only export the config below, e.g. `["services", "nginx"]`.
+  # Unlike the whole config this includes the subtrees left out below.
+, path ? null }:
 
 with builtins;
 
@@ -24,5 +27,9 @@ let
     # services.gitlab = null;
   };
 
-  inherit (utilities { inherit lib; }) catchErrors;
-in catchErrors config
+  inherit (utilities { inherit lib; }) catchErrors catchErrorsAt;
+in if path == null then
+  catchErrors config
+else
+  let attrPath = fromJSON path;
+  in catchErrorsAt attrPath (lib.attrByPath attrPath null computer.config)
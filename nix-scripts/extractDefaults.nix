@@ -0,0 +1,53 @@
+{ utilities ? import ./utilities.nix, flakePath ? "/etc/nixos", hostname }:
+
+# Evaluate the defaults that `extract.nix` can only show as a `literalExpression`
+# (i.e. options with a `defaultText`) in the context of a particular host.
+# The output mirrors the structure of the option tree, options without a
+# `defaultText` are left out since their default is already known.
+
+with builtins;
+
+let
+  # Load flake
+  computer = (getFlake flakePath).nixosConfigurations.${hostname};
+
+  # Get necessary components
+  lib = computer.pkgs.lib;
+  options = computer.options;
+
+  # Same exclusions as in `extractConfig.nix`, evaluating these is too expensive
+  skipped = [
+    "assertions"
+    "home-manager"
+    "system.build.manual"
+    "virtualisation"
+    "boot"
+    "services"
+  ];
+
+  inherit (utilities { inherit lib; }) catchErrors isVisibleNameValue;
+in with lib;
+
+let
+  isSkipped = path: elem (concatStringsSep "." path) skipped;
+
+  # Returns `null` when there is nothing to export for this (sub)tree
+  recurse = path: opt:
+    if isSkipped path then
+      null
+    else if isOption opt then
+      if opt ? defaultText && opt ? default then
+        { _evaluated = catchErrors opt.default; }
+      else
+        null
+    else
+      let
+        visibleOptions = filterAttrs isVisibleNameValue opt;
+        children = mapAttrs (name: recurse (path ++ [ name ])) visibleOptions;
+        nonEmpty = filterAttrs (name: v: v != null) children;
+      in if nonEmpty == { } then null else nonEmpty;
+
+  # Unwrap the `_evaluated` markers now that empty subtrees are pruned
+  unwrap = v: if v ? _evaluated then v._evaluated else mapAttrs (n: unwrap) v;
+  tree = recurse [ ] options;
+in if tree == null then { } else unwrap tree
@@ -0,0 +1,29 @@
+{ flakePath ? "/etc/nixos", hostname }:
+
+# List the options defined in the config of a host together with the files defining them
+
+with builtins;
+
+let
+  # Load flake
+  computer = (getFlake flakePath).nixosConfigurations.${hostname};
+
+  # Get necessary components
+  lib = computer.pkgs.lib;
+  options = computer.options;
+in with lib;
+
+let
+  # Options of submodules are only visible through their values, only visit the option sets
+  recurse = opts:
+    concatMap (v:
+      if isOption v then
+        (if v.isDefined then [ v ] else [ ])
+      else if isAttrs v then
+        recurse v
+      else
+        [ ]) (attrValues (removeAttrs opts [ "_module" ]));
+in map (opt: {
+  inherit (opt) loc;
+  files = map toString opt.files;
+}) (recurse options)
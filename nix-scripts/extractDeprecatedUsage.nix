@@ -0,0 +1,33 @@
+{ utilities ? import ./utilities.nix, flakePath ? "/etc/nixos", hostname }:
+
+# List the renamed and removed options that are still defined in the config of a host
+
+with builtins;
+
+let
+  # Load flake
+  computer = (getFlake flakePath).nixosConfigurations.${hostname};
+
+  # Get necessary components
+  lib = computer.pkgs.lib;
+  options = computer.options;
+
+  inherit (utilities { inherit lib; }) aliasKind;
+in with lib;
+
+let
+  isDeprecated = opt: elem (aliasKind opt) [ "renamed" "removed" ];
+
+  # Deprecated options are never part of a submodule, only visit the option sets
+  recurse = opts:
+    concatMap (v:
+      if isOption v then
+        (if isDeprecated v && v.isDefined then [ v ] else [ ])
+      else if isAttrs v then
+        recurse v
+      else
+        [ ]) (attrValues (removeAttrs opts [ "_module" ]));
+in map (opt: {
+  inherit (opt) loc;
+  files = map toString opt.files;
+}) (recurse options)
@@ -0,0 +1,16 @@
+{ flakePath ? "/etc/nixos", hostname, path }:
+
+# Evaluate a single value of the config of a host again, to get the message and position
+# of the error `tryEval` hid in `extractConfig.nix`. This is meant to fail, the error
+# output of `nix-instantiate` is what we are after.
+# `path` is a JSON list of attribute names and list indices, as exported by `catchErrors`.
+
+with builtins;
+
+let
+  # Load flake
+  computer = (getFlake flakePath).nixosConfigurations.${hostname};
+
+  step = val: segment:
+    if isInt segment then elemAt val segment else getAttr segment val;
+in seq (foldl' step computer.config (fromJSON path)) null
@@ -0,0 +1,15 @@
+# Version information of the nixpkgs used for extracting options
+{ nixpkgs ? toString <nixpkgs>, lib ? import "${nixpkgs}/lib", revision ? null }:
+
+with builtins;
+
+{
+  inherit (lib) version;
+  revision = if revision != null then
+    revision
+  # Flake inputs know their revision
+  else if isAttrs nixpkgs && nixpkgs ? rev then
+    nixpkgs.rev
+  else
+    lib.trivial.revisionWithDefault null;
+}
@@ -20,17 +20,114 @@ rec {
   isVisibleOption = { visible ? true, internal ? false, ... }@opt:
     (if isString visible then visible == "shallow" else visible) && !internal;
 
+  # Target of an alias made by `mkRenamedOptionModule` or `mkAliasOptionModule`, `null` otherwise.
+  # These are only recognizable by the description they generate.
+  aliasTarget = opt:
+    let
+      raw = opt.description or null;
+      # Newer nixpkgs wrap descriptions in `{ _type = "mdDoc"; text = ...; }`
+      description = if isString raw then
+        raw
+      else if isAttrs raw then
+        raw.text or ""
+      else
+        "";
+      found = match ".*Alias of (<option>|\\{option}`)([^<`]+)(</option>|`)\\..*"
+        description;
+    in if found == null then null else elemAt found 1;
+  # Options made by `mkRemovedOptionModule` only throw when used, recognize them
+  # by the lack of a description, type and default instead of calling `apply`
+  isRemovedOption = opt:
+    !(isVisibleOption opt) && !(opt ? description) && !(opt ? default)
+    && opt ? apply && (opt.type.name or null) == "unspecified";
+  # Kind of deprecated option, `null` for ordinary options:
+  # - "alias": made by `mkAliasOptionModule`, still fully supported
+  # - "renamed": made by `mkRenamedOptionModule`, gives a warning when used
+  # - "removed": made by `mkRemovedOptionModule`, gives an error when used
+  aliasKind = opt:
+    if aliasTarget opt != null then
+      (if isVisibleOption opt then "alias" else "renamed")
+    else if isRemovedOption opt then
+      "removed"
+    else
+      null;
+  # The replacement instructions given to `mkRemovedOptionModule`, by the removed option
+  # as shown by `showOption`. Its `apply` throws them, but `tryEval` drops the message
+  # of a `throw`, so take them from the assertion it adds to the config as well.
+  # `assertions` is the value of the `assertions` option.
+  removalInstructions = assertions:
+    let
+      message = a:
+        let try = tryEval (a.message or null);
+        in if try.success && isString try.value then try.value else "";
+      parse = a:
+        match
+        "The option definition `([^']*)' in .* no longer has any effect; please remove it\\.\n(.*[^\n])\n*"
+        (message a);
+      found = filter (m: m != null) (map parse assertions);
+    in listToAttrs (map (m: nameValuePair (elemAt m 0) (elemAt m 1)) found);
+  # The message `apply` of `mkRemovedOptionModule` throws, `instructions` are the
+  # `removalInstructions` of the config
+  removalMessage = instructions: opt:
+    let shown = showOption opt.loc;
+    in if isRemovedOption opt then
+      "The option `${shown}' can no longer be used since it's been removed."
+      + optionalString (instructions ? ${shown}) " ${instructions.${shown}}"
+    else
+      null;
+  # Keep visible options and deprecated ones, the latter are marked as such
+  isVisibleOrDeprecatedNameValue = name: v:
+    isVisibleNameValue name v || (isOption v && aliasKind v != null);
+
+
+  # Whether `a` and `b` are the very same value, e.g. the same instance of a type.
+  # Inside lists `==` compares values by pointer before comparing their contents,
+  # and types contain functions which otherwise never compare equal.
+  sameValue = a: b: [ a ] == [ b ];
+
 
   # Guard methods, they take a function for easy composability
-  guardTryEval = f: v:
+  # `tryEval` drops the error message, keep the path so it can be evaluated again,
+  # see `extractError.nix`
+  guardTryEvalAt = path: f: v:
     let try = tryEval v;
-    in if try.success then f try.value else { _error = true; };
+    in if try.success then f try.value else { _error = true; inherit path; };
   guardDerivation = f: v:
-    if (isDerivation v) then {
-      _derivation = true;
-      # TODO: protect against errors here too?
-      inherit (v) name meta;
-    } else
+    if (isDerivation v) then
+      let
+        # Packages with broken metadata are common, guard every field
+        safe = default: x:
+          let try = tryEval (deepSeq x x);
+          in if try.success then try.value else default;
+        meta = let try = tryEval (v.meta or { });
+        in if try.success && isAttrs try.value then try.value else { };
+        homepage = meta.homepage or null;
+        showLicense = l:
+          if isAttrs l then
+            l.spdxId or l.shortName or l.fullName or "unknown"
+          else
+            toString l;
+        showMaintainer = m:
+          if isAttrs m then
+            m.github or m.name or m.email or "unknown"
+          else
+            toString m;
+      in {
+        _derivation = true;
+        name = safe "<unknown>" v.name;
+        pname = safe null (v.pname or null);
+        version = safe null (v.version or null);
+        # Only the path, without the context that would make it a dependency of the evaluation
+        outPath = safe null (unsafeDiscardStringContext v.outPath);
+        meta = {
+          description = safe null (meta.description or null);
+          homepage =
+            safe null (if isList homepage then head homepage else homepage);
+          license = safe [ ] (map showLicense (toList (meta.license or [ ])));
+          maintainers = safe [ ] (map showMaintainer (meta.maintainers or [ ]));
+        };
+      }
+    else
       f v;
   guardFunction = f: v: if (isFunction v) then { _function = true; } else f v;
   guardOptionType = f: v: if (isOptionType v) then { _type = true; } else f v;
@@ -42,9 +139,18 @@ rec {
     else
       (if (isList val) then map f val else val);
 
+  # Like `recurseWith`, passing the attribute name or list index appended to `path`
+  recurseWithPath = f: path: val:
+    if (isAttrs val) then
+      mapAttrs (k: v: f (path ++ [ k ]) v) val
+    else
+      (if (isList val) then imap0 (i: v: f (path ++ [ i ]) v) val else val);
+
   # Process config with recursive tryEval to root out `throw` errors
-  catchErrors = guardTryEval (guardDerivation
-    (guardFunction (guardOptionType (recurseWith catchErrors))));
+  catchErrorsAt = path:
+    guardTryEvalAt path (guardDerivation
+      (guardFunction (guardOptionType (recurseWithPath catchErrorsAt path))));
+  catchErrors = catchErrorsAt [ ];
 
   # Guard against values that don't make sense in JSON
   catchJson =
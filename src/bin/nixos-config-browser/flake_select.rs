@@ -1,8 +1,6 @@
 // This entire file is quite horrible honestly but it (mostly) works,
 // is nicely isolated and can easily be replaced in the future.
 
-fn main() {}
-
 use druid::commands::SHOW_OPEN_PANEL;
 use druid::widget::{Button, Controller, Flex, Label, TextBox, ViewSwitcher, Widget};
 use druid::{
@@ -0,0 +1,642 @@
+mod flake_select;
+
+// Copyright 2022 The Druid Authors, Sybrand Aarnoutse.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// This is a pseudo tree file manager (no interaction with your actual
+// filesystem whatsoever). It's intended to use most of the features of
+// the `Tree` widget in a familiar context. It's by no mean polished, and
+// probably lacks a lot of features, we want to focus on the tree widget here.
+
+use nixos_druid::bundle::Bundle;
+use nixos_druid::checks::collect_checks;
+use nixos_druid::closure::Closure;
+use nixos_druid::controller::{FocusOption, LiveReload};
+use nixos_druid::data::{AppData, DisplayData};
+use nixos_druid::delegate::{Delegate, MERGE_CONFIG, OPEN_SNAPSHOT, SAVE_SNAPSHOT, UPDATE_BUNDLE};
+use nixos_druid::diff::OptionsDiff;
+use nixos_druid::interchange::{export_value, import_value, to_nix_module, ConfigFormat};
+use nixos_druid::node::{ExtraChildDisplay, OptionNode};
+use nixos_druid::parse::{show_path, NixErrorDetails, NixGuardedValue, NixPathSegment, NixValue};
+use nixos_druid::run::{LoadJsonError, NixpkgsSource, OptionSource};
+use nixos_druid::tree_node::OptionTreeNode;
+use nixos_druid::view::{checks_panel, closure_view, LinkedCheck, Opener};
+use nixos_druid::watch::{flake_dir, FlakeWatcher};
+use std::path::Path;
+use std::sync::Arc;
+
+use druid::widget::{Checkbox, CrossAxisAlignment, Flex, Label, Painter, Scroll, Split, Tabs};
+use druid::{
+    lens, AppLauncher, Color, Env, ExtEventSink, LensExt, LocalizedString, Menu, MenuItem,
+    RenderContext, SingleUse, Target, Widget, WidgetExt, WindowDesc, WindowId,
+};
+use druid_widget_nursery::tree::Tree;
+
+use druid_widget_nursery::selectors;
+
+selectors! {
+    /// Internal wiring, mostly to update the filetype and the sorting
+    UPDATE_DIR_VIEW,
+    UPDATE_FILE,
+}
+
+fn ui_builder(
+    checks: Vec<LinkedCheck>,
+    closure: Option<Result<Closure, String>>,
+) -> impl Widget<AppData<OptionNode>> {
+    let tree = Tree::new(
+        || {
+            Flex::row()
+                .with_default_spacer()
+                .with_child(Label::dynamic(|data: &OptionNode, _env| data.name.clone()))
+                .with_default_spacer()
+                .with_child(Label::dynamic(|data: &OptionNode, _env| {
+                    let mut badge = data
+                        .default_comparison()
+                        .map(|cmp| cmp.badge())
+                        .unwrap_or("")
+                        .to_string();
+                    if data.upgrade_affected() {
+                        badge.push_str("⚠️");
+                    }
+                    if data.deprecated_in_use() {
+                        badge.push_str("⛔");
+                    }
+                    badge
+                }))
+                .background(Painter::new(|ctx, data: &OptionNode, _env| {
+                    if data.changed {
+                        let rect = ctx.size().to_rect();
+                        ctx.fill(rect, &CHANGED_COLOR);
+                    }
+                }))
+                .controller(FocusOption::new())
+        },
+        // The boolean deciding whether the tree should expand or not, acquired via Lens
+        OptionNode::expanded,
+    )
+    .with_opener(|| Opener::new())
+    .lens(AppData::tree);
+
+    let only_errors = Checkbox::new("Only show errors").lens(AppData::tree.then(lens::Map::new(
+        |node: &OptionNode| node.only_errors,
+        |node: &mut OptionNode, only_errors| node.set_only_errors(only_errors),
+    )));
+    let wrapped_tree = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(only_errors.padding(4.0))
+        .with_flex_child(Scroll::new(tree), 1.0);
+    let label = Label::dynamic(|data: &DisplayData, _| data.to_string()).lens(AppData::display);
+    let details = Split::rows(label, checks_panel(checks)).split_point(0.7);
+    let details = match closure {
+        Some(closure) => Tabs::new()
+            .with_tab("Documentation", details)
+            .with_tab("Closure", closure_view(closure).lens(AppData::display))
+            .boxed(),
+        None => details.boxed(),
+    };
+
+    Split::columns(wrapped_tree, details)
+        .split_point(0.3)
+        .min_size(300.0, 400.0)
+        .controller(LiveReload::new())
+}
+
+fn make_menu(
+    _window: Option<WindowId>,
+    _data: &AppData<OptionNode>,
+    _env: &Env,
+) -> Menu<AppData<OptionNode>> {
+    let snapshot_menu = Menu::new(
+        LocalizedString::new("nixos-config-browser-menu-snapshot").with_placeholder("Snapshot"),
+    )
+    .entry(MenuItem::new("Save snapshot…").command(SAVE_SNAPSHOT))
+    .entry(MenuItem::new("Open snapshot…").command(OPEN_SNAPSHOT));
+
+    Menu::empty().entry(snapshot_menu)
+}
+
+/// Background of the options changed by the last reload, see `watch_host`
+const CHANGED_COLOR: Color = Color::rgba8(0xff, 0xd7, 0x00, 0x60);
+
+const USAGE: &str = "\
+Usage: nixos-config-browser [HOST] [--upgrade-to <nixpkgs>] [--closure]
+       nixos-config-browser [HOST] export-config <option> <json|yaml|toml> <file>
+       nixos-config-browser [HOST] import-config <file> <option> <module.nix>
+       nixos-config-browser [HOST] save-snapshot <file>
+       nixos-config-browser open-snapshot <file>
+
+HOST is selected in a dialog unless given as `--flake <path> --nixos <hostname>`.
+The config is evaluated again whenever files of a local flake change.
+--upgrade-to marks the options set in the config that change in the given nixpkgs,
+given as a path, `<flake>#<input>` or `<nixpkgs>`.
+--closure adds a tab with the closure of the built system, read from the local store.
+`export-config` writes the config below an option path like `services.nginx` to a file,
+`import-config` checks a JSON, YAML or TOML file against the option types and writes it
+as a NixOS module setting that option.
+`save-snapshot` bundles the options, config and nixpkgs version of HOST into one file,
+with the values of options named like secrets, e.g. `hashedPassword`, removed.
+`open-snapshot` browses such a file without evaluating anything.";
+
+struct Arguments {
+    host: Option<(String, String)>,
+    upgrade_to: Option<NixpkgsSource>,
+    closure: bool,
+    export_config: Option<(String, ConfigFormat, String)>,
+    /// The file to import, the option to set and the module to write
+    import_config: Option<(String, String, String)>,
+    save_snapshot: Option<String>,
+    open_snapshot: Option<String>,
+}
+
+fn parse_arguments(mut args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+    fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+        args.next().ok_or(format!("`{flag}` expects a value"))
+    }
+
+    let (mut flake, mut hostname) = (None, None);
+    let mut upgrade_to = None;
+    let mut closure = false;
+    let mut export_config = None;
+    let mut import_config = None;
+    let (mut save_snapshot, mut open_snapshot) = (None, None);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--flake" => flake = Some(value(&mut args, "--flake")?),
+            "--nixos" => hostname = Some(value(&mut args, "--nixos")?),
+            "--upgrade-to" => upgrade_to = Some(value(&mut args, "--upgrade-to")?.parse()?),
+            "--closure" => closure = true,
+            "export-config" => {
+                let option = value(&mut args, "export-config")?;
+                let format = value(&mut args, "export-config")?.parse()?;
+                export_config = Some((option, format, value(&mut args, "export-config")?));
+            }
+            "import-config" => {
+                let file = value(&mut args, "import-config")?;
+                let option = value(&mut args, "import-config")?;
+                import_config = Some((file, option, value(&mut args, "import-config")?));
+            }
+            "save-snapshot" => save_snapshot = Some(value(&mut args, "save-snapshot")?),
+            "open-snapshot" => open_snapshot = Some(value(&mut args, "open-snapshot")?),
+            other => return Err(format!("Unknown argument `{other}`")),
+        }
+    }
+
+    let commands = [
+        export_config.is_some(),
+        import_config.is_some(),
+        save_snapshot.is_some(),
+        open_snapshot.is_some(),
+    ];
+    if commands.iter().filter(|c| **c).count() > 1 {
+        return Err("Only one of the commands can be given".to_string());
+    }
+    if open_snapshot.is_some() && (flake.is_some() || upgrade_to.is_some() || closure) {
+        return Err("`open-snapshot` can't be combined with a host or other flags".to_string());
+    }
+
+    let host = match (flake, hostname) {
+        (Some(flake), Some(hostname)) => Some((flake, hostname)),
+        (None, None) => None,
+        _ => return Err("`--flake` and `--nixos` have to be given together".to_string()),
+    };
+
+    Ok(Arguments {
+        host,
+        upgrade_to,
+        closure,
+        export_config,
+        import_config,
+        save_snapshot,
+        open_snapshot,
+    })
+}
+
+fn export_config(
+    flake: &str,
+    hostname: &str,
+    (option, format, file): (String, ConfigFormat, String),
+) {
+    let path: Vec<&str> = option.split('.').collect();
+    let value = nixos_druid::run::get_config_at(flake, hostname, &path)
+        .expect("Getting NixOS config failed");
+    let content = export_value(&value, format).unwrap_or_else(|e| {
+        eprintln!("Exporting `{option}` as {format} failed: {e}");
+        std::process::exit(1);
+    });
+    std::fs::write(&file, content).expect("Writing export file failed");
+    eprintln!("Exported `{option}` as {format} to `{file}`.");
+}
+
+fn import_config(flake: &str, hostname: &str, (file, option, module): (String, String, String)) {
+    let format = ConfigFormat::from_file_name(&file).unwrap_or_else(|| {
+        eprintln!("The format of `{file}` is unknown, expected a .json, .yaml or .toml file");
+        std::process::exit(1);
+    });
+    let content = std::fs::read_to_string(&file).expect("Reading import file failed");
+    let value = import_value(&content, format).unwrap_or_else(|e| {
+        eprintln!("Parsing `{file}` as {format} failed: {e}");
+        std::process::exit(1);
+    });
+
+    let source = OptionSource::NixosConfiguration {
+        flake: flake.to_string(),
+        hostname: hostname.to_string(),
+    };
+    let options =
+        nixos_druid::run::get_options_from(&source).expect("Getting NixOS options failed");
+    let tree = OptionNode::new("NixOS Configuration".to_string(), options);
+    let node = tree.find(&option).unwrap_or_else(|| {
+        eprintln!("`{option}` is not an option of {source}");
+        std::process::exit(1);
+    });
+
+    let mut errors = Vec::new();
+    node.check_value(&value, &option, &mut errors);
+    let path: Vec<&str> = option.split('.').collect();
+    let nix = if errors.is_empty() {
+        to_nix_module(&path, &value)
+    } else {
+        Err(errors)
+    };
+
+    match nix {
+        Ok(nix) => {
+            std::fs::write(&module, nix).expect("Writing module failed");
+            eprintln!("Wrote `{file}` as a module setting `{option}` to `{module}`.");
+        }
+        Err(errors) => {
+            eprintln!("`{file}` doesn't fit the option types:");
+            for error in errors {
+                eprintln!("    {error}");
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The message and position of the error at `path` of the config, which needs its own
+/// evaluation of the host
+fn explain_error(
+    flake_path: &str,
+    hostname: &str,
+    path: &[NixPathSegment],
+) -> Option<NixErrorDetails> {
+    nixos_druid::run::get_error_details(flake_path, hostname, path).unwrap_or_else(|e| {
+        eprintln!("Explaining error at `{}` failed: {:?}", show_path(path), e);
+        None
+    })
+}
+
+/// Explain all errors up front for a snapshot, the browser only explains the errors of
+/// the selected option, see `Delegate::with_error_explainer`
+fn explain_errors(flake_path: &str, hostname: &str, config: &mut NixGuardedValue) {
+    let mut errors = config.errors_mut();
+    let error_count = errors.len();
+    for (i, error) in errors.iter_mut().enumerate() {
+        eprintln!("Explaining error {} of {error_count}…", i + 1);
+        error.details = explain_error(flake_path, hostname, &error.path);
+    }
+    if error_count > 0 {
+        eprintln!("Explaining {error_count} errors in the config is done.");
+    }
+}
+
+/// Evaluate everything the browser shows of a host
+fn evaluate_host(flake_path: &str, hostname: &str) -> Bundle {
+    // Use the options of the host itself so options of its own modules are included
+    let option_source = OptionSource::NixosConfiguration {
+        flake: flake_path.to_string(),
+        hostname: hostname.to_string(),
+    };
+    let options =
+        nixos_druid::run::get_options_from(&option_source).expect("Getting NixOS options failed");
+    eprintln!("Parsing options is done.");
+    let nixpkgs = match nixos_druid::run::get_nixpkgs_info(&option_source) {
+        Ok(info) => Some(info),
+        Err(e) => {
+            eprintln!("Getting the nixpkgs version failed: {:?}", e);
+            None
+        }
+    };
+    let config =
+        nixos_druid::run::get_config(flake_path, hostname).expect("Getting NixOS config failed");
+    eprintln!("Parsing config is done.");
+    let evaluated_defaults = match nixos_druid::run::get_evaluated_defaults(flake_path, hostname) {
+        Ok(defaults) => {
+            eprintln!("Evaluating defaults is done.");
+            Some(defaults)
+        }
+        // Not fatal, the defaults given as `literalExpression` just can't be compared
+        Err(e) => {
+            eprintln!("Evaluating defaults failed: {:?}", e);
+            None
+        }
+    };
+    let definitions = match nixos_druid::run::get_definitions(flake_path, hostname) {
+        Ok(definitions) => {
+            eprintln!("Finding the definitions of options is done.");
+            definitions
+        }
+        Err(e) => {
+            eprintln!("Finding the definitions of options failed: {:?}", e);
+            Vec::new()
+        }
+    };
+    let deprecated =
+        nixos_druid::run::get_deprecated_usage(flake_path, hostname).unwrap_or_else(|e| {
+            eprintln!("Finding deprecated options in the config failed: {:?}", e);
+            Vec::new()
+        });
+    let checks = match nixos_druid::run::get_config_checks(flake_path, hostname) {
+        Ok(checks) => {
+            eprintln!("Evaluating assertions and warnings is done.");
+            Some(checks)
+        }
+        Err(e) => {
+            eprintln!("Evaluating assertions and warnings failed: {:?}", e);
+            None
+        }
+    };
+
+    Bundle {
+        host: format!("{flake_path}#{hostname}"),
+        nixpkgs,
+        options,
+        config,
+        evaluated_defaults,
+        definitions,
+        deprecated,
+        checks,
+        redacted: Vec::new(),
+    }
+}
+
+fn config_tree(options: NixValue, config: NixGuardedValue) -> OptionNode {
+    let root_name = "NixOS Configuration".to_string();
+    let mut option_tree = OptionNode::new(root_name, options);
+    option_tree.set_extra_child_display(ExtraChildDisplay::First);
+    option_tree.add_config(Some(config));
+    option_tree.mark_errors();
+    option_tree
+}
+
+/// The tree and the checks linked to it shown of a bundle
+fn build_tree(bundle: &Bundle) -> (OptionNode, Vec<LinkedCheck>) {
+    let mut option_tree = config_tree(bundle.options.clone(), bundle.config.clone());
+    if let Some(ref defaults) = bundle.evaluated_defaults {
+        option_tree.add_evaluated_defaults(Some(defaults.clone()));
+    }
+    for definition in bundle.definitions.iter() {
+        option_tree.add_definition(&definition.loc, &definition.files);
+    }
+    for usage in bundle.deprecated.iter() {
+        eprintln!(
+            "Warning: deprecated option `{}` is still set in {}",
+            usage.loc.join("."),
+            usage.files.join(", ")
+        );
+        option_tree.add_deprecated_definition(&usage.loc, &usage.files);
+    }
+
+    let checks = bundle
+        .checks
+        .as_ref()
+        .map(collect_checks)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|check| {
+            // Only link what is actually an option (or a set of them) in the tree
+            let options = check
+                .mentioned_paths()
+                .into_iter()
+                .filter_map(|path| {
+                    let node = option_tree.find(&path)?;
+                    Some((path, node.focused_display_data()))
+                })
+                .collect();
+            LinkedCheck { check, options }
+        })
+        .collect();
+
+    (option_tree, checks)
+}
+
+fn title(bundle: &Bundle, snapshot: bool) -> String {
+    let mut title = format!("NixOS Config Browser - {}", bundle.host);
+    if let Some(ref info) = bundle.nixpkgs {
+        title.push_str(&format!(" - {}", info.short()));
+    }
+    if snapshot {
+        title.push_str(" (snapshot)");
+    }
+    title
+}
+
+/// Evaluate the config again whenever the files of the flake change and send it to the
+/// window to merge, see `LiveReload`. Only flakes in a local directory can be watched.
+fn watch_host(sink: ExtEventSink, flake_path: String, hostname: String, mut bundle: Arc<Bundle>) {
+    let dir = match flake_dir(&flake_path) {
+        Some(dir) => dir,
+        None => {
+            eprintln!("`{flake_path}` is not a local directory, changes to it aren't shown.");
+            return;
+        }
+    };
+    let watcher = match FlakeWatcher::new(&dir) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Watching `{}` failed: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    while let Some(changed) = watcher.wait() {
+        let changed: Vec<String> = changed
+            .iter()
+            .map(|path| format!("`{}`", path.display()))
+            .collect();
+        eprintln!(
+            "{} changed, evaluating the config again.",
+            changed.join(", ")
+        );
+        let config = match nixos_druid::run::eval_config(&flake_path, &hostname) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Evaluating the changed config failed: {:?}", e);
+                continue;
+            }
+        };
+        let tree = config_tree(bundle.options.clone(), config.clone());
+
+        // Saving a snapshot saves the new config as well. Only the config is evaluated
+        // again, what was evaluated along with it is left out instead of going stale.
+        // The options stay the ones the browser was started with, like in the tree.
+        let mut next = (*bundle).clone();
+        next.config = config;
+        next.evaluated_defaults = None;
+        next.definitions.clear();
+        next.deprecated.clear();
+        next.checks = None;
+        next.redacted.clear();
+        next.redact_secrets();
+        bundle = Arc::new(next);
+
+        let sent = sink
+            .submit_command(UPDATE_BUNDLE, bundle.clone(), Target::Auto)
+            .and_then(|()| sink.submit_command(MERGE_CONFIG, SingleUse::new(tree), Target::Auto));
+        if sent.is_err() {
+            // The application quit
+            return;
+        }
+        eprintln!("Reloading the config is done.");
+    }
+}
+
+/// Show the tree, `watch` is the host to reload when its flake changes and whose errors
+/// are explained when shown
+fn launch(
+    title: String,
+    option_tree: OptionNode,
+    checks: Vec<LinkedCheck>,
+    closure: Option<Result<Closure, String>>,
+    bundle: Bundle,
+    watch: Option<(String, String)>,
+) {
+    // Create the main window
+    let main_window = WindowDesc::new(ui_builder(checks, closure))
+        .window_size((600.0, 600.0))
+        .menu(make_menu)
+        .title(title);
+
+    let data = AppData::new(option_tree);
+    eprintln!("GUI `Data` is built.");
+
+    let bundle = Arc::new(bundle);
+    let mut delegate = Delegate::new().with_bundle(bundle.clone());
+    if let Some((ref flake_path, ref hostname)) = watch {
+        let (flake_path, hostname) = (flake_path.clone(), hostname.clone());
+        delegate =
+            delegate.with_error_explainer(move |path| explain_error(&flake_path, &hostname, path));
+    }
+    let launcher = AppLauncher::with_window(main_window).delegate(delegate);
+    // let launcher = launcher.log_to_console();
+    if let Some((flake_path, hostname)) = watch {
+        let sink = launcher.get_external_handle();
+        std::thread::spawn(move || watch_host(sink, flake_path, hostname, bundle));
+    }
+
+    // start the application
+    launcher.launch(data).expect("launch failed");
+}
+
+fn report_redacted(bundle: &Bundle) {
+    if !bundle.redacted.is_empty() {
+        eprintln!(
+            "Removed the values of {} options named like secrets:",
+            bundle.redacted.len()
+        );
+        for path in bundle.redacted.iter() {
+            eprintln!("    {path}");
+        }
+    }
+}
+
+pub fn main() {
+    let arguments = parse_arguments(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        std::process::exit(1);
+    });
+
+    if let Some(file) = arguments.open_snapshot {
+        // Nothing is evaluated, the snapshot may come from a machine without this flake
+        let bundle = Bundle::open(Path::new(&file)).unwrap_or_else(|e| {
+            eprintln!("Opening snapshot `{file}` failed: {e}");
+            std::process::exit(1);
+        });
+        report_redacted(&bundle);
+        let (option_tree, checks) = build_tree(&bundle);
+        launch(
+            title(&bundle, true),
+            option_tree,
+            checks,
+            None,
+            bundle,
+            None,
+        );
+        return;
+    }
+
+    let (flake_path, hostname) = match arguments.host {
+        Some(host) => host,
+        None => crate::flake_select::select_hostname()
+            .extract_results()
+            .expect("Selection of system flake and/or particular `nixosConfiguration` failed"),
+    };
+
+    if let Some(export) = arguments.export_config {
+        export_config(&flake_path, &hostname, export);
+        return;
+    }
+    if let Some(import) = arguments.import_config {
+        import_config(&flake_path, &hostname, import);
+        return;
+    }
+
+    let mut bundle = evaluate_host(&flake_path, &hostname);
+
+    if let Some(file) = arguments.save_snapshot {
+        explain_errors(&flake_path, &hostname, &mut bundle.config);
+        bundle.redact_secrets();
+        report_redacted(&bundle);
+        bundle
+            .save(Path::new(&file))
+            .unwrap_or_else(|e| panic!("Saving snapshot failed: {e}"));
+        eprintln!("Saved a snapshot of {} to `{file}`.", bundle.host);
+        return;
+    }
+
+    let (mut option_tree, checks) = build_tree(&bundle);
+    if let Some(nixpkgs) = arguments.upgrade_to {
+        let new_source = OptionSource::Nixpkgs {
+            nixpkgs,
+            modules: Vec::new(),
+        };
+        let new_root = nixos_druid::run::get_options_from(&new_source)
+            .expect("Getting NixOS options to upgrade to failed");
+        let notes = OptionsDiff::new(&bundle.options, &new_root)
+            .without_foreign_removals(&bundle.options)
+            .notes_by_path();
+        eprintln!("Comparing options with {new_source} is done.");
+        option_tree.add_upgrade_notes(&notes);
+    }
+
+    let closure = arguments.closure.then(|| {
+        let closure = nixos_druid::run::get_toplevel_path(&flake_path, &hostname)
+            .and_then(|toplevel| nixos_druid::run::get_closure(&toplevel))
+            .map_err(|e| match e {
+                LoadJsonError::FailedEval(stderr) => stderr,
+                other => format!("{:?}", other),
+            });
+        eprintln!("Reading the system closure is done.");
+        closure
+    });
+
+    // The tree shows the values as they are, only what can be saved from it is redacted
+    let title = title(&bundle, false);
+    bundle.redact_secrets();
+    let watch = Some((flake_path, hostname));
+    launch(title, option_tree, checks, closure, bundle, watch);
+}
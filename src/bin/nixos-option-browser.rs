@@ -1,6 +1,3 @@
-mod node;
-use node::OptionNode;
-
 // Copyright 2022 The Druid Authors, Sybrand Aarnoutse.
 //
 // Licensed under the Apache License, Version 2.0 (the "License");
@@ -22,12 +19,24 @@ use node::OptionNode;
 
 use nixos_druid::controller::FocusOption;
 use nixos_druid::data::{AppData, DisplayData};
-use nixos_druid::delegate::Delegate;
-use nixos_druid::view::Opener;
+use nixos_druid::delegate::{Delegate, DiffDelegate, EXPORT_DOCUMENTATION};
+use nixos_druid::diff::OptionsDiff;
+use nixos_druid::export::{export, ExportFormat};
+use nixos_druid::node::OptionNode;
+use nixos_druid::run::{NixpkgsSource, OptionSource};
+use nixos_druid::schema::write_json_schema;
+use nixos_druid::tree_node::OptionTreeNode;
+use nixos_druid::view::{diff_report, Opener};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use druid::widget::{Flex, Label, Scroll, Split};
-use druid::{AppLauncher, LocalizedString, Widget, WidgetExt, WindowDesc};
-use druid_widget_nursery::tree::{Tree, TreeNode};
+use druid::widget::{CrossAxisAlignment, Flex, Label, Scroll, Split};
+use druid::{
+    AppLauncher, Env, LocalizedString, Menu, MenuItem, Widget, WidgetExt, WindowDesc, WindowId,
+};
+use druid_widget_nursery::tree::Tree;
 
 use druid_widget_nursery::selectors;
 
@@ -37,46 +46,13 @@ selectors! {
     UPDATE_FILE,
 }
 
-// AttrsOf(Submodule(_)), ListOf(Submodule(_)) get an extra child for showing documentation
-impl TreeNode for OptionNode {
-    fn get_child(&self, index: usize) -> &Self {
-        if let Some(ref nested) = self.extra_child {
-            nested.get_child(index)
-        } else {
-            &self.children[index]
-        }
-    }
-
-    fn for_child_mut(&mut self, index: usize, mut cb: impl FnMut(&mut Self, usize)) {
-        if let Some(ref mut nested) = self.extra_child {
-            nested.for_child_mut(index, cb)
-        } else {
-            cb(&mut self.children[index], index);
-        }
-    }
-
-    fn children_count(&self) -> usize {
-        if let Some(ref nested) = self.extra_child {
-            nested.children_count()
-        } else {
-            self.children.len()
-        }
-    }
-}
-
-fn ui_builder() -> impl Widget<AppData<OptionNode>> {
+fn ui_builder(status: String) -> impl Widget<AppData<OptionNode>> {
     let tree = Tree::new(
         || {
             Flex::row()
                 .with_default_spacer()
                 .with_child(Label::dynamic(|data: &OptionNode, _env| {
-                    if let Some(ref t) = data.option_type {
-                        if let Some(ext) = t.get_name_extension() {
-                            return format!("{}.{}", data.name, ext);
-                        }
-                    }
-
-                    data.name.clone()
+                    data.get_path_segment()
                 }))
                 .controller(FocusOption::new())
         },
@@ -89,25 +65,243 @@ fn ui_builder() -> impl Widget<AppData<OptionNode>> {
     let wrapped_tree = Scroll::new(tree);
     let label = Label::dynamic(|data: &DisplayData, _| data.to_string()).lens(AppData::display);
 
-    Split::columns(wrapped_tree, label)
+    let split = Split::columns(wrapped_tree, label)
         .split_point(0.3)
-        .min_size(300.0, 400.0)
+        .min_size(300.0, 400.0);
+
+    Flex::column()
+        .with_flex_child(split, 1.0)
+        .with_child(Label::new(status).padding(2.0))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
 }
 
-pub fn main() {
-    // Create the main window
-    let main_window = WindowDesc::new(ui_builder())
-        .window_size((600.0, 600.0))
-        .title(
-            LocalizedString::new("nixos-option-browser-window-title")
-                .with_placeholder("NixOS Options Browser"),
+fn make_menu(
+    _window: Option<WindowId>,
+    _data: &AppData<OptionNode>,
+    _env: &Env,
+) -> Menu<AppData<OptionNode>> {
+    let mut export_menu = Menu::new(
+        LocalizedString::new("nixos-option-browser-menu-export").with_placeholder("Export"),
+    );
+    for format in [
+        ExportFormat::Html,
+        ExportFormat::Markdown,
+        ExportFormat::Man,
+    ] {
+        export_menu = export_menu.entry(
+            MenuItem::new(format!("Documentation as {format}…"))
+                .command(EXPORT_DOCUMENTATION.with(format)),
         );
+    }
+
+    Menu::empty().entry(export_menu)
+}
+
+const USAGE: &str = "\
+Usage: nixos-option-browser [SOURCE] [export <html|markdown|man> <path>]
+       nixos-option-browser [SOURCE] schema <path>
+       nixos-option-browser [--module <file>]... diff <old> <new> [export markdown <path>]
+
+SOURCE selects the options to browse, by default those of `<nixpkgs/nixos>`:
+    --nixpkgs <path>                use a local checkout or store path of nixpkgs
+    --nixpkgs-input <flake>#<input> use the nixpkgs input of a flake, e.g. `/etc/nixos#nixpkgs`
+    --module <file>                 also evaluate this module file, can be repeated
+    --flake <path> --nixos <host>   `nixosConfigurations.<host>.options` of a flake
+    --flake <path> --home <name>    `homeConfigurations.<name>.options` of a flake
+
+`diff` compares the options of two nixpkgs, given as a path, `<flake>#<input>` or `<nixpkgs>`.
+`schema` writes a JSON Schema of the options, e.g. to validate JSON configuration.";
+
+struct Arguments {
+    source: OptionSource,
+    export: Option<(ExportFormat, String)>,
+    /// Where to write a JSON Schema of the options
+    schema: Option<String>,
+    /// The old and new nixpkgs to compare
+    diff: Option<(NixpkgsSource, NixpkgsSource)>,
+}
 
-    let root = nixos_druid::run::get_options().expect("Getting NixOS options failed");
+fn parse_arguments(mut args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+    fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+        args.next().ok_or(format!("`{flag}` expects a value"))
+    }
+
+    let (mut flake, mut nixos, mut home) = (None, None, None);
+    let mut nixpkgs = None;
+    let mut modules = Vec::new();
+    let mut export = None;
+    let mut schema = None;
+    let mut diff = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--flake" => flake = Some(value(&mut args, "--flake")?),
+            "--nixos" => nixos = Some(value(&mut args, "--nixos")?),
+            "--home" => home = Some(value(&mut args, "--home")?),
+            "--nixpkgs" => {
+                let path = PathBuf::from(value(&mut args, "--nixpkgs")?);
+                nixpkgs = Some(NixpkgsSource::Path(path));
+            }
+            "--nixpkgs-input" => {
+                let reference = value(&mut args, "--nixpkgs-input")?;
+                let (flake, input) = reference.rsplit_once('#').ok_or(format!(
+                    "`{reference}` should be of the form `<flake>#<input>`"
+                ))?;
+                nixpkgs = Some(NixpkgsSource::FlakeInput {
+                    flake: flake.to_string(),
+                    input: input.to_string(),
+                });
+            }
+            "--module" => {
+                let file = value(&mut args, "--module")?;
+                let path = std::fs::canonicalize(&file)
+                    .map_err(|e| format!("Module file `{file}` not found: {e}"))?;
+                modules.push(path);
+            }
+            "diff" => {
+                let old = value(&mut args, "diff")?.parse()?;
+                diff = Some((old, value(&mut args, "diff")?.parse()?));
+            }
+            "export" => {
+                let format = value(&mut args, "export")?.parse()?;
+                export = Some((format, value(&mut args, "export")?));
+            }
+            "schema" => schema = Some(value(&mut args, "schema")?),
+            other => return Err(format!("Unknown argument `{other}`")),
+        }
+    }
+
+    if diff.is_some() && (flake.is_some() || nixpkgs.is_some() || schema.is_some()) {
+        return Err("`diff` can only be combined with `--module`".to_string());
+    }
+    if schema.is_some() && export.is_some() {
+        return Err("`schema` and `export` can't be combined".to_string());
+    }
+
+    let source = match (flake, nixos, home) {
+        (None, None, None) => OptionSource::Nixpkgs {
+            nixpkgs: nixpkgs.unwrap_or_default(),
+            modules,
+        },
+        (Some(flake), Some(hostname), None) if modules.is_empty() && nixpkgs.is_none() => {
+            OptionSource::NixosConfiguration { flake, hostname }
+        }
+        (Some(flake), None, Some(name)) if modules.is_empty() && nixpkgs.is_none() => {
+            OptionSource::HomeConfiguration { flake, name }
+        }
+        _ => {
+            return Err(
+                "`--flake` needs exactly one of `--nixos` or `--home` and can't be combined with `--module` or a nixpkgs source"
+                    .to_string(),
+            )
+        }
+    };
+
+    Ok(Arguments {
+        source,
+        export,
+        schema,
+        diff,
+    })
+}
+
+fn run_diff(
+    modules: Vec<PathBuf>,
+    (old, new): (NixpkgsSource, NixpkgsSource),
+    export: Option<(ExportFormat, String)>,
+) {
+    let title = format!("NixOS Options Diff: {old} → {new}");
+    let get_options = |nixpkgs| {
+        let source = OptionSource::Nixpkgs {
+            nixpkgs,
+            modules: modules.clone(),
+        };
+        nixos_druid::run::get_options_from(&source)
+            .unwrap_or_else(|e| panic!("Getting the options of {source} failed: {e:?}"))
+    };
+    let diff = OptionsDiff::new(&get_options(old), &get_options(new));
+    eprintln!("Comparing options is done.");
+
+    match export {
+        Some((ExportFormat::Markdown, path)) => {
+            let file = File::create(&path).expect("Creating export file failed");
+            diff.write_markdown(&mut BufWriter::new(file))
+                .expect("Exporting options diff failed");
+            eprintln!("Exported options diff to `{path}`.");
+            return;
+        }
+        Some((format, _)) => {
+            eprintln!("An options diff can only be exported as Markdown, not as {format}");
+            std::process::exit(1);
+        }
+        None => (),
+    }
+
+    let window = WindowDesc::new(diff_report(&diff))
+        .window_size((800.0, 600.0))
+        .title(title);
+
+    AppLauncher::with_window(window)
+        .delegate(DiffDelegate::new(Arc::new(diff)))
+        .launch(())
+        .expect("launch failed");
+}
+
+pub fn main() {
+    let arguments = parse_arguments(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        std::process::exit(1);
+    });
+
+    if let Some(diff) = arguments.diff {
+        let modules = match arguments.source {
+            OptionSource::Nixpkgs { modules, .. } => modules,
+            _ => unreachable!("`diff` is only allowed with plain nixpkgs"),
+        };
+        run_diff(modules, diff, arguments.export);
+        return;
+    }
+
+    let root = nixos_druid::run::get_options_from(&arguments.source)
+        .expect("Getting NixOS options failed");
     eprintln!("Parsing options is done.");
+
+    if let Some(path) = arguments.schema {
+        let title = format!("Options of {}", arguments.source);
+        write_json_schema(&root, &title, path.as_ref()).expect("Writing JSON Schema failed");
+        eprintln!("Wrote JSON Schema to `{path}`.");
+        return;
+    }
+
     let root_name = "NixOS Configuration".to_string();
     let tree = OptionNode::new(root_name, root);
 
+    if let Some((format, path)) = arguments.export {
+        export(&tree, format, path.as_ref()).expect("Exporting documentation failed");
+        eprintln!("Exported documentation as {format} to `{path}`.");
+        return;
+    }
+
+    let (title, status) = match nixos_druid::run::get_nixpkgs_info(&arguments.source) {
+        Ok(info) => (
+            format!("NixOS Options Browser - {}", info.short()),
+            format!("Options of {}, {}", arguments.source, info),
+        ),
+        Err(e) => {
+            eprintln!("Getting the nixpkgs version failed: {:?}", e);
+            (
+                "NixOS Options Browser".to_string(),
+                format!("Options of {}, unknown nixpkgs version", arguments.source),
+            )
+        }
+    };
+
+    // Create the main window
+    let main_window = WindowDesc::new(ui_builder(status))
+        .window_size((600.0, 600.0))
+        .menu(make_menu)
+        .title(title);
+
     let data = AppData::new(tree);
     eprintln!("GUI `Data` is built.");
 
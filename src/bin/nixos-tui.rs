@@ -0,0 +1,744 @@
+//! Terminal frontend showing the same tree as `nixos-option-browser` and
+//! `nixos-config-browser`, for machines without a display, e.g. over SSH.
+
+use nixos_druid::node::{ExtraChildDisplay, OptionNode};
+use nixos_druid::run::{NixpkgsSource, OptionSource};
+use nixos_druid::tree_node::OptionTreeNode;
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+
+use std::io::{self, Stdout};
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: nixos-tui [SOURCE]
+
+SOURCE selects the options to browse, by default those of `<nixpkgs/nixos>`:
+    --nixpkgs <path>                use a local checkout or store path of nixpkgs
+    --nixpkgs-input <flake>#<input> use the nixpkgs input of a flake, e.g. `/etc/nixos#nixpkgs`
+    --module <file>                 also evaluate this module file, can be repeated
+    --flake <path> --nixos <host>   the options and config of `nixosConfigurations.<host>`
+    --flake <path> --home <name>    `homeConfigurations.<name>.options` of a flake";
+
+const HELP: &str = "↑↓/jk move  →/l/enter open  ←/h close  / search  n/N next/previous match  \
+tab details  e only errors  q quit";
+
+struct Arguments {
+    source: OptionSource,
+}
+
+fn parse_arguments(mut args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+    fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+        args.next().ok_or(format!("`{flag}` expects a value"))
+    }
+
+    let (mut flake, mut nixos, mut home) = (None, None, None);
+    let mut nixpkgs = None;
+    let mut modules = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--flake" => flake = Some(value(&mut args, "--flake")?),
+            "--nixos" => nixos = Some(value(&mut args, "--nixos")?),
+            "--home" => home = Some(value(&mut args, "--home")?),
+            "--nixpkgs" => {
+                let path = PathBuf::from(value(&mut args, "--nixpkgs")?);
+                nixpkgs = Some(NixpkgsSource::Path(path));
+            }
+            "--nixpkgs-input" => {
+                let reference = value(&mut args, "--nixpkgs-input")?;
+                let (flake, input) = reference.rsplit_once('#').ok_or(format!(
+                    "`{reference}` should be of the form `<flake>#<input>`"
+                ))?;
+                nixpkgs = Some(NixpkgsSource::FlakeInput {
+                    flake: flake.to_string(),
+                    input: input.to_string(),
+                });
+            }
+            "--module" => {
+                let file = value(&mut args, "--module")?;
+                let path = std::fs::canonicalize(&file)
+                    .map_err(|e| format!("Module file `{file}` not found: {e}"))?;
+                modules.push(path);
+            }
+            other => return Err(format!("Unknown argument `{other}`")),
+        }
+    }
+
+    let source = match (flake, nixos, home) {
+        (None, None, None) => OptionSource::Nixpkgs {
+            nixpkgs: nixpkgs.unwrap_or_default(),
+            modules,
+        },
+        (Some(flake), Some(hostname), None) if modules.is_empty() && nixpkgs.is_none() => {
+            OptionSource::NixosConfiguration { flake, hostname }
+        }
+        (Some(flake), None, Some(name)) if modules.is_empty() && nixpkgs.is_none() => {
+            OptionSource::HomeConfiguration { flake, name }
+        }
+        _ => {
+            return Err(
+                "`--flake` needs exactly one of `--nixos` or `--home` and can't be combined with `--module` or a nixpkgs source"
+                    .to_string(),
+            )
+        }
+    };
+
+    Ok(Arguments { source })
+}
+
+/// Build the tree like the GUI binaries do, with the config attached for a NixOS host
+fn load_tree(source: &OptionSource) -> OptionNode {
+    let options = nixos_druid::run::get_options_from(source).expect("Getting NixOS options failed");
+    eprintln!("Parsing options is done.");
+
+    let (flake, hostname) = match source {
+        OptionSource::NixosConfiguration { flake, hostname } => (flake, hostname),
+        _ => return OptionNode::new("NixOS Options".to_string(), options),
+    };
+
+    let mut tree = OptionNode::new("NixOS Configuration".to_string(), options);
+    tree.set_extra_child_display(ExtraChildDisplay::First);
+    let config =
+        nixos_druid::run::get_config(flake, hostname).expect("Getting NixOS config failed");
+    eprintln!("Parsing config is done.");
+    tree.add_config(Some(config));
+    tree.mark_errors();
+    match nixos_druid::run::get_evaluated_defaults(flake, hostname) {
+        Ok(defaults) => {
+            tree.add_evaluated_defaults(Some(defaults));
+            eprintln!("Evaluating defaults is done.");
+        }
+        Err(e) => eprintln!("Evaluating defaults failed: {:?}", e),
+    }
+    tree
+}
+
+fn node_at<'a, T: OptionTreeNode>(root: &'a T, path: &[usize]) -> &'a T {
+    path.iter().fold(root, |node, &i| node.visible_child(i))
+}
+
+fn node_at_mut<'a, T: OptionTreeNode>(root: &'a mut T, path: &[usize]) -> &'a mut T {
+    path.iter().fold(root, |node, &i| node.visible_child_mut(i))
+}
+
+/// Option path of the node at `path`, leaving out the root
+fn option_path<T: OptionTreeNode>(root: &T, path: &[usize]) -> String {
+    let mut node = root;
+    let mut segments = Vec::with_capacity(path.len());
+    for &i in path {
+        node = node.visible_child(i);
+        segments.push(node.get_path_segment());
+    }
+    segments.join(".")
+}
+
+/// A line of the tree pane, `path` holds the indices of the visible children leading to it
+struct Row {
+    path: Vec<usize>,
+    depth: usize,
+}
+
+fn collect_rows<T: OptionTreeNode>(node: &T, path: &mut Vec<usize>, rows: &mut Vec<Row>) {
+    rows.push(Row {
+        path: path.clone(),
+        depth: path.len(),
+    });
+    if node.is_expanded() {
+        for i in 0..node.visible_children_count() {
+            path.push(i);
+            collect_rows(node.visible_child(i), path, rows);
+            path.pop();
+        }
+    }
+}
+
+/// Every node built so far whose option path contains `query` while the path of its
+/// parent doesn't, ignoring case. Folded recursive types aren't searched, they only
+/// grow when opened.
+fn search<T: OptionTreeNode>(root: &T, query: &str) -> Vec<Vec<usize>> {
+    fn visit<T: OptionTreeNode>(
+        node: &T,
+        prefix: &str,
+        query: &str,
+        path: &mut Vec<usize>,
+        out: &mut Vec<Vec<usize>>,
+    ) {
+        for i in 0..node.visible_children_count() {
+            let child = node.visible_child(i);
+            let name = match prefix {
+                "" => child.get_path_segment(),
+                _ => format!("{prefix}.{}", child.get_path_segment()),
+            };
+            path.push(i);
+            if name.to_lowercase().contains(query) && !prefix.to_lowercase().contains(query) {
+                out.push(path.clone());
+            }
+            visit(child, &name, query, path, out);
+            path.pop();
+        }
+    }
+
+    let mut matches = Vec::new();
+    visit(
+        root,
+        "",
+        &query.to_lowercase(),
+        &mut Vec::new(),
+        &mut matches,
+    );
+    matches
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Tree,
+    Details,
+}
+
+struct Browser {
+    tree: OptionNode,
+    has_config: bool,
+    rows: Vec<Row>,
+    list: ListState,
+    focus: Focus,
+    detail_scroll: u16,
+    /// The query being typed after `/`
+    input: Option<String>,
+    matches: Vec<Vec<usize>>,
+    current_match: usize,
+    status: String,
+}
+
+impl Browser {
+    fn new(mut tree: OptionNode, has_config: bool) -> Self {
+        tree.toggle_expanded();
+        let mut browser = Self {
+            tree,
+            has_config,
+            rows: Vec::new(),
+            list: ListState::default(),
+            focus: Focus::Tree,
+            detail_scroll: 0,
+            input: None,
+            matches: Vec::new(),
+            current_match: 0,
+            status: HELP.to_string(),
+        };
+        browser.rebuild_rows(None);
+        browser
+    }
+
+    fn selected(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    fn selected_path(&self) -> Vec<usize> {
+        self.rows[self.selected()].path.clone()
+    }
+
+    /// Recompute the rows after the tree changed, selecting the row at `path` if given
+    fn rebuild_rows(&mut self, path: Option<&[usize]>) {
+        let selected = self.selected();
+        self.rows.clear();
+        collect_rows(&self.tree, &mut Vec::new(), &mut self.rows);
+        let index = path
+            .and_then(|path| self.rows.iter().position(|row| row.path == path))
+            .unwrap_or_else(|| selected.min(self.rows.len() - 1));
+        self.select(index);
+    }
+
+    fn select(&mut self, index: usize) {
+        if self.list.selected() != Some(index) {
+            self.detail_scroll = 0;
+        }
+        self.list.select(Some(index));
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.rows.len() as isize - 1;
+        let index = (self.selected() as isize + delta).clamp(0, last);
+        self.select(index as usize);
+    }
+
+    /// Open the selected node, or go to its first child when it's open already
+    fn open(&mut self) {
+        let path = self.selected_path();
+        let node = node_at_mut(&mut self.tree, &path);
+        if node.visible_children_count() == 0 {
+            return;
+        }
+        if node.is_expanded() {
+            self.move_by(1);
+        } else {
+            node.toggle_expanded();
+            self.rebuild_rows(Some(&path));
+        }
+    }
+
+    /// Close the selected node, or go to its parent when it's closed already
+    fn close(&mut self) {
+        let mut path = self.selected_path();
+        let node = node_at_mut(&mut self.tree, &path);
+        if node.is_expanded() && !path.is_empty() {
+            node.toggle_expanded();
+        } else {
+            path.pop();
+        }
+        self.rebuild_rows(Some(&path));
+    }
+
+    /// Open every ancestor of the node at `path` and select it
+    fn reveal(&mut self, path: &[usize]) {
+        for depth in 0..path.len() {
+            let node = node_at_mut(&mut self.tree, &path[..depth]);
+            if !node.is_expanded() {
+                node.toggle_expanded();
+            }
+        }
+        self.rebuild_rows(Some(path));
+    }
+
+    fn run_search(&mut self, query: &str) {
+        self.matches = search(&self.tree, query);
+        self.current_match = 0;
+        match self.matches.first().cloned() {
+            Some(path) => {
+                self.status = format!("1/{} matches for `{query}`", self.matches.len());
+                self.reveal(&path);
+            }
+            None => self.status = format!("No option matches `{query}`"),
+        }
+    }
+
+    fn next_match(&mut self, forward: bool) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let count = self.matches.len();
+        self.current_match = match forward {
+            true => (self.current_match + 1) % count,
+            false => (self.current_match + count - 1) % count,
+        };
+        self.status = format!("{}/{} matches", self.current_match + 1, count);
+        let path = self.matches[self.current_match].clone();
+        self.reveal(&path);
+    }
+
+    fn toggle_only_errors(&mut self) {
+        if !self.has_config {
+            return;
+        }
+        let only_errors = !self.tree.only_errors;
+        self.tree.set_only_errors(only_errors);
+        // The indices of the visible children changed
+        self.matches.clear();
+        self.select(0);
+        self.rebuild_rows(None);
+        self.status = match only_errors {
+            true => "Only showing options containing errors".to_string(),
+            false => HELP.to_string(),
+        };
+    }
+
+    /// Returns `false` when the browser should quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        if let Some(ref mut input) = self.input {
+            match key.code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => {
+                    let query = std::mem::take(input);
+                    self.input = None;
+                    if !query.is_empty() {
+                        self.run_search(&query);
+                    }
+                }
+                KeyCode::Esc => self.input = None,
+                _ => (),
+            }
+            return true;
+        }
+
+        match (self.focus, key.code) {
+            (_, KeyCode::Char('q')) => return false,
+            (_, KeyCode::Tab) => {
+                self.focus = match self.focus {
+                    Focus::Tree => Focus::Details,
+                    Focus::Details => Focus::Tree,
+                }
+            }
+            (_, KeyCode::Char('/')) => self.input = Some(String::new()),
+            (_, KeyCode::Char('n')) => self.next_match(true),
+            (_, KeyCode::Char('N')) => self.next_match(false),
+            (_, KeyCode::Char('e')) => self.toggle_only_errors(),
+
+            (Focus::Tree, KeyCode::Up | KeyCode::Char('k')) => self.move_by(-1),
+            (Focus::Tree, KeyCode::Down | KeyCode::Char('j')) => self.move_by(1),
+            (Focus::Tree, KeyCode::PageUp) => self.move_by(-20),
+            (Focus::Tree, KeyCode::PageDown) => self.move_by(20),
+            (Focus::Tree, KeyCode::Home | KeyCode::Char('g')) => self.select(0),
+            (Focus::Tree, KeyCode::End | KeyCode::Char('G')) => self.select(self.rows.len() - 1),
+            (Focus::Tree, KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter) => self.open(),
+            (Focus::Tree, KeyCode::Left | KeyCode::Char('h')) => self.close(),
+
+            (Focus::Details, KeyCode::Up | KeyCode::Char('k')) => {
+                self.detail_scroll = self.detail_scroll.saturating_sub(1)
+            }
+            (Focus::Details, KeyCode::Down | KeyCode::Char('j')) => {
+                self.detail_scroll = self.detail_scroll.saturating_add(1)
+            }
+            (Focus::Details, KeyCode::PageUp) => {
+                self.detail_scroll = self.detail_scroll.saturating_sub(20)
+            }
+            (Focus::Details, KeyCode::PageDown) => {
+                self.detail_scroll = self.detail_scroll.saturating_add(20)
+            }
+            (Focus::Details, KeyCode::Home | KeyCode::Char('g')) => self.detail_scroll = 0,
+            _ => (),
+        }
+        true
+    }
+
+    fn row_line(&self, row: &Row) -> ListItem<'static> {
+        let node = node_at(&self.tree, &row.path);
+        let opener = match (node.visible_children_count(), node.is_expanded()) {
+            (0, _) => " ",
+            (_, true) => "▾",
+            (_, false) => "▸",
+        };
+        let mut badge = node
+            .default_comparison()
+            .map(|cmp| cmp.badge())
+            .unwrap_or("")
+            .to_string();
+        if node.upgrade_affected() {
+            badge.push_str("⚠️");
+        }
+        if node.deprecated_in_use() {
+            badge.push('⛔');
+        }
+
+        let text = format!(
+            "{}{} {} {} {}",
+            "  ".repeat(row.depth),
+            opener,
+            node.get_icon(),
+            node.get_path_segment(),
+            badge
+        );
+        let style = match node.has_error {
+            true => Style::default().fg(Color::Red),
+            false => Style::default(),
+        };
+        ListItem::new(Line::from(text)).style(style)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)])
+                .split(frame.area());
+            [chunks[0], chunks[1]]
+        };
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+            .split(main);
+
+        let current = self.focus;
+        let border = |focus: Focus| match current == focus {
+            true => Style::default().fg(Color::Cyan),
+            false => Style::default(),
+        };
+
+        let items: Vec<ListItem> = self.rows.iter().map(|row| self.row_line(row)).collect();
+        let tree = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(border(Focus::Tree))
+                    .title(self.tree.name.clone()),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(tree, panes[0], &mut self.list);
+
+        let path = self.selected_path();
+        let details = node_at(&self.tree, &path)
+            .focused_display_data()
+            .to_string();
+        let details = Paragraph::new(details)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(border(Focus::Details))
+                    .title(option_path(&self.tree, &path)),
+            )
+            .wrap(Wrap { trim: false })
+            .scroll((self.detail_scroll, 0));
+        frame.render_widget(details, panes[1]);
+
+        let status_line = match self.input {
+            Some(ref input) => format!("/{input}"),
+            None => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)
+}
+
+fn run(terminal: &mut Terminal<CrosstermBackend<Stdout>>, browser: &mut Browser) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| browser.draw(frame))?;
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press && !browser.handle_key(key) => {
+                return Ok(())
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn main() {
+    let arguments = parse_arguments(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        std::process::exit(1);
+    });
+
+    let has_config = matches!(arguments.source, OptionSource::NixosConfiguration { .. });
+    let tree = load_tree(&arguments.source);
+    let mut browser = Browser::new(tree, has_config);
+
+    // Don't leave the terminal in raw mode when panicking
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
+
+    enable_raw_mode().expect("Enabling raw mode failed");
+    execute!(io::stdout(), EnterAlternateScreen).expect("Entering alternate screen failed");
+    let mut terminal =
+        Terminal::new(CrosstermBackend::new(io::stdout())).expect("Creating terminal failed");
+
+    let result = run(&mut terminal, &mut browser);
+    restore_terminal().expect("Restoring terminal failed");
+    result.expect("Drawing the terminal failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn option(name: &str) -> Value {
+        json!({
+            "_option": true,
+            "description": "",
+            "type": {
+                "_type": true,
+                "name": name,
+                "description": name,
+                "functorName": name,
+                "nestedTypes": {},
+            },
+        })
+    }
+
+    fn arguments(args: &[&str]) -> Result<OptionSource, String> {
+        parse_arguments(args.iter().map(|a| a.to_string())).map(|a| a.source)
+    }
+
+    /// networking.{firewall.enable, hostName} and services.nginx.enable
+    fn browser() -> Browser {
+        let options = serde_json::from_value(json!({
+            "networking": {
+                "hostName": option("str"),
+                "firewall": { "enable": option("bool") },
+            },
+            "services": { "nginx": { "enable": option("bool") } },
+        }))
+        .unwrap();
+        Browser::new(OptionNode::new("root".to_string(), options), false)
+    }
+
+    fn shown(browser: &Browser) -> Vec<String> {
+        browser
+            .rows
+            .iter()
+            .map(|row| option_path(&browser.tree, &row.path))
+            .collect()
+    }
+
+    fn selected(browser: &Browser) -> String {
+        option_path(&browser.tree, &browser.selected_path())
+    }
+
+    #[test]
+    fn sources() {
+        assert_eq!(
+            arguments(&[]),
+            Ok(OptionSource::Nixpkgs {
+                nixpkgs: NixpkgsSource::NixPath,
+                modules: Vec::new(),
+            })
+        );
+        assert_eq!(
+            arguments(&["--nixpkgs-input", "/etc/nixos#nixpkgs"]),
+            Ok(OptionSource::Nixpkgs {
+                nixpkgs: NixpkgsSource::FlakeInput {
+                    flake: "/etc/nixos".to_string(),
+                    input: "nixpkgs".to_string(),
+                },
+                modules: Vec::new(),
+            })
+        );
+        assert_eq!(
+            arguments(&["--flake", "/etc/nixos", "--nixos", "host"]),
+            Ok(OptionSource::NixosConfiguration {
+                flake: "/etc/nixos".to_string(),
+                hostname: "host".to_string(),
+            })
+        );
+        assert_eq!(
+            arguments(&["--home", "me", "--flake", "."]),
+            Ok(OptionSource::HomeConfiguration {
+                flake: ".".to_string(),
+                name: "me".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(arguments(&["--flake", "/etc/nixos"]).is_err());
+        assert!(arguments(&["--flake", ".", "--nixos", "a", "--home", "b"]).is_err());
+        assert!(arguments(&["--flake", ".", "--nixos", "a", "--nixpkgs", "."]).is_err());
+        assert!(arguments(&["--nixpkgs-input", "/etc/nixos"]).is_err());
+        assert!(arguments(&["--module", "/does/not/exist.nix"]).is_err());
+        assert_eq!(
+            arguments(&["--nixos"]),
+            Err("`--nixos` expects a value".to_string())
+        );
+        assert_eq!(
+            arguments(&["--verbose"]),
+            Err("Unknown argument `--verbose`".to_string())
+        );
+    }
+
+    #[test]
+    fn rows() {
+        let browser = browser();
+        assert_eq!(shown(&browser), ["", "networking", "services"]);
+        let depths: Vec<usize> = browser.rows.iter().map(|row| row.depth).collect();
+        assert_eq!(depths, [0, 1, 1]);
+    }
+
+    #[test]
+    fn open_and_close() {
+        let mut browser = browser();
+        browser.move_by(1);
+        browser.open();
+        assert_eq!(
+            shown(&browser),
+            [
+                "",
+                "networking",
+                "networking.firewall",
+                "networking.hostName",
+                "services"
+            ]
+        );
+        assert_eq!(selected(&browser), "networking");
+
+        // Open again goes to the first child, a leaf doesn't open
+        browser.open();
+        assert_eq!(selected(&browser), "networking.firewall");
+        browser.move_by(1);
+        browser.open();
+        assert_eq!(selected(&browser), "networking.hostName");
+        assert_eq!(browser.rows.len(), 5);
+
+        // Closing a closed node goes to its parent, closing that hides the children
+        browser.close();
+        assert_eq!(selected(&browser), "networking");
+        browser.close();
+        assert_eq!(shown(&browser), ["", "networking", "services"]);
+        assert_eq!(selected(&browser), "networking");
+
+        // The root stays open
+        browser.select(0);
+        browser.close();
+        assert_eq!(shown(&browser), ["", "networking", "services"]);
+    }
+
+    #[test]
+    fn selection_stays_in_the_rows() {
+        let mut browser = browser();
+        browser.move_by(-5);
+        assert_eq!(browser.selected(), 0);
+        browser.move_by(20);
+        assert_eq!(selected(&browser), "services");
+
+        // The last row disappears when its parent is closed
+        browser.reveal(&[1, 0, 0]);
+        assert_eq!(selected(&browser), "services.nginx.enable");
+        assert_eq!(browser.selected(), browser.rows.len() - 1);
+        browser.tree.toggle_expanded();
+        browser.rebuild_rows(None);
+        assert_eq!(shown(&browser), [""]);
+        assert_eq!(browser.selected(), 0);
+    }
+
+    #[test]
+    fn search_and_reveal() {
+        let mut browser = browser();
+        let matches = search(&browser.tree, "ENABLE");
+        let paths: Vec<String> = matches
+            .iter()
+            .map(|path| option_path(&browser.tree, path))
+            .collect();
+        assert_eq!(
+            paths,
+            ["networking.firewall.enable", "services.nginx.enable"]
+        );
+        // Children of a match only match when they add to it
+        let matches = search(&browser.tree, "networking");
+        assert_eq!(matches, [vec![0]]);
+
+        browser.run_search("nginx");
+        assert_eq!(selected(&browser), "services.nginx");
+        assert_eq!(
+            shown(&browser),
+            ["", "networking", "services", "services.nginx"]
+        );
+        browser.run_search("enable");
+        browser.next_match(true);
+        assert_eq!(selected(&browser), "services.nginx.enable");
+        browser.next_match(true);
+        assert_eq!(selected(&browser), "networking.firewall.enable");
+        browser.next_match(false);
+        assert_eq!(selected(&browser), "services.nginx.enable");
+    }
+}
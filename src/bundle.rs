@@ -0,0 +1,463 @@
+//! Read-only bundles of everything `nixos-config-browser` shows of a host, so it can be
+//! browsed elsewhere without access to the flake and without evaluating Nix.
+//!
+//! A bundle is saved with the encoding of `parse::snapshot`, it only has to match the
+//! format version and not the crate version, or a JSON cache, like a snapshot does.
+
+use crate::parse::snapshot::{self, Reader, Snapshot, SnapshotError, Writer};
+use crate::parse::{
+    show_path, NixConfigChecks, NixDefinition, NixGuardedValue, NixOption, NixPathSegment,
+    NixTypeValue, NixValue,
+};
+use crate::run::NixpkgsInfo;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"nxdbndl\0";
+
+/// What `Bundle::redact_secrets` replaces values with
+pub const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// The host as `<flake>#<hostname>`
+    pub host: String,
+    pub nixpkgs: Option<NixpkgsInfo>,
+    pub options: NixValue,
+    /// Including the details of the errors that were explained
+    pub config: NixGuardedValue,
+    pub evaluated_defaults: Option<NixGuardedValue>,
+    pub definitions: Vec<NixDefinition>,
+    /// Renamed and removed options still defined in the config
+    pub deprecated: Vec<NixDefinition>,
+    pub checks: Option<NixConfigChecks>,
+    /// Paths of the values replaced by `redact_secrets`, the ones of option
+    /// documentation end in ` (default)` or ` (example)`
+    pub redacted: Vec<String>,
+}
+
+impl Bundle {
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn open(path: &Path) -> Result<Self, SnapshotError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&snapshot::FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&snapshot::encode(self));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Reader::new(bytes);
+        if r.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(SnapshotError::Incompatible(
+                "not a snapshot bundle".to_string(),
+            ));
+        }
+        r.format_version()?;
+        snapshot::decode(&bytes[MAGIC.len() + 4..])
+    }
+
+    /// Replace the values of the config and of the evaluated defaults whose attribute
+    /// names suggest a secret, see `is_secret_name`, as well as the default and example
+    /// of options with such names, which the host's own modules may set to a secret.
+    /// Booleans and `null` are kept, they can't hold one. Messages of assertions,
+    /// warnings and errors are kept as well.
+    pub fn redact_secrets(&mut self) {
+        let mut redacted = Vec::new();
+        redact(&mut self.config, &mut Vec::new(), &mut redacted);
+        if let Some(ref mut defaults) = self.evaluated_defaults {
+            // The same paths as in the config, only report those once
+            redact(defaults, &mut Vec::new(), &mut Vec::new());
+        }
+        redact_options(&mut self.options, &mut Vec::new(), &mut redacted);
+        redacted.sort();
+        self.redacted.extend(redacted);
+    }
+}
+
+/// Whether an attribute name suggests its value is a secret, e.g. `hashedPassword`,
+/// `pskRaw`, `secretKeyFile`, `apiToken` or `privateKey`, but not `keyMap` or `hostKeys`
+pub fn is_secret_name(name: &str) -> bool {
+    const SECRET_WORDS: [&str; 11] = [
+        "password",
+        "passwd",
+        "passphrase",
+        "secret",
+        "secrets",
+        "token",
+        "tokens",
+        "psk",
+        "credential",
+        "credentials",
+        "apikey",
+    ];
+    const KEY_QUALIFIERS: [&str; 6] = ["private", "secret", "api", "auth", "access", "preshared"];
+
+    let words = words(name);
+    words.iter().any(|w| SECRET_WORDS.contains(&w.as_str()))
+        || words
+            .windows(2)
+            .any(|pair| pair[1] == "key" && KEY_QUALIFIERS.contains(&pair[0].as_str()))
+}
+
+/// Lowercase words of a camelCase, snake_case or kebab-case name
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        let next = chars.get(i + 1);
+        // `apiKey` and the `PSK` of `PSKRaw` start new words
+        let starts_word = c.is_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_numeric()
+                    || (p.is_uppercase() && next.is_some_and(|n| n.is_lowercase()))
+            });
+        if starts_word && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Whether a value can hold a secret, booleans and `null` can't
+fn can_hold_secret(val: &NixGuardedValue) -> bool {
+    !matches!(val, NixGuardedValue::Bool(_) | NixGuardedValue::Null(_))
+}
+
+fn redact(val: &mut NixGuardedValue, path: &mut Vec<NixPathSegment>, redacted: &mut Vec<String>) {
+    match val {
+        NixGuardedValue::Attrs(attrs) => {
+            for (name, v) in attrs.iter_mut() {
+                path.push(NixPathSegment::Name(name.clone()));
+                if !is_secret_name(name) {
+                    redact(v, path, redacted);
+                } else if can_hold_secret(v) {
+                    **v = NixGuardedValue::String(REDACTED.to_string());
+                    redacted.push(show_path(path));
+                }
+                path.pop();
+            }
+        }
+        NixGuardedValue::List(items) => {
+            for (i, v) in items.iter_mut().enumerate() {
+                path.push(NixPathSegment::Index(i));
+                redact(v, path, redacted);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Redact the defaults and examples of secret-named options, also of the options of
+/// submodules. Those are named by the path of their option, e.g. `users.users.password`
+/// for the submodule of `users.users`.
+fn redact_options(val: &mut NixValue, path: &mut Vec<NixPathSegment>, redacted: &mut Vec<String>) {
+    match val {
+        NixValue::Set(set) => {
+            for (name, v) in set.iter_mut() {
+                path.push(NixPathSegment::Name(name.clone()));
+                if let (NixValue::Option(opt), true) = (&mut **v, is_secret_name(name)) {
+                    redact_documentation(opt, &show_path(path), redacted);
+                }
+                redact_options(v, path, redacted);
+                path.pop();
+            }
+        }
+        NixValue::Option(opt) => redact_type(&mut opt.r#type, path, redacted),
+    }
+}
+
+fn redact_type(t: &mut NixTypeValue, path: &mut Vec<NixPathSegment>, redacted: &mut Vec<String>) {
+    match t {
+        NixTypeValue::Type(t) => {
+            for nested in t.nestedTypes.values_mut() {
+                redact_type(nested, path, redacted);
+            }
+        }
+        NixTypeValue::Submodule(sub) => {
+            let mut options = NixValue::Set(std::mem::take(&mut sub.options));
+            redact_options(&mut options, path, redacted);
+            if let NixValue::Set(options) = options {
+                sub.options = options;
+            }
+            if let Some(ref mut freeform) = sub.freeform_type {
+                redact_type(freeform, path, redacted);
+            }
+        }
+        NixTypeValue::Reference(_) => (),
+    }
+}
+
+fn redact_documentation(opt: &mut NixOption, path: &str, redacted: &mut Vec<String>) {
+    for (kind, val) in [("default", &mut opt.default), ("example", &mut opt.example)] {
+        if let Some(ref mut val) = val {
+            if can_hold_secret(val) {
+                *val = NixGuardedValue::String(REDACTED.to_string());
+                redacted.push(format!("{} ({})", path, kind));
+            }
+        }
+    }
+}
+
+impl Snapshot for Bundle {
+    const KIND: u8 = 2;
+
+    fn encode<'a>(&'a self, w: &mut Writer<'a>) {
+        w.str(&self.host);
+        match self.nixpkgs {
+            Some(ref info) => {
+                w.bool(true);
+                w.str(&info.version);
+                w.opt_str(&info.revision);
+            }
+            None => w.bool(false),
+        }
+        w.value(&self.options);
+        w.guarded(&self.config);
+        match self.evaluated_defaults {
+            Some(ref defaults) => {
+                w.bool(true);
+                w.guarded(defaults);
+            }
+            None => w.bool(false),
+        }
+        for definitions in [&self.definitions, &self.deprecated] {
+            w.uint(definitions.len() as u64);
+            for definition in definitions {
+                w.strs(&definition.loc);
+                w.strs(&definition.files);
+            }
+        }
+        match self.checks {
+            Some(ref checks) => {
+                w.bool(true);
+                w.guarded(&checks.assertions);
+                w.guarded(&checks.warnings);
+            }
+            None => w.bool(false),
+        }
+        w.strs(&self.redacted);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, SnapshotError> {
+        fn read_definitions(r: &mut Reader) -> Result<Vec<NixDefinition>, SnapshotError> {
+            let len = r.len()?;
+            (0..len)
+                .map(|_| {
+                    Ok(NixDefinition {
+                        loc: r.strs()?,
+                        files: r.strs()?,
+                    })
+                })
+                .collect()
+        }
+
+        let host = r.str()?;
+        let nixpkgs = if r.bool()? {
+            Some(NixpkgsInfo {
+                version: r.str()?,
+                revision: r.opt_str()?,
+            })
+        } else {
+            None
+        };
+        let options = r.value()?;
+        let config = r.guarded()?;
+        let evaluated_defaults = if r.bool()? { Some(r.guarded()?) } else { None };
+        let definitions = read_definitions(r)?;
+        let deprecated = read_definitions(r)?;
+        let checks = if r.bool()? {
+            Some(NixConfigChecks {
+                assertions: r.guarded()?,
+                warnings: r.guarded()?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Bundle {
+            host,
+            nixpkgs,
+            options,
+            config,
+            evaluated_defaults,
+            definitions,
+            deprecated,
+            checks,
+            redacted: r.strs()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_names() {
+        for name in [
+            "hashedPassword",
+            "initialPassword",
+            "passwordFile",
+            "pskRaw",
+            "PSKRaw",
+            "secretKeyFile",
+            "apiToken",
+            "privateKey",
+            "api_key",
+            "client-secret",
+            "environmentSecrets",
+        ] {
+            assert!(is_secret_name(name), "{} should be a secret", name);
+        }
+        for name in [
+            "keyMap",
+            "hostKeys",
+            "authorizedKeys",
+            "passthru",
+            "publicKey",
+            "keys",
+        ] {
+            assert!(!is_secret_name(name), "{} should not be a secret", name);
+        }
+    }
+
+    #[test]
+    fn redacts_option_documentation() {
+        let options: NixValue = serde_json::from_str(
+            r#"{
+                "apiToken": {
+                    "_option": true, "description": "", "default": "hunter2",
+                    "example": { "_type": "literalExpression", "text": "\"abc\"" },
+                    "type": { "_type": true, "name": "str", "description": "string", "functorName": "str", "nestedTypes": {} }
+                },
+                "enable": {
+                    "_option": true, "description": "", "default": false, "example": null,
+                    "type": { "_type": true, "name": "bool", "description": "boolean", "functorName": "bool", "nestedTypes": {} }
+                },
+                "users": {
+                    "_option": true, "description": "", "default": null, "example": null,
+                    "type": { "_submodule": true, "options": { "password": {
+                        "_option": true, "description": "", "default": "x", "example": null,
+                        "type": { "_type": true, "name": "str", "description": "string", "functorName": "str", "nestedTypes": {} }
+                    } } }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut bundle = Bundle {
+            host: "/etc/nixos#laptop".to_string(),
+            nixpkgs: None,
+            options,
+            config: NixGuardedValue::Attrs(Default::default()),
+            evaluated_defaults: None,
+            definitions: Vec::new(),
+            deprecated: Vec::new(),
+            checks: None,
+            redacted: Vec::new(),
+        };
+        bundle.redact_secrets();
+        assert_eq!(
+            bundle.redacted,
+            [
+                "apiToken (default)",
+                "apiToken (example)",
+                "users.password (default)"
+            ]
+        );
+
+        let option = |set: &crate::parse::NixSet, name: &str| match *set[name] {
+            NixValue::Option(ref opt) => opt.clone(),
+            NixValue::Set(_) => panic!("{} is not an option", name),
+        };
+        let options = match bundle.options {
+            NixValue::Set(ref set) => set.clone(),
+            NixValue::Option(_) => unreachable!(),
+        };
+        let redacted = NixGuardedValue::String(REDACTED.to_string());
+        let token = option(&options, "apiToken");
+        assert_eq!(token.default.unwrap().compare(&redacted), Some(true));
+        assert_eq!(token.example.unwrap().compare(&redacted), Some(true));
+        let enable = option(&options, "enable").default.unwrap();
+        assert_eq!(enable.compare(&NixGuardedValue::Bool(false)), Some(true));
+        let password = match option(&options, "users").r#type {
+            NixTypeValue::Submodule(sub) => option(&sub.options, "password"),
+            _ => panic!("users should be a submodule"),
+        };
+        assert_eq!(password.default.unwrap().compare(&redacted), Some(true));
+    }
+
+    #[test]
+    fn redacts_secrets_and_round_trips() {
+        let config: NixGuardedValue = serde_json::from_str(
+            r#"{
+                "networking": { "wireless": { "networks": { "home": { "psk": "hunter2", "hidden": false } } } },
+                "services": { "openssh": { "settings": { "PasswordAuthentication": false } } },
+                "users": { "users": { "alice": { "hashedPassword": null, "initialPassword": "x", "name": "alice" } } }
+            }"#,
+        )
+        .unwrap();
+        let mut bundle = Bundle {
+            host: "/etc/nixos#laptop".to_string(),
+            nixpkgs: None,
+            options: NixValue::Set(Default::default()),
+            config,
+            evaluated_defaults: None,
+            definitions: vec![NixDefinition {
+                loc: vec!["users".to_string(), "users".to_string()],
+                files: vec!["/etc/nixos/users.nix".to_string()],
+            }],
+            deprecated: Vec::new(),
+            checks: None,
+            redacted: Vec::new(),
+        };
+        bundle.redact_secrets();
+        assert_eq!(
+            bundle.redacted,
+            [
+                "networking.wireless.networks.home.psk",
+                "users.users.alice.initialPassword"
+            ]
+        );
+        let shown = bundle.config.to_string();
+        assert!(!shown.contains("hunter2"));
+        assert!(shown.contains(REDACTED));
+
+        let opened = Bundle::from_bytes(&bundle.to_bytes()).unwrap();
+        assert_eq!(opened.host, bundle.host);
+        assert_eq!(opened.redacted, bundle.redacted);
+        assert_eq!(opened.definitions[0].files, bundle.definitions[0].files);
+        assert_eq!(opened.config.compare(&bundle.config), Some(true));
+
+        // A snapshot of a JSON cache is not a bundle
+        let key = snapshot::CacheKey {
+            len: 0,
+            modified: Default::default(),
+        };
+        let cache_snapshot = snapshot::to_bytes(&bundle.config, key);
+        assert!(matches!(
+            Bundle::from_bytes(&cache_snapshot),
+            Err(SnapshotError::Incompatible(_))
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+//! Failing `assertions` and active `warnings` of a config, as shown before a rebuild.
+
+use crate::parse::{NixConfigChecks, NixGuardedValue};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckKind {
+    FailedAssertion,
+    Warning,
+}
+
+impl std::fmt::Display for CheckKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckKind::FailedAssertion => write!(f, "Failed assertion"),
+            CheckKind::Warning => write!(f, "Warning"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConfigCheck {
+    pub kind: CheckKind,
+    pub message: String,
+}
+
+impl ConfigCheck {
+    /// Everything in the message that looks like an option path, e.g. `boot.loader.grub.devices`.
+    /// Whether these are actual options is up to the caller.
+    pub fn mentioned_paths(&self) -> Vec<String> {
+        let is_path_char =
+            |c: char| c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '<' | '>' | '*' | '"');
+
+        let mut paths: Vec<String> = Vec::new();
+        for word in self.message.split(|c| !is_path_char(c)) {
+            let path = word.trim_matches(|c| c == '.' || c == '"');
+            // A single dot could still be the end of a sentence, require two segments
+            let segments = path.split('.').count();
+            // Neither versions like `22.05` nor abbreviations like `e.g.` are options
+            let is_prose = !path.starts_with(char::is_alphabetic)
+                || path.split('.').all(|s| s.chars().count() == 1);
+            if segments >= 2
+                && !is_prose
+                && path.split('.').all(|s| !s.is_empty())
+                && !paths.iter().any(|p| p == path)
+            {
+                paths.push(path.to_string());
+            }
+        }
+        paths
+    }
+}
+
+impl std::fmt::Display for ConfigCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+/// Failed assertions first, then warnings, in the order the config lists them
+pub fn collect_checks(raw: &NixConfigChecks) -> Vec<ConfigCheck> {
+    let mut checks = messages(CheckKind::FailedAssertion, "assertions", &raw.assertions);
+    checks.extend(messages(CheckKind::Warning, "warnings", &raw.warnings));
+    checks
+}
+
+fn messages(kind: CheckKind, option: &str, list: &NixGuardedValue) -> Vec<ConfigCheck> {
+    let check = |message: String| ConfigCheck { kind, message };
+
+    match list {
+        NixGuardedValue::List(items) => items
+            .iter()
+            .map(|item| match **item {
+                NixGuardedValue::String(ref s) => check(s.clone()),
+                ref other => check(format!("<message can't be shown: {other:?}>")),
+            })
+            .collect(),
+        NixGuardedValue::Error(_) => vec![check(format!("<evaluating `{option}` failed>"))],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(message: &str) -> Vec<String> {
+        let check = ConfigCheck {
+            kind: CheckKind::Warning,
+            message: message.to_string(),
+        };
+        check.mentioned_paths()
+    }
+
+    #[test]
+    fn mentioned_paths() {
+        assert_eq!(
+            paths("You must set boot.loader.grub.devices."),
+            ["boot.loader.grub.devices"]
+        );
+        assert_eq!(
+            paths("The option \"services.nginx.enable\" conflicts with `services.caddy.enable`"),
+            ["services.nginx.enable", "services.caddy.enable"]
+        );
+        assert_eq!(
+            paths("users.users.<name>.shell is unset"),
+            ["users.users.<name>.shell"]
+        );
+        assert_eq!(
+            paths("Set networking.hostName, networking.hostName is empty"),
+            ["networking.hostName"]
+        );
+    }
+
+    #[test]
+    fn prose_is_not_a_path() {
+        assert!(paths("Use a different shell, e.g. zsh. Or i.e. bash.").is_empty());
+        assert!(paths("Deprecated since 22.05 and removed in 1.2.3").is_empty());
+        assert!(paths("Nothing to see here... really").is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+//! The closure of a built system, read from the local store with `nix path-info`.
+//!
+//! Everything here only looks at paths that are built or substituted already,
+//! so it works offline.
+
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StorePathInfo {
+    /// Only part of the output of older Nix versions, newer ones use it as key
+    #[serde(default)]
+    pub path: String,
+    #[serde(rename = "narSize")]
+    pub nar_size: u64,
+    #[serde(default, rename = "closureSize")]
+    pub closure_size: Option<u64>,
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+/// The output of `nix path-info --json`, its format changed in Nix 2.19
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum PathInfoOutput {
+    List(Vec<StorePathInfo>),
+    Map(HashMap<String, StorePathInfo>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub root: String,
+    pub paths: HashMap<String, StorePathInfo>,
+}
+
+impl Closure {
+    pub fn new(root: String, output: PathInfoOutput) -> Self {
+        let infos: Vec<StorePathInfo> = match output {
+            PathInfoOutput::List(list) => list,
+            PathInfoOutput::Map(map) => map
+                .into_iter()
+                .map(|(path, info)| StorePathInfo { path, ..info })
+                .collect(),
+        };
+
+        // Older Nix versions don't always prefix references with the store directory
+        let store_dir = root
+            .rsplit_once('/')
+            .map_or("/nix/store", |(dir, _)| dir)
+            .to_string();
+        let paths = infos
+            .into_iter()
+            .map(|mut info| {
+                for reference in info.references.iter_mut() {
+                    if !reference.starts_with('/') {
+                        *reference = format!("{}/{}", store_dir, reference);
+                    }
+                }
+                (info.path.clone(), info)
+            })
+            .collect();
+
+        Self { root, paths }
+    }
+
+    pub fn closure_size(&self) -> u64 {
+        self.paths
+            .get(&self.root)
+            .and_then(|info| info.closure_size)
+            .unwrap_or_else(|| self.paths.values().map(|info| info.nar_size).sum())
+    }
+
+    /// Paths with the largest own size first
+    pub fn largest(&self, count: usize) -> Vec<&StorePathInfo> {
+        let mut infos: Vec<&StorePathInfo> = self.paths.values().collect();
+        infos.sort_by(|l, r| r.nar_size.cmp(&l.nar_size).then(l.path.cmp(&r.path)));
+        infos.truncate(count);
+        infos
+    }
+
+    /// A shortest chain of references from the root to `target`, like `nix why-depends`.
+    /// `None` if `target` is not part of the closure.
+    pub fn why_depends(&self, target: &str) -> Option<Vec<&str>> {
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([self.root.as_str()]);
+
+        while let Some(path) = queue.pop_front() {
+            if path == target {
+                let mut chain = vec![path];
+                let mut current = path;
+                while let Some(prev) = previous.get(current) {
+                    chain.push(prev);
+                    current = prev;
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+
+            let references = self.paths.get(path).map(|info| info.references.iter());
+            for reference in references.into_iter().flatten() {
+                let reference = reference.as_str();
+                if reference != self.root && !previous.contains_key(reference) {
+                    previous.insert(reference, path);
+                    queue.push_back(reference);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Name of a store path without the store directory and hash
+pub fn short_name(path: &str) -> &str {
+    let base = path.rsplit('/').next().unwrap_or(path);
+    match base.split_once('-') {
+        Some((hash, name)) if hash.len() == 32 => name,
+        _ => base,
+    }
+}
+
+/// Human readable size in binary units, e.g. `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn closure(references: serde_json::Value) -> Closure {
+        let infos: HashMap<String, serde_json::Value> = serde_json::from_value(references).unwrap();
+        let output = infos
+            .into_iter()
+            .map(|(path, references)| {
+                let info = json!({ "narSize": 1, "references": references });
+                (format!("/nix/store/{path}"), info)
+            })
+            .collect();
+        let output = serde_json::from_value(output).unwrap();
+        Closure::new("/nix/store/system".to_string(), output)
+    }
+
+    #[test]
+    fn why_depends() {
+        // Store paths usually refer to themselves, older Nix versions leave out the store dir
+        let closure = closure(json!({
+            "system": ["etc", "sw"],
+            "etc": ["etc", "/nix/store/openssl"],
+            "sw": ["sw", "curl"],
+            "curl": ["openssl"],
+            "openssl": ["openssl", "system"],
+        }));
+
+        let chain = |target: &str| closure.why_depends(&format!("/nix/store/{target}"));
+        assert_eq!(
+            chain("openssl").unwrap(),
+            ["/nix/store/system", "/nix/store/etc", "/nix/store/openssl"]
+        );
+        assert_eq!(
+            chain("curl").unwrap(),
+            ["/nix/store/system", "/nix/store/sw", "/nix/store/curl"]
+        );
+        assert_eq!(chain("system").unwrap(), ["/nix/store/system"]);
+        assert!(chain("glibc").is_none());
+    }
+}
@@ -1,10 +1,13 @@
 use std::marker::PhantomData;
+use std::time::Duration;
 
-use crate::delegate::FOCUS_OPTION;
+use crate::data::AppData;
+use crate::delegate::{FOCUS_OPTION, MERGE_CONFIG};
 
-use crate::tree_node::TreeOptionNode;
+use crate::node::OptionNode;
+use crate::tree_node::{OptionTreeNode, TreeOptionNode};
 use druid::widget::{Controller, Widget};
-use druid::{Env, Event, EventCtx, Lens};
+use druid::{Env, Event, EventCtx, Lens, TimerToken};
 
 pub struct FocusOption<T>(PhantomData<T>);
 
@@ -32,6 +35,65 @@ impl<T: TreeOptionNode, W: Widget<T>> Controller<T, W> for FocusOption<T> {
     }
 }
 
+/// How long the options changed by a reload stay highlighted
+const CHANGED_HIGHLIGHT_TIME: Duration = Duration::from_secs(3);
+
+/// Merges the trees sent with `MERGE_CONFIG` into the shown one, see
+/// `OptionNode::merge_config`. The detail pane is updated for the selected option, the changed options are unmarked after `CHANGED_HIGHLIGHT_TIME`.
+pub struct LiveReload {
+    clear_changed: TimerToken,
+}
+
+impl LiveReload {
+    pub fn new() -> Self {
+        Self {
+            clear_changed: TimerToken::INVALID,
+        }
+    }
+}
+
+impl<W: Widget<AppData<OptionNode>>> Controller<AppData<OptionNode>, W> for LiveReload {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppData<OptionNode>,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(MERGE_CONFIG) => {
+                if let Some(tree) = cmd.get_unchecked(MERGE_CONFIG).take() {
+                    let selected = data.display.get_value().and_then(|value| {
+                        let path = data.tree.path_to_value(value)?;
+                        let name = data.tree.node_at(&path)?.name.clone();
+                        Some((path, name))
+                    });
+                    // Only the changes of the latest reload are highlighted
+                    data.tree.clear_changed();
+                    if data.tree.merge_config(tree) {
+                        self.clear_changed = ctx.request_timer(CHANGED_HIGHLIGHT_TIME);
+                    }
+                    // Nodes added or removed before it move the selected one
+                    let node = selected.and_then(|(path, name)| {
+                        data.tree.node_at(&path).filter(|node| node.name == name)
+                    });
+                    // Through the delegate, which explains new errors
+                    if let Some(node) = node {
+                        ctx.submit_command(FOCUS_OPTION.with(node.focused_display_data()));
+                    }
+                }
+                ctx.set_handled();
+            }
+            Event::Timer(token) if *token == self.clear_changed => {
+                data.tree.clear_changed();
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
 pub struct DisabledController<A, L> {
     lens: L,
     phantom_a: PhantomData<A>,
@@ -5,7 +5,7 @@ use druid::{Data, Lens};
 /// Top-level `Data` struct, holds ALL data of the application
 #[derive(Clone, Data, Lens)]
 pub struct AppData<T: TreeOptionNode> {
-    tree: T,
+    pub tree: T,
     pub display: DisplayData,
 }
 
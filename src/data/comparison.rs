@@ -0,0 +1,57 @@
+use crate::data::OptionDocumentation;
+use crate::parse::NixGuardedValue;
+#[cfg(feature = "gui")]
+use druid::Data;
+
+/// How the value of an option relates to its default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum DefaultComparison {
+    /// The value is equal to the (evaluated) default
+    Default,
+    /// The value differs from the default
+    Overridden,
+    /// The default is a `literalExpression` that could not be evaluated,
+    /// or one of the values contains functions or errors
+    Incomparable,
+}
+
+impl DefaultComparison {
+    /// Returns `None` when there is no value or no default to compare.
+    pub fn compare(
+        documentation: Option<&OptionDocumentation>,
+        value: Option<&NixGuardedValue>,
+    ) -> Option<Self> {
+        let default = documentation?.effective_default()?;
+        let value = value?;
+
+        Some(match default.compare(value) {
+            Some(true) => DefaultComparison::Default,
+            Some(false) => DefaultComparison::Overridden,
+            None => DefaultComparison::Incomparable,
+        })
+    }
+
+    /// Small badge to show next to the option name in the tree
+    pub fn badge(&self) -> &'static str {
+        use DefaultComparison::*;
+
+        match self {
+            Default => "",
+            Overridden => "✏️",
+            Incomparable => "❔",
+        }
+    }
+}
+
+impl std::fmt::Display for DefaultComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use DefaultComparison::*;
+
+        match self {
+            Default => write!(f, "default"),
+            Overridden => write!(f, "overridden"),
+            Incomparable => write!(f, "cannot be compared"),
+        }
+    }
+}
@@ -1,20 +1,48 @@
-use crate::parse::{NixGuardedValue, NixOption};
+use crate::data::DefaultComparison;
+use crate::parse::{
+    show_path, NixAliasKind, NixDerivation, NixErrorDetails, NixGuardedValue, NixOption,
+    NixPathSegment,
+};
+#[cfg(feature = "gui")]
 use druid::{Data, Lens};
+use std::sync::Arc;
 
 /// Top-level `Data` instance holding all data of a selected option
-#[derive(Clone, Data, Lens)]
+#[derive(Clone)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
 pub struct DisplayData {
     documentation: Option<OptionDocumentation>,
-    // FIXME: Very hacky, maybe implement PartialEq?
-    #[data(ignore)]
-    value: Option<NixGuardedValue>,
+    /// Shared with the `OptionNode` it is shown for, a new value is a new `Arc`
+    value: Option<Arc<NixGuardedValue>>,
 }
 
 impl std::fmt::Display for DisplayData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match (self.documentation.as_ref(), self.value.as_ref()) {
-            (None, _) => write!(f, "No documentation available."),
-            (Some(ref d), Some(ref v)) => write!(f, "Value: {}\n\n\n{}", v, d),
+        match (self.documentation.as_ref(), self.value.as_deref()) {
+            // Part of a value, e.g. of a JSON `settings` option
+            (None, Some(ref v)) => write!(f, "Value: {}", v),
+            (None, None) => write!(f, "No documentation available."),
+            (Some(ref d), Some(ref v)) => {
+                write!(f, "Value: {}", v)?;
+                let packages = v.derivations();
+                if !packages.is_empty() {
+                    write!(f, "\n\nPackages ({}):", packages.len())?;
+                    for package in packages {
+                        write!(f, "\n\n{}", PackageCard(package))?;
+                    }
+                }
+                for error in v.errors() {
+                    write!(f, "\n\nError in `{}`: ", show_path(&error.path))?;
+                    match error.details {
+                        Some(ref details) => write!(f, "{}", details)?,
+                        None => write!(f, "message unknown")?,
+                    }
+                }
+                if let (Some(def), Some(cmp)) = (d.effective_default(), self.default_comparison()) {
+                    write!(f, "\n\nDefault → Current ({}): {} → {}", cmp, def, v)?;
+                }
+                write!(f, "\n\n\n{}", d)
+            }
             (Some(ref d), None) => d.fmt(f),
         }
     }
@@ -30,24 +58,104 @@ impl DisplayData {
 
     pub fn new_with(
         documentation: Option<OptionDocumentation>,
-        value: Option<NixGuardedValue>,
+        value: Option<Arc<NixGuardedValue>>,
     ) -> Self {
         Self {
             documentation,
             value,
         }
     }
+
+    pub fn get_documentation(&self) -> Option<&OptionDocumentation> {
+        self.documentation.as_ref()
+    }
+
+    pub fn get_value(&self) -> Option<&NixGuardedValue> {
+        self.value.as_deref()
+    }
+
+    pub fn default_comparison(&self) -> Option<DefaultComparison> {
+        DefaultComparison::compare(self.documentation.as_ref(), self.value.as_deref())
+    }
+
+    /// Paths of the errors in the value whose message isn't known yet
+    pub fn unexplained_errors(&self) -> Vec<Vec<NixPathSegment>> {
+        self.value
+            .iter()
+            .flat_map(|v| v.errors())
+            .filter(|e| e.details.is_none())
+            .map(|e| e.path.clone())
+            .collect()
+    }
+
+    /// Show the details of the error at `path`, if the value has it. The value is copied
+    /// instead of changed for the `OptionNode` sharing it.
+    pub fn explain_error(&mut self, path: &[NixPathSegment], details: &NixErrorDetails) {
+        if let Some(ref mut value) = self.value {
+            let unexplained = value
+                .errors()
+                .iter()
+                .any(|e| e.path == path && e.details.is_none());
+            if unexplained {
+                Arc::make_mut(value).explain_error(path, details);
+            }
+        }
+    }
 }
 
 /// `Data` instance holding the static part of the documentation
-#[derive(Clone, Data, Debug, Lens)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
 pub struct OptionDocumentation {
     pub description: String,
     pub type_name: String,
-    #[data(ignore)]
-    pub default: Option<NixGuardedValue>,
-    #[data(ignore)]
-    pub example: Option<NixGuardedValue>,
+    pub default: Option<Arc<NixGuardedValue>>,
+    pub example: Option<Arc<NixGuardedValue>>,
+    /// The default evaluated in the context of a host,
+    /// only set when `default` is a `literalExpression`
+    pub evaluated_default: Option<Arc<NixGuardedValue>>,
+    /// Files of the modules declaring this option
+    pub declarations: im::Vector<String>,
+    /// Changes to this option in the nixpkgs being upgraded to, see `diff::OptionsDiff`
+    pub upgrade_notes: im::Vector<String>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub alias_kind: Option<NixAliasKind>,
+    pub alias_target: Option<String>,
+    pub removal_message: Option<String>,
+    /// Files of a config still defining this renamed or removed option
+    pub deprecated_definitions: im::Vector<String>,
+    /// Files of a config defining this option, see `run::get_definitions`
+    pub definitions: im::Vector<String>,
+}
+
+impl OptionDocumentation {
+    /// For attributes of a submodule that aren't declared as options,
+    /// but allowed by its `freeformType`
+    pub fn freeform(type_name: String) -> Self {
+        Self {
+            description:
+                "Not declared as an option, allowed by the `freeformType` of the submodule."
+                    .to_owned(),
+            type_name,
+            default: None,
+            example: None,
+            evaluated_default: None,
+            declarations: im::Vector::new(),
+            upgrade_notes: im::Vector::new(),
+            alias_kind: None,
+            alias_target: None,
+            removal_message: None,
+            deprecated_definitions: im::Vector::new(),
+            definitions: im::Vector::new(),
+        }
+    }
+
+    /// The default to compare values against, prefers the evaluated default
+    pub fn effective_default(&self) -> Option<&NixGuardedValue> {
+        self.evaluated_default
+            .as_deref()
+            .or(self.default.as_deref())
+    }
 }
 
 impl From<&NixOption> for OptionDocumentation {
@@ -55,8 +163,16 @@ impl From<&NixOption> for OptionDocumentation {
         Self {
             description: opt.description.clone(),
             type_name: opt.r#type.to_string(),
-            default: opt.default.clone(),
-            example: opt.example.clone(),
+            default: opt.default.clone().map(Arc::new),
+            example: opt.example.clone().map(Arc::new),
+            evaluated_default: None,
+            declarations: opt.declarations.iter().cloned().collect(),
+            upgrade_notes: im::Vector::new(),
+            alias_kind: opt.alias_kind,
+            alias_target: opt.alias_target.clone(),
+            removal_message: opt.removal_message.clone(),
+            deprecated_definitions: im::Vector::new(),
+            definitions: im::Vector::new(),
         }
     }
 }
@@ -69,13 +185,91 @@ impl std::fmt::Display for OptionDocumentation {
             self.description, self.type_name
         )?;
 
+        match (self.alias_kind, self.alias_target.as_ref()) {
+            (Some(NixAliasKind::Alias), Some(target)) => write!(f, "\n\nAlias of `{}`", target)?,
+            (Some(NixAliasKind::Renamed), Some(target)) => {
+                write!(f, "\n\nDeprecated: renamed to `{}`", target)?
+            }
+            (Some(NixAliasKind::Removed), _) => write!(
+                f,
+                "\n\nRemoved: {}",
+                self.removal_message
+                    .as_deref()
+                    .unwrap_or("no longer available")
+            )?,
+            _ => (),
+        }
+        if !self.deprecated_definitions.is_empty() {
+            write!(f, "\n\nWarning: still set in:")?;
+            for file in self.deprecated_definitions.iter() {
+                write!(f, "\n    {}", file)?;
+            }
+        }
+
         if let Some(ref def) = self.default {
             write!(f, "\n\nDefault: {}", def)?;
         }
+        if let Some(ref eval) = self.evaluated_default {
+            write!(f, "\n\nEvaluated default: {}", eval)?;
+        }
         if let Some(ref ex) = self.example {
             write!(f, "\n\nExample: {}", ex)?;
         }
+        for note in self.upgrade_notes.iter() {
+            write!(f, "\n\nUpgrade: {}", note)?;
+        }
+        if !self.definitions.is_empty() {
+            write!(f, "\n\nDefined in:")?;
+            for file in self.definitions.iter() {
+                write!(f, "\n    {}", file)?;
+            }
+        }
+        if !self.declarations.is_empty() {
+            write!(f, "\n\nDeclared in:")?;
+            for file in self.declarations.iter() {
+                write!(f, "\n    {}", file)?;
+            }
+        }
 
         write!(f, "")
     }
 }
+
+/// Summary of a package as shown in the detail pane
+struct PackageCard<'a>(&'a NixDerivation);
+
+impl std::fmt::Display for PackageCard<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let drv = self.0;
+        match (drv.pname.as_ref(), drv.version.as_ref()) {
+            (Some(pname), Some(version)) if !version.is_empty() => {
+                write!(f, "📦 {} {}", pname, version)?
+            }
+            _ => write!(f, "📦 {}", drv.name)?,
+        }
+
+        let meta = &drv.meta;
+        if let Some(ref description) = meta.description {
+            write!(f, "\n    {}", description)?;
+        }
+        if !meta.license.is_empty() {
+            write!(f, "\n    License: {}", meta.license.join(", "))?;
+        }
+        if let Some(ref homepage) = meta.homepage {
+            write!(f, "\n    Homepage: {}", homepage)?;
+        }
+        if !meta.maintainers.is_empty() {
+            write!(f, "\n    Maintainers: {}", meta.maintainers.join(", "))?;
+        }
+        if let Some(ref path) = drv.out_path {
+            let state = if drv.in_local_store {
+                "in the local store"
+            } else {
+                "not built"
+            };
+            write!(f, "\n    {} ({})", path, state)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 mod documentation;
+#[cfg(feature = "gui")]
 mod app_data;
+mod comparison;
 
 pub use documentation::{DisplayData, OptionDocumentation};
+#[cfg(feature = "gui")]
 pub use app_data::AppData;
+pub use comparison::DefaultComparison;
@@ -1,30 +1,281 @@
+use crate::bundle::Bundle;
 use crate::data::{AppData, DisplayData};
+use crate::diff::OptionsDiff;
+use crate::export::{export, ExportFormat};
+use crate::node::OptionNode;
+use crate::parse::{NixErrorDetails, NixPathSegment};
 use crate::tree_node::TreeOptionNode;
-use druid::{AppDelegate, Command, DelegateCtx, Env, Handled, Selector, Target};
+use druid::commands::{OPEN_FILE, SAVE_FILE_AS, SHOW_OPEN_PANEL, SHOW_SAVE_PANEL};
+use druid::{
+    AppDelegate, Command, DelegateCtx, Env, FileDialogOptions, Handled, Selector, SingleUse, Target,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
 use std::marker::PhantomData;
+use std::process::Command as Process;
+use std::sync::Arc;
 
 /// Open this option in the option editor
 pub const FOCUS_OPTION: Selector<DisplayData> = Selector::new("main.focus-option");
 
-pub struct Delegate<T>(PhantomData<T>);
+/// Ask for a location and export the documentation of the tree there
+pub const EXPORT_DOCUMENTATION: Selector<ExportFormat> = Selector::new("main.export-documentation");
+
+/// Ask for a location and save the bundle given to `Delegate::with_bundle` there
+pub const SAVE_SNAPSHOT: Selector = Selector::new("main.save-snapshot");
+
+/// Ask for a bundle and browse it in a new `nixos-config-browser open-snapshot <file>`
+pub const OPEN_SNAPSHOT: Selector = Selector::new("main.open-snapshot");
+
+/// Merge a tree with a newly evaluated config into the shown one,
+/// handled by `controller::LiveReload`
+pub const MERGE_CONFIG: Selector<SingleUse<OptionNode>> = Selector::new("main.merge-config");
+
+/// Replace the bundle saved by `SAVE_SNAPSHOT`, e.g. after the config was evaluated again.
+/// The errors explained so far are forgotten, they may have changed.
+pub const UPDATE_BUNDLE: Selector<Arc<Bundle>> = Selector::new("main.update-bundle");
+
+/// The details of an error in the config, sent from the thread explaining the errors of
+/// the selected option. Tagged with the `ErrorExplainer::generation` it was asked in.
+const ERROR_DETAILS: Selector<(usize, Vec<NixPathSegment>, Option<NixErrorDetails>)> =
+    Selector::new("main.error-details");
+
+/// Gets the details of the error at a path of the config, `None` if there are none
+pub type ExplainError = dyn Fn(&[NixPathSegment]) -> Option<NixErrorDetails> + Send + Sync;
+
+/// Explains the errors of the selected option in the background, as each needs its own
+/// evaluation of the host, see `Delegate::with_error_explainer`
+struct ErrorExplainer {
+    explain: Arc<ExplainError>,
+    /// `None` for errors that don't occur when evaluated on their own
+    details: HashMap<Vec<NixPathSegment>, Option<NixErrorDetails>>,
+    pending: HashSet<Vec<NixPathSegment>>,
+    /// Increased whenever the config is replaced
+    generation: usize,
+}
+
+impl ErrorExplainer {
+    /// Show the known details of the errors in `display`, explain the others
+    fn explain_errors(&mut self, ctx: &mut DelegateCtx, display: &mut DisplayData) {
+        let mut unknown = Vec::new();
+        for path in display.unexplained_errors() {
+            match self.details.get(&path) {
+                Some(Some(details)) => display.explain_error(&path, details),
+                Some(None) => (),
+                None => {
+                    if self.pending.insert(path.clone()) {
+                        unknown.push(path);
+                    }
+                }
+            }
+        }
+        if unknown.is_empty() {
+            return;
+        }
+
+        let (sink, explain, generation) = (
+            ctx.get_external_handle(),
+            self.explain.clone(),
+            self.generation,
+        );
+        std::thread::spawn(move || {
+            for path in unknown {
+                let details = explain(&path);
+                let payload = (generation, path, details);
+                if sink
+                    .submit_command(ERROR_DETAILS, payload, Target::Auto)
+                    .is_err()
+                {
+                    // The application quit
+                    return;
+                }
+            }
+        });
+    }
+
+    fn forget(&mut self) {
+        self.details.clear();
+        self.pending.clear();
+        self.generation += 1;
+    }
+}
+
+/// What the open save dialog is for
+#[derive(Clone, Copy)]
+enum PendingSave {
+    Export(ExportFormat),
+    Snapshot,
+}
+
+pub struct Delegate<T> {
+    pending_save: Option<PendingSave>,
+    /// The host shown, saved by `SAVE_SNAPSHOT`
+    bundle: Option<Arc<Bundle>>,
+    errors: Option<ErrorExplainer>,
+    phantom: PhantomData<T>,
+}
 
 impl<T> Delegate<T> {
     pub fn new() -> Self {
-        Self(PhantomData)
+        Self {
+            pending_save: None,
+            bundle: None,
+            errors: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Allow saving `bundle` as a snapshot, it should be redacted already
+    pub fn with_bundle(mut self, bundle: Arc<Bundle>) -> Self {
+        self.bundle = Some(bundle);
+        self
+    }
+
+    /// Explain the errors in the config of the options focused with `FOCUS_OPTION` with
+    /// `explain`, which is called on another thread. The details are kept until the
+    /// bundle is updated and are included in saved snapshots.
+    pub fn with_error_explainer(
+        mut self,
+        explain: impl Fn(&[NixPathSegment]) -> Option<NixErrorDetails> + Send + Sync + 'static,
+    ) -> Self {
+        self.errors = Some(ErrorExplainer {
+            explain: Arc::new(explain),
+            details: HashMap::new(),
+            pending: HashSet::new(),
+            generation: 0,
+        });
+        self
+    }
+
+    /// The bundle to save, with the errors explained so far
+    fn explained_bundle(&self) -> Option<Bundle> {
+        let mut bundle = Bundle::clone(self.bundle.as_ref()?);
+        if let Some(ref errors) = self.errors {
+            for (path, details) in errors.details.iter() {
+                if let Some(details) = details {
+                    bundle.config.explain_error(path, details);
+                }
+            }
+        }
+        Some(bundle)
     }
 }
 
 impl<T: TreeOptionNode> AppDelegate<AppData<T>> for Delegate<T> {
     fn command(
         &mut self,
-        _ctx: &mut DelegateCtx,
-        _target: Target,
+        ctx: &mut DelegateCtx,
+        target: Target,
         cmd: &Command,
         data: &mut AppData<T>,
         _env: &Env,
     ) -> Handled {
         if let Some(doc) = cmd.get(FOCUS_OPTION) {
             data.display = doc.clone();
+            if let Some(ref mut errors) = self.errors {
+                errors.explain_errors(ctx, &mut data.display);
+            }
+            Handled::Yes
+        } else if let Some((generation, path, details)) = cmd.get(ERROR_DETAILS) {
+            if let Some(ref mut errors) = self.errors {
+                // Otherwise it's about a config that was replaced since
+                if *generation == errors.generation {
+                    errors.pending.remove(path);
+                    if let Some(details) = details {
+                        data.display.explain_error(path, details);
+                    }
+                    errors.details.insert(path.clone(), details.clone());
+                }
+            }
+            Handled::Yes
+        } else if let Some(format) = cmd.get(EXPORT_DOCUMENTATION) {
+            self.pending_save = Some(PendingSave::Export(*format));
+            let options =
+                FileDialogOptions::new().title(format!("Export documentation as {format}"));
+            ctx.submit_command(SHOW_SAVE_PANEL.with(options).to(target));
+            Handled::Yes
+        } else if cmd.is(SAVE_SNAPSHOT) {
+            if self.bundle.is_some() {
+                self.pending_save = Some(PendingSave::Snapshot);
+                let options = FileDialogOptions::new().title("Save snapshot");
+                ctx.submit_command(SHOW_SAVE_PANEL.with(options).to(target));
+            }
+            Handled::Yes
+        } else if let Some(bundle) = cmd.get(UPDATE_BUNDLE) {
+            self.bundle = Some(bundle.clone());
+            if let Some(ref mut errors) = self.errors {
+                errors.forget();
+            }
+            Handled::Yes
+        } else if cmd.is(OPEN_SNAPSHOT) {
+            let options = FileDialogOptions::new().title("Open snapshot");
+            ctx.submit_command(SHOW_OPEN_PANEL.with(options).to(target));
+            Handled::Yes
+        } else if let Some(info) = cmd.get(SAVE_FILE_AS) {
+            match self.pending_save.take() {
+                Some(PendingSave::Export(format)) => {
+                    if let Err(e) = export(&data.tree, format, &info.path) {
+                        eprintln!("Exporting documentation failed: {e}");
+                    }
+                    Handled::Yes
+                }
+                Some(PendingSave::Snapshot) => {
+                    let bundle = self
+                        .explained_bundle()
+                        .expect("Saving a snapshot without one");
+                    match bundle.save(&info.path) {
+                        Ok(()) => eprintln!("Saved snapshot to `{}`.", info.path.display()),
+                        Err(e) => eprintln!("Saving snapshot failed: {e}"),
+                    }
+                    Handled::Yes
+                }
+                None => Handled::No,
+            }
+        } else if let Some(info) = cmd.get(OPEN_FILE) {
+            // A new process, the checks and title of this window are fixed
+            let opened = std::env::current_exe().and_then(|exe| {
+                Process::new(exe)
+                    .arg("open-snapshot")
+                    .arg(&info.path)
+                    .spawn()
+            });
+            if let Err(e) = opened {
+                eprintln!("Opening snapshot failed: {e}");
+            }
+            Handled::Yes
+        } else {
+            Handled::No
+        }
+    }
+}
+
+/// Delegate for the window showing `view::diff_report`, writes the Markdown export
+pub struct DiffDelegate {
+    diff: Arc<OptionsDiff>,
+}
+
+impl DiffDelegate {
+    pub fn new(diff: Arc<OptionsDiff>) -> Self {
+        Self { diff }
+    }
+}
+
+impl AppDelegate<()> for DiffDelegate {
+    fn command(
+        &mut self,
+        _ctx: &mut DelegateCtx,
+        _target: Target,
+        cmd: &Command,
+        _data: &mut (),
+        _env: &Env,
+    ) -> Handled {
+        if let Some(info) = cmd.get(SAVE_FILE_AS) {
+            let result = File::create(&info.path)
+                .and_then(|file| self.diff.write_markdown(&mut BufWriter::new(file)));
+            if let Err(e) = result {
+                eprintln!("Exporting options diff failed: {e}");
+            }
             Handled::Yes
         } else {
             Handled::No
@@ -0,0 +1,402 @@
+//! Compare the options of two nixpkgs versions, e.g. to plan a NixOS release upgrade.
+//!
+//! Options are compared by their path as shown in the documentation,
+//! submodule options are included with `<name>` and `*` in their path.
+
+use crate::parse::{NixAliasKind, NixGuardedValue, NixOption, NixSet, NixTypeValue, NixValue};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Result, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiffCategory {
+    Added,
+    Removed,
+    Renamed,
+    TypeChanged,
+    DefaultChanged,
+}
+
+impl DiffCategory {
+    pub const ALL: [DiffCategory; 5] = [
+        DiffCategory::Added,
+        DiffCategory::Removed,
+        DiffCategory::Renamed,
+        DiffCategory::TypeChanged,
+        DiffCategory::DefaultChanged,
+    ];
+}
+
+impl std::fmt::Display for DiffCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use DiffCategory::*;
+
+        match self {
+            Added => write!(f, "Added options"),
+            Removed => write!(f, "Removed options"),
+            Renamed => write!(f, "Renamed options"),
+            TypeChanged => write!(f, "Changed types"),
+            DefaultChanged => write!(f, "Changed defaults"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum OptionChange {
+    Added,
+    Removed,
+    Renamed { to: String },
+    TypeChanged { old: String, new: String },
+    DefaultChanged { old: String, new: String },
+}
+
+impl OptionChange {
+    pub fn category(&self) -> DiffCategory {
+        match self {
+            OptionChange::Added => DiffCategory::Added,
+            OptionChange::Removed => DiffCategory::Removed,
+            OptionChange::Renamed { .. } => DiffCategory::Renamed,
+            OptionChange::TypeChanged { .. } => DiffCategory::TypeChanged,
+            OptionChange::DefaultChanged { .. } => DiffCategory::DefaultChanged,
+        }
+    }
+}
+
+impl std::fmt::Display for OptionChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use OptionChange::*;
+
+        match self {
+            Added => write!(f, "added"),
+            Removed => write!(f, "removed"),
+            Renamed { to } => write!(f, "renamed to `{to}`"),
+            TypeChanged { old, new } => write!(f, "type changed from `{old}` to `{new}`"),
+            DefaultChanged { old, new } => {
+                write!(f, "default changed from `{old}` to `{new}`")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DiffEntry {
+    pub path: String,
+    pub change: OptionChange,
+}
+
+/// All changes between two option trees, sorted by path
+#[derive(Clone, Debug, Default)]
+pub struct OptionsDiff {
+    pub entries: Vec<DiffEntry>,
+}
+
+impl OptionsDiff {
+    pub fn new(old: &NixValue, new: &NixValue) -> Self {
+        let old = flatten_options(old);
+        let new = flatten_options(new);
+        let mut entries = Vec::new();
+
+        for (path, old_opt) in old.iter() {
+            let change = |change| DiffEntry {
+                path: path.clone(),
+                change,
+            };
+
+            match new.get(path) {
+                None => entries.push(change(OptionChange::Removed)),
+                Some(new_opt) => {
+                    if old_opt.alias_kind.is_none() {
+                        match (new_opt.alias_kind, &new_opt.alias_target) {
+                            (Some(NixAliasKind::Removed), _) => {
+                                entries.push(change(OptionChange::Removed));
+                                continue;
+                            }
+                            (Some(_), Some(to)) => {
+                                entries.push(change(OptionChange::Renamed { to: to.clone() }));
+                                continue;
+                            }
+                            _ => (),
+                        }
+                    }
+
+                    let (old_type, new_type) =
+                        (old_opt.r#type.to_string(), new_opt.r#type.to_string());
+                    if old_type != new_type {
+                        entries.push(change(OptionChange::TypeChanged {
+                            old: old_type,
+                            new: new_type,
+                        }));
+                    }
+                    if defaults_differ(old_opt.default.as_ref(), new_opt.default.as_ref()) {
+                        entries.push(change(OptionChange::DefaultChanged {
+                            old: show_default(old_opt.default.as_ref()),
+                            new: show_default(new_opt.default.as_ref()),
+                        }));
+                    }
+                }
+            }
+        }
+
+        for (path, new_opt) in new.iter() {
+            // A new alias or stub for a removed option is not an interesting addition
+            if !old.contains_key(path) && new_opt.alias_kind.is_none() {
+                entries.push(DiffEntry {
+                    path: path.clone(),
+                    change: OptionChange::Added,
+                });
+            }
+        }
+
+        entries.sort_by(|l, r| l.path.cmp(&r.path));
+        Self { entries }
+    }
+
+    /// Drop the removals of options that no NixOS module of nixpkgs declares, e.g. those of
+    /// a host's own modules, which are missing when comparing with plain nixpkgs
+    pub fn without_foreign_removals(mut self, old: &NixValue) -> Self {
+        let old = flatten_options(old);
+        self.entries.retain(|entry| {
+            !matches!(entry.change, OptionChange::Removed)
+                || old
+                    .get(&entry.path)
+                    .is_none_or(|opt| declared_in_nixpkgs(opt))
+        });
+        self
+    }
+
+    pub fn in_category(&self, category: DiffCategory) -> impl Iterator<Item = &DiffEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.change.category() == category)
+    }
+
+    /// Descriptions of all changes, indexed by option path
+    pub fn notes_by_path(&self) -> HashMap<String, Vec<String>> {
+        let mut notes: HashMap<String, Vec<String>> = HashMap::new();
+        for entry in self.entries.iter() {
+            notes
+                .entry(entry.path.clone())
+                .or_default()
+                .push(entry.change.to_string());
+        }
+        notes
+    }
+
+    pub fn write_markdown<W: Write>(&self, out: &mut W) -> Result<()> {
+        writeln!(out, "# NixOS options diff")?;
+
+        for category in DiffCategory::ALL {
+            let entries: Vec<&DiffEntry> = self.in_category(category).collect();
+            writeln!(out, "\n## {} ({})\n", category, entries.len())?;
+
+            for entry in entries {
+                match entry.change {
+                    OptionChange::Added | OptionChange::Removed => {
+                        writeln!(out, "- `{}`", entry.path)?
+                    }
+                    ref change => writeln!(out, "- `{}`: {}", entry.path, change)?,
+                }
+            }
+        }
+
+        out.flush()
+    }
+}
+
+/// Map all options in the tree (including submodule options) by their path
+pub fn flatten_options(root: &NixValue) -> BTreeMap<String, &NixOption> {
+    let mut out = BTreeMap::new();
+    match root {
+        NixValue::Set(set) => flatten_set("", set, &mut out),
+        NixValue::Option(opt) => {
+            out.insert(String::new(), opt);
+        }
+    }
+    out
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}.{name}")
+    }
+}
+
+fn flatten_set<'a>(prefix: &str, set: &'a NixSet, out: &mut BTreeMap<String, &'a NixOption>) {
+    for (name, val) in set.iter() {
+        let path = join(prefix, name);
+        match **val {
+            NixValue::Option(ref opt) => {
+                flatten_type(&path, &opt.r#type, out);
+                out.insert(path, opt);
+            }
+            NixValue::Set(ref set) => flatten_set(&path, set, out),
+        }
+    }
+}
+
+fn flatten_type<'a>(path: &str, t: &'a NixTypeValue, out: &mut BTreeMap<String, &'a NixOption>) {
+    match t {
+        NixTypeValue::Submodule(sub) => flatten_set(path, &sub.options, out),
+        NixTypeValue::Type(t) => {
+            for (key, nested) in t.nestedTypes.iter() {
+                match (t.name.as_ref(), key.as_ref()) {
+                    ("attrsOf" | "lazyAttrsOf", "elemType") => {
+                        flatten_type(&join(path, "<name>"), nested, out)
+                    }
+                    ("listOf", "elemType") => flatten_type(&join(path, "*"), nested, out),
+                    _ => flatten_type(path, nested, out),
+                }
+            }
+        }
+        // The options of a recursive type are already listed where it's first used
+        NixTypeValue::Reference(_) => (),
+    }
+}
+
+/// Options without declarations (e.g. from snapshots of older versions) are assumed to be
+/// in nixpkgs
+fn declared_in_nixpkgs(opt: &NixOption) -> bool {
+    opt.declarations.is_empty()
+        || opt
+            .declarations
+            .iter()
+            .any(|file| file.contains("/nixos/modules/"))
+}
+
+fn defaults_differ(old: Option<&NixGuardedValue>, new: Option<&NixGuardedValue>) -> bool {
+    use NixGuardedValue::LiteralExpression;
+
+    match (old, new) {
+        (None, None) => false,
+        (Some(LiteralExpression(l)), Some(LiteralExpression(r))) => l.text.trim() != r.text.trim(),
+        // Functions and the like can't be compared, don't report them
+        (Some(l), Some(r)) => l.compare(r) == Some(false),
+        _ => true,
+    }
+}
+
+fn show_default(val: Option<&NixGuardedValue>) -> String {
+    match val {
+        Some(NixGuardedValue::LiteralExpression(e)) => e.text.trim().to_string(),
+        // Use the compact `Debug` output, `Display` spans multiple lines
+        Some(v) => format!("{v:?}"),
+        None => "none".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn option(option_type: &str, declarations: &[&str]) -> serde_json::Value {
+        json!({
+            "_option": true,
+            "description": "",
+            "type": {
+                "_type": true,
+                "name": option_type,
+                "description": option_type,
+                "functorName": option_type,
+                "nestedTypes": {},
+            },
+            "declarations": declarations,
+        })
+    }
+
+    fn with(
+        mut option: serde_json::Value,
+        key: &str,
+        value: serde_json::Value,
+    ) -> serde_json::Value {
+        option[key] = value;
+        option
+    }
+
+    fn options(value: serde_json::Value) -> NixValue {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn foreign_removals() {
+        let nixpkgs = "/nix/store/x-source/nixos/modules/services/web-servers/nginx/default.nix";
+        let old = options(json!({
+            "services": {
+                "nginx": { "enable": option("bool", &[nixpkgs]) },
+                "myApp": { "enable": option("bool", &["/nix/store/y-source/modules/app.nix"]) },
+            },
+        }));
+        let new = options(json!({}));
+
+        let diff = OptionsDiff::new(&old, &new);
+        assert_eq!(diff.entries.len(), 2);
+        let diff = diff.without_foreign_removals(&old);
+        let paths: Vec<&str> = diff.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["services.nginx.enable"]);
+    }
+
+    #[test]
+    fn changes() {
+        let literal = |text: &str| json!({ "_type": "literalExpression", "text": text });
+        let old = options(json!({
+            "kept": with(option("int", &[]), "default", json!(1)),
+            "port": with(option("int", &[]), "default", json!(80)),
+            "package": with(option("package", &[]), "default", literal("pkgs.hello")),
+            "user": option("str", &[]),
+            "old": option("bool", &[]),
+            "gone": option("bool", &[]),
+        }));
+        let new = options(json!({
+            "kept": with(option("int", &[]), "default", json!(1.0)),
+            "port": with(option("int", &[]), "default", json!(8080)),
+            "package": with(option("package", &[]), "default", literal(" pkgs.hello\n")),
+            "user": option("nullOr", &[]),
+            "old": with(
+                with(option("bool", &[]), "aliasKind", json!("renamed")),
+                "aliasTarget",
+                json!("new"),
+            ),
+            "gone": with(option("bool", &[]), "aliasKind", json!("removed")),
+            "new": option("bool", &[]),
+        }));
+
+        let diff = OptionsDiff::new(&old, &new);
+        let changes: Vec<String> = diff
+            .entries
+            .iter()
+            .map(|e| format!("{}: {}", e.path, e.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "gone: removed",
+                "new: added",
+                "old: renamed to `new`",
+                "port: default changed from `80` to `8080`",
+                "user: type changed from `str` to `nullOr`",
+            ]
+        );
+        assert_eq!(diff.in_category(DiffCategory::Added).count(), 1);
+        assert_eq!(diff.notes_by_path()["gone"], ["removed"]);
+    }
+
+    #[test]
+    fn submodule_paths() {
+        let sub = json!({
+            "_submodule": true,
+            "options": { "port": option("int", &[]) },
+        });
+        let mut attrs_of = option("attrsOf", &[]);
+        attrs_of["type"]["nestedTypes"] = json!({ "elemType": sub.clone() });
+        let mut list_of = option("listOf", &[]);
+        list_of["type"]["nestedTypes"] = json!({ "elemType": sub });
+
+        let old = options(json!({ "hosts": attrs_of, "rules": list_of }));
+        let paths: Vec<String> = flatten_options(&old).into_keys().collect();
+        assert_eq!(
+            paths,
+            ["hosts", "hosts.<name>.port", "rules", "rules.*.port"]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+//! Export option documentation to static files.
+//!
+//! Works on any tree implementing `OptionTreeNode` and keeps the option paths
+//! as shown in the option browser, i.e. including `<name>` and `*` for submodules.
+
+use crate::data::OptionDocumentation;
+use crate::parse::NixGuardedValue;
+use crate::tree_node::OptionTreeNode;
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A directory with an `index.html` and a search index
+    Html,
+    /// A single Markdown file
+    Markdown,
+    /// A roff man page like `configuration.nix(5)`
+    Man,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(ExportFormat::Html),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "man" | "roff" => Ok(ExportFormat::Man),
+            other => Err(format!(
+                "Unknown export format `{other}`, expected one of `html`, `markdown` or `man`"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Html => write!(f, "HTML"),
+            ExportFormat::Markdown => write!(f, "Markdown"),
+            ExportFormat::Man => write!(f, "man page"),
+        }
+    }
+}
+
+/// A single documented option together with its full path
+#[derive(Clone, Debug)]
+pub struct ExportedOption {
+    pub path: Vec<String>,
+    pub documentation: OptionDocumentation,
+}
+
+impl ExportedOption {
+    pub fn name(&self) -> String {
+        self.path.join(".")
+    }
+
+    /// Anchor usable in HTML, made from the option path. Other characters are escaped
+    /// as `_` and their hex code, `_` itself as `__`, so no two options share one.
+    fn anchor(&self) -> String {
+        let mut anchor = String::new();
+        for b in self.name().bytes() {
+            match b {
+                b'_' => anchor.push_str("__"),
+                b'-' | b'.' => anchor.push(b as char),
+                _ if b.is_ascii_alphanumeric() => anchor.push(b as char),
+                _ => anchor.push_str(&format!("_{b:02x}")),
+            }
+        }
+        anchor
+    }
+}
+
+/// Collect all documented options below `root` (excluding `root` itself) in tree order.
+pub fn collect_options<T: OptionTreeNode>(root: &T) -> Vec<ExportedOption> {
+    fn visit<T: OptionTreeNode>(node: &T, prefix: &[String], out: &mut Vec<ExportedOption>) {
+        for i in 0..node.visible_children_count() {
+            let child = node.visible_child(i);
+            let mut path = prefix.to_vec();
+            path.push(child.get_path_segment());
+
+            if let Some(doc) = child.focused_display_data().get_documentation() {
+                out.push(ExportedOption {
+                    path: path.clone(),
+                    documentation: doc.clone(),
+                });
+            }
+            visit(child, &path, out);
+        }
+    }
+
+    let mut out = Vec::new();
+    visit(root, &[], &mut out);
+    out
+}
+
+/// Export the documentation of all options below `root` to `path`.
+/// For `ExportFormat::Html` `path` is a directory which will be created if needed.
+pub fn export<T: OptionTreeNode>(root: &T, format: ExportFormat, path: &Path) -> Result<()> {
+    let options = collect_options(root);
+
+    match format {
+        ExportFormat::Html => write_html(&options, path),
+        ExportFormat::Markdown => {
+            write_markdown(&options, &mut BufWriter::new(File::create(path)?))
+        }
+        ExportFormat::Man => write_man(&options, &mut BufWriter::new(File::create(path)?)),
+    }
+}
+
+/// Source text of a value, `literalExpression`s are shown without decoration
+fn value_source(val: &NixGuardedValue) -> String {
+    match val {
+        NixGuardedValue::LiteralExpression(e) => e.text.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub fn write_markdown<W: Write>(options: &[ExportedOption], out: &mut W) -> Result<()> {
+    writeln!(out, "# NixOS options")?;
+
+    for opt in options {
+        let doc = &opt.documentation;
+        writeln!(out, "\n## `{}`\n", opt.name())?;
+        writeln!(out, "{}\n", doc.description.trim())?;
+        writeln!(out, "*Type:* {}", doc.type_name)?;
+
+        if let Some(ref def) = doc.default {
+            writeln!(out, "\n*Default:*\n```nix\n{}\n```", value_source(def))?;
+        }
+        if let Some(ref ex) = doc.example {
+            writeln!(out, "\n*Example:*\n```nix\n{}\n```", value_source(ex))?;
+        }
+    }
+
+    out.flush()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_SEARCH_SCRIPT: &str = r#"
+function search(query) {
+  query = query.toLowerCase();
+  for (const entry of SEARCH_INDEX) {
+    const matches = entry.name.toLowerCase().includes(query)
+      || entry.description.toLowerCase().includes(query);
+    document.getElementById(entry.anchor).hidden = !matches;
+  }
+}
+"#;
+
+pub fn write_html(options: &[ExportedOption], dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    // The search index is a script instead of JSON so it also loads from `file://`
+    let index: Vec<serde_json::Value> = options
+        .iter()
+        .map(|opt| {
+            serde_json::json!({
+                "name": opt.name(),
+                "anchor": opt.anchor(),
+                "description": opt.documentation.description,
+                "type": opt.documentation.type_name,
+            })
+        })
+        .collect();
+    let mut index_file = BufWriter::new(File::create(dir.join("search-index.js"))?);
+    write!(
+        index_file,
+        "const SEARCH_INDEX = {};\n{}",
+        serde_json::Value::Array(index),
+        HTML_SEARCH_SCRIPT
+    )?;
+    index_file.flush()?;
+
+    let mut out = BufWriter::new(File::create(dir.join("index.html"))?);
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>NixOS options</title>\n<script src=\"search-index.js\"></script>\n</head>\n<body>\n\
+         <h1>NixOS options</h1>\n\
+         <input type=\"search\" placeholder=\"Search options\" oninput=\"search(this.value)\">"
+    )?;
+
+    for opt in options {
+        let doc = &opt.documentation;
+        writeln!(out, "<section id=\"{}\">", opt.anchor())?;
+        writeln!(
+            out,
+            "<h2><a href=\"#{0}\"><code>{1}</code></a></h2>",
+            opt.anchor(),
+            escape_html(&opt.name())
+        )?;
+        writeln!(out, "<p>{}</p>", escape_html(doc.description.trim()))?;
+        writeln!(out, "<p><em>Type:</em> {}</p>", escape_html(&doc.type_name))?;
+
+        if let Some(ref def) = doc.default {
+            writeln!(
+                out,
+                "<p><em>Default:</em></p><pre><code>{}</code></pre>",
+                escape_html(&value_source(def))
+            )?;
+        }
+        if let Some(ref ex) = doc.example {
+            writeln!(
+                out,
+                "<p><em>Example:</em></p><pre><code>{}</code></pre>",
+                escape_html(&value_source(ex))
+            )?;
+        }
+        writeln!(out, "</section>")?;
+    }
+
+    writeln!(out, "</body>\n</html>")?;
+    out.flush()
+}
+
+/// Escape text for roff, also makes sure no line starts with a control character
+fn escape_roff(s: &str) -> String {
+    s.replace('\\', "\\e")
+        .lines()
+        .map(|line| {
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{line}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn write_man<W: Write>(options: &[ExportedOption], out: &mut W) -> Result<()> {
+    writeln!(
+        out,
+        ".TH \"CONFIGURATION\\&.NIX\" \"5\" \"\" \"nixos-druid\""
+    )?;
+    writeln!(
+        out,
+        ".SH \"NAME\"\nconfiguration.nix \\- NixOS system configuration specification"
+    )?;
+    writeln!(out, ".SH \"OPTIONS\"")?;
+
+    for opt in options {
+        let doc = &opt.documentation;
+        writeln!(out, ".PP\n\\fB{}\\fR\n.RS 4", escape_roff(&opt.name()))?;
+        writeln!(out, "{}", escape_roff(doc.description.trim()))?;
+        writeln!(out, ".sp\n\\fIType:\\fR\n{}", escape_roff(&doc.type_name))?;
+
+        if let Some(ref def) = doc.default {
+            writeln!(
+                out,
+                ".sp\n\\fIDefault:\\fR\n.nf\n{}\n.fi",
+                escape_roff(&value_source(def))
+            )?;
+        }
+        if let Some(ref ex) = doc.example {
+            writeln!(
+                out,
+                ".sp\n\\fIExample:\\fR\n.nf\n{}\n.fi",
+                escape_roff(&value_source(ex))
+            )?;
+        }
+        writeln!(out, ".RE")?;
+    }
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::OptionNode;
+    use serde_json::json;
+
+    fn exported(path: &[&str]) -> ExportedOption {
+        ExportedOption {
+            path: path.iter().map(|s| s.to_string()).collect(),
+            documentation: OptionDocumentation::freeform("string".to_string()),
+        }
+    }
+
+    fn option(option_type: serde_json::Value) -> serde_json::Value {
+        json!({ "_option": true, "description": "", "type": option_type })
+    }
+
+    fn nix_type(name: &str, nested_types: serde_json::Value) -> serde_json::Value {
+        json!({
+            "_type": true,
+            "name": name,
+            "description": name,
+            "functorName": name,
+            "nestedTypes": nested_types,
+        })
+    }
+
+    #[test]
+    fn submodule_paths() {
+        let sub = json!({
+            "_submodule": true,
+            "options": { "port": option(nix_type("int", json!({}))) },
+        });
+        let options = json!({
+            "hosts": option(nix_type("listOf", json!({ "elemType": sub }))),
+            "users": option(nix_type("attrsOf", json!({ "elemType": sub }))),
+        });
+        let root = OptionNode::new("root".to_string(), serde_json::from_value(options).unwrap());
+
+        let names: Vec<String> = collect_options(&root)
+            .iter()
+            .map(ExportedOption::name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "hosts.*",
+                "hosts.*.port",
+                "users.<name>",
+                "users.<name>.port"
+            ]
+        );
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(
+            exported(&["users", "<name>", "shell"]).anchor(),
+            "users._3cname_3e.shell"
+        );
+        assert_eq!(exported(&["hosts", "*"]).anchor(), "hosts._2a");
+        assert_eq!(exported(&["a_b"]).anchor(), "a__b");
+        assert_ne!(
+            exported(&["foo", "<name>"]).anchor(),
+            exported(&["foo", "_name_"]).anchor()
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape_html(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(
+            escape_roff("a\\b\n.TH x\n'quote\nplain"),
+            "a\\eb\n\\&.TH x\n\\&'quote\nplain"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Export config values as JSON, YAML or TOML and import such files as a NixOS module.
+//!
+//! Values that have no equivalent in these formats are written as objects with a marker
+//! key, the same ones `utilities.nix` uses to get them out of Nix:
+//!
+//! - functions: `{ "_function": true }`
+//! - packages: `{ "_derivation": true, "name": ..., "pname": ..., "version": ..., "outPath": ... }`
+//! - values that failed to evaluate:
+//!   `{ "_error": true, "path": [...], "message": ..., "position": ... }`
+//! - `literalExpression`s: `{ "_type": "literalExpression", "text": ... }`
+//! - option types: `{ "_type": true }`
+//! - `null`, only in TOML which has no null: `{ "_null": true }`
+//!
+//! On import `literalExpression`s are inserted as Nix code and `_null` becomes `null`,
+//! the other markers can't be turned back into Nix values and are reported as errors.
+
+use crate::parse::NixGuardedValue;
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// The format of the value types of `pkgs.formats`, e.g. `JSON value`
+    pub fn from_type_description(description: &str) -> Option<Self> {
+        match description {
+            "JSON value" => Some(ConfigFormat::Json),
+            "YAML value" | "Yaml value" => Some(ConfigFormat::Yaml),
+            "TOML value" => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the extension of a file name
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        name.rsplit_once('.').and_then(|(_, ext)| ext.parse().ok())
+    }
+}
+
+impl std::str::FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ConfigFormat::Json),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            "toml" => Ok(ConfigFormat::Toml),
+            other => Err(format!(
+                "Unknown config format `{other}`, expected one of `json`, `yaml` or `toml`"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "JSON"),
+            ConfigFormat::Yaml => write!(f, "YAML"),
+            ConfigFormat::Toml => write!(f, "TOML"),
+        }
+    }
+}
+
+/// The value at `path` (attribute names) in `root`, `None` if it doesn't exist
+pub fn subtree<'a>(root: &'a NixGuardedValue, path: &[&str]) -> Option<&'a NixGuardedValue> {
+    path.iter().try_fold(root, |val, name| match val {
+        NixGuardedValue::Attrs(attrs) => attrs.get(*name).map(|v| &**v),
+        _ => None,
+    })
+}
+
+/// Convert a value to JSON, see the module documentation for the representation
+/// of values JSON has no equivalent for
+pub fn to_json(val: &NixGuardedValue) -> Value {
+    use NixGuardedValue::*;
+
+    match val {
+        Function(_) => json!({ "_function": true }),
+        Derivation(d) => json!({
+            "_derivation": true,
+            "name": d.name,
+            "pname": d.pname,
+            "version": d.version,
+            "outPath": d.out_path,
+        }),
+        Error(e) => {
+            let details = e.details.as_ref();
+            json!({
+                "_error": true,
+                "path": e.path,
+                "message": details.map(|d| &d.message),
+                "position": details.and_then(|d| d.position.as_ref()),
+            })
+        }
+        LiteralExpression(e) => json!({ "_type": "literalExpression", "text": e.text }),
+        OptionType(_) => json!({ "_type": true }),
+
+        Attrs(attrs) => Value::Object(attrs.iter().map(|(k, v)| (k.clone(), to_json(v))).collect()),
+        List(list) => Value::Array(list.iter().map(|v| to_json(v)).collect()),
+        String(s) => json!(s),
+        Number(n) => json!(n),
+        Float(n) => json!(n),
+        Bool(b) => json!(b),
+        Null(_) => Value::Null,
+    }
+}
+
+pub fn export_value(val: &NixGuardedValue, format: ConfigFormat) -> Result<String, String> {
+    let json = to_json(val);
+    match format {
+        ConfigFormat::Json => serde_json::to_string_pretty(&json).map_err(|e| e.to_string()),
+        ConfigFormat::Yaml => serde_yaml::to_string(&json).map_err(|e| e.to_string()),
+        ConfigFormat::Toml => {
+            if !json.is_object() {
+                return Err("TOML can only hold attribute sets, export a parent instead".into());
+            }
+            let toml = toml::Value::try_from(encode_nulls(json)).map_err(|e| e.to_string())?;
+            toml::to_string_pretty(&toml).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Read a file's content, `_null` markers are turned back into `null`
+pub fn import_value(content: &str, format: ConfigFormat) -> Result<Value, String> {
+    let value: Value = match format {
+        ConfigFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string())?,
+        ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string())?,
+        ConfigFormat::Toml => toml::from_str(content).map_err(|e| e.to_string())?,
+    };
+    Ok(decode_nulls(value))
+}
+
+fn encode_nulls(val: Value) -> Value {
+    match val {
+        Value::Null => json!({ "_null": true }),
+        Value::Object(map) => {
+            Value::Object(map.into_iter().map(|(k, v)| (k, encode_nulls(v))).collect())
+        }
+        Value::Array(list) => Value::Array(list.into_iter().map(encode_nulls).collect()),
+        other => other,
+    }
+}
+
+fn decode_nulls(val: Value) -> Value {
+    match val {
+        Value::Object(map) if is_marker(&map, "_null") => Value::Null,
+        Value::Object(map) => {
+            Value::Object(map.into_iter().map(|(k, v)| (k, decode_nulls(v))).collect())
+        }
+        Value::Array(list) => Value::Array(list.into_iter().map(decode_nulls).collect()),
+        other => other,
+    }
+}
+
+fn is_marker(map: &Map<String, Value>, key: &str) -> bool {
+    map.get(key) == Some(&Value::Bool(true))
+}
+
+/// A NixOS module setting the option at `path` to `value`.
+/// Fails with the paths of all values that can't be written as Nix.
+pub fn to_nix_module(path: &[&str], value: &Value) -> Result<String, Vec<String>> {
+    let mut errors = Vec::new();
+    let mut nix = String::new();
+    write_nix(value, &path.join("."), 1, &mut nix, &mut errors);
+
+    if errors.is_empty() {
+        let path: Vec<String> = path.iter().map(|s| nix_attr_name(s)).collect();
+        Ok(format!("{{\n  {} = {};\n}}\n", path.join("."), nix))
+    } else {
+        Err(errors)
+    }
+}
+
+fn write_nix(val: &Value, path: &str, depth: usize, out: &mut String, errors: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    match val {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Number(n) => out.push_str(&nix_number(n)),
+        Value::String(s) => out.push_str(&nix_string(s)),
+        Value::Array(list) => {
+            out.push('[');
+            for (i, v) in list.iter().enumerate() {
+                out.push_str(&format!("\n{indent}  "));
+                // Lists are separated by spaces, wrap everything that isn't atomic.
+                // Nix has no negative literals, `-1` is `1` negated.
+                let needs_parens = match v {
+                    Value::Object(m) => m.contains_key("_type"),
+                    Value::Number(n) => n.as_f64().is_some_and(f64::is_sign_negative),
+                    _ => false,
+                };
+                if needs_parens {
+                    out.push('(');
+                }
+                write_nix(v, &format!("{path}[{i}]"), depth + 1, out, errors);
+                if needs_parens {
+                    out.push(')');
+                }
+            }
+            out.push_str(&format!("\n{indent}]"));
+        }
+        Value::Object(map) => {
+            if map.get("_type") == Some(&json!("literalExpression")) {
+                match map.get("text").and_then(Value::as_str) {
+                    Some(text) => out.push_str(text.trim()),
+                    None => errors.push(format!("`{path}`: `literalExpression` without text")),
+                }
+                return;
+            }
+            for marker in ["_function", "_derivation", "_error", "_type"] {
+                if map.contains_key(marker) {
+                    errors.push(format!("`{path}`: a `{marker}` value can't be imported"));
+                    return;
+                }
+            }
+
+            out.push('{');
+            for (k, v) in map.iter() {
+                out.push_str(&format!("\n{indent}  {} = ", nix_attr_name(k)));
+                write_nix(v, &format!("{path}.{k}"), depth + 1, out, errors);
+                out.push(';');
+            }
+            out.push_str(&format!("\n{indent}}}"));
+        }
+    }
+}
+
+/// Floats need a `.` to be read as floats, also with an exponent like `1e100`
+fn nix_number(n: &serde_json::Number) -> String {
+    // `Display` of `f64` writes every digit of large and small floats
+    let number = match n.as_f64() {
+        Some(f) if n.is_f64() => format!("{f:?}"),
+        _ => return n.to_string(),
+    };
+    if number.contains('.') {
+        return number;
+    }
+    match number.find('e') {
+        Some(exponent) => format!("{}.0{}", &number[..exponent], &number[exponent..]),
+        None => format!("{number}.0"),
+    }
+}
+
+fn nix_string(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("${", "\\${")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t")
+    )
+}
+
+/// Attribute names that aren't plain identifiers need quotes
+fn nix_attr_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''));
+    let is_keyword = matches!(
+        name,
+        "if" | "then" | "else" | "assert" | "with" | "let" | "in" | "rec" | "inherit" | "or"
+    );
+
+    if is_identifier && !is_keyword {
+        name.to_string()
+    } else {
+        nix_string(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nix_module() {
+        let value = json!({
+            "port": 8080,
+            "ratio": 1e100,
+            "small": -2.5e-7,
+            "offsets": [-1, 2, -0.5, { "_type": "literalExpression", "text": "pkgs.hello" }],
+            "name": "a \"b\" ${c}",
+            "my-attr": null,
+            "if": true,
+        });
+        let nix = to_nix_module(&["services", "my.app"], &value).unwrap();
+        let expected = r#"{
+  services."my.app" = {
+    "if" = true;
+    my-attr = null;
+    name = "a \"b\" \${c}";
+    offsets = [
+      (-1)
+      2
+      (-0.5)
+      (pkgs.hello)
+    ];
+    port = 8080;
+    ratio = 1.0e100;
+    small = -2.5e-7;
+  };
+}
+"#;
+        assert_eq!(nix, expected);
+    }
+
+    #[test]
+    fn nix_module_errors() {
+        let value = json!({ "f": { "_function": true }, "l": [{ "_error": true }] });
+        let errors = to_nix_module(&["x"], &value).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "`x.f`: a `_function` value can't be imported",
+                "`x.l[0]`: a `_error` value can't be imported",
+            ]
+        );
+    }
+
+    #[test]
+    fn attr_names() {
+        for (name, nix) in [
+            ("enable", "enable"),
+            ("my-attr", "my-attr"),
+            ("_x'", "_x'"),
+            ("resolv.conf", "\"resolv.conf\""),
+            ("1password", "\"1password\""),
+            ("inherit", "\"inherit\""),
+            ("", "\"\""),
+        ] {
+            assert_eq!(nix_attr_name(name), nix);
+        }
+    }
+}
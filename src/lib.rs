@@ -1,7 +1,23 @@
-pub mod controller;
+// The option tree, running nix and everything derived from the options
+pub mod bundle;
+pub mod checks;
+pub mod closure;
 pub mod data;
-pub mod delegate;
+pub mod diff;
+pub mod export;
+pub mod interchange;
+pub mod node;
 pub mod parse;
 pub mod run;
+pub mod schema;
 pub mod tree_node;
+#[cfg(feature = "watch")]
+pub mod watch;
+
+// Widgets and plumbing of the druid binaries
+#[cfg(feature = "gui")]
+pub mod controller;
+#[cfg(feature = "gui")]
+pub mod delegate;
+#[cfg(feature = "gui")]
 pub mod view;
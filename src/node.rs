@@ -0,0 +1,1826 @@
+//! The tree of options shown by the binaries, with the config attached to it.
+//!
+//! Build it from the exported options with `OptionNode::new`, attach a config with
+//! `add_config` and walk it with `find` or the `OptionTreeNode` implementation:
+//!
+//! ```no_run
+//! use nixos_druid::node::OptionNode;
+//! use nixos_druid::run::{get_config, get_options_from, OptionSource};
+//!
+//! let source = OptionSource::NixosConfiguration {
+//!     flake: "/etc/nixos".to_string(),
+//!     hostname: "laptop".to_string(),
+//! };
+//! let mut tree = OptionNode::new("NixOS".to_string(), get_options_from(&source).unwrap());
+//! tree.add_config(Some(get_config("/etc/nixos", "laptop").unwrap()));
+//! let node = tree.find("networking.hostName").unwrap();
+//! println!("{:?}", node.value);
+//! ```
+
+use crate::data::DisplayData;
+use crate::data::{DefaultComparison, OptionDocumentation};
+use crate::interchange::ConfigFormat;
+use crate::parse::{NixAliasKind, NixGuardedValue, NixTypeGraph, NixTypeValue, NixValue};
+use crate::tree_node::OptionTreeNode;
+#[cfg(feature = "gui")]
+use druid::{Data, Lens};
+#[cfg(feature = "gui")]
+use druid_widget_nursery::TreeNode;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// How the `OptionTreeNode` implementation shows the extra child of `attrsOf`/`listOf` submodules
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum ExtraChildDisplay {
+    /// In place of the children, for browsing documentation where there are no children
+    Inline,
+    /// Before the children, which are the elements of the config
+    First,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct OptionNode {
+    pub name: String,
+    /// Shared by all instances of a submodule, only copied when changed
+    pub documentation: Option<Arc<OptionDocumentation>>,
+    pub option_type: Option<OptionType>,
+    /// Attached with `add_config`, a changed value is a new `Arc`
+    pub value: Option<Arc<NixGuardedValue>>,
+    pub children: im::Vector<OptionNode>,
+    /// An extra child for options where children are of type `Submodule`.
+    /// This extra child gives a way to view documentation when there are no
+    /// real children.
+    pub extra_child: Option<Arc<OptionNode>>,
+    /// The type of config attributes that aren't declared as options, see `add_freeform_config`.
+    /// Like the option type it never changes after creation.
+    #[cfg_attr(feature = "gui", data(ignore))]
+    pub freeform_type: Option<Box<OptionType>>,
+    /// Whether this is an attribute allowed by the `freeformType` of the parent
+    /// instead of a declared option
+    pub freeform: bool,
+    pub expanded: bool,
+    /// Whether the value of this node or any of its children contains an error
+    pub has_error: bool,
+    /// Whether the value of this node or one below it changed in the last `merge_config`
+    pub changed: bool,
+    /// Only show children with errors, see `set_only_errors`
+    pub only_errors: bool,
+    /// See `set_extra_child_display`. Every node using it keeps a copy, as the
+    /// `TreeNode` implementation only sees the node it's called on.
+    pub extra_child_display: ExtraChildDisplay,
+}
+
+impl OptionNode {
+    fn new_option(name: String, option_type: OptionType, doc: OptionDocumentation) -> Self {
+        let mut node = Self::new_set(name, im::Vector::new());
+        node.documentation = Some(Arc::new(doc));
+        node.set_option_type(option_type);
+        node
+    }
+
+    /// Set the type of an option along with the children it implies
+    fn set_option_type(&mut self, option_type: OptionType) {
+        let (children, freeform_type, extra_child) = Self::structure(&option_type);
+
+        self.option_type = Some(option_type);
+        self.children = children;
+        self.extra_child = extra_child;
+        self.freeform_type = freeform_type;
+    }
+
+    /// The children and `freeformType` implied by a type: the options of a submodule,
+    /// also in a `nullOr` or `either`. `attrsOf` and `listOf` containing submodules get an
+    /// extra child documenting their elements instead.
+    fn structure(
+        option_type: &OptionType,
+    ) -> (
+        im::Vector<OptionNode>,
+        Option<Box<OptionType>>,
+        Option<Arc<OptionNode>>,
+    ) {
+        use im::Vector;
+        use OptionType::*;
+
+        match option_type {
+            Submodule(sub, freeform) => (sub.to_owned(), freeform.to_owned(), None),
+            NullOr(t) => Self::structure(t),
+            Either(l, r) => Self::structure(if l.contains_submodule() { l } else { r }),
+            AttrsOf(t) if t.contains_submodule() => {
+                let extra_child = OptionNode::new_element("<name>".to_string(), t, None);
+                (Vector::new(), None, Some(Arc::new(extra_child)))
+            }
+            ListOf(t) if t.contains_submodule() => {
+                let extra_child = OptionNode::new_element("*".to_string(), t, None);
+                (Vector::new(), None, Some(Arc::new(extra_child)))
+            }
+            _ => (Vector::new(), None, None),
+        }
+    }
+
+    /// Expand a recursive type one level so this node gets the options it refers to.
+    /// Expanding all of them up front would never end, this is done when the parent
+    /// is expanded or a value is attached.
+    fn unfold(&mut self) {
+        match self.option_type {
+            Some(ref t) if t.is_folded() => {
+                let unfolded = t.unfold();
+                self.set_option_type(unfolded);
+                // The new children don't know the settings yet
+                self.set_only_errors(self.only_errors);
+                self.set_extra_child_display(self.extra_child_display);
+            }
+            _ => (),
+        }
+    }
+
+    /// Unfold the children, so the tree knows whether they can be expanded in turn
+    fn unfold_children(&mut self) {
+        for c in self.children.iter_mut() {
+            c.unfold();
+        }
+        if let Some(ref mut nested) = self.extra_child {
+            let nested = Arc::make_mut(nested);
+            nested.unfold();
+            for c in nested.children.iter_mut() {
+                c.unfold();
+            }
+        }
+    }
+
+    fn new_set(name: String, mut children: im::Vector<OptionNode>) -> Self {
+        children.sort_by(|left, right| left.name.cmp(&right.name));
+
+        Self {
+            name,
+            documentation: None,
+            option_type: None,
+            value: None,
+            children,
+            extra_child: None,
+            freeform_type: None,
+            freeform: false,
+            expanded: false,
+            has_error: false,
+            changed: false,
+            only_errors: false,
+            extra_child_display: ExtraChildDisplay::Inline,
+        }
+    }
+
+    pub fn new(name: String, val: NixValue) -> Self {
+        let graph = Arc::new(val.type_graph());
+        Self::new_with_graph(name, val, &graph)
+    }
+
+    fn new_with_graph(name: String, val: NixValue, graph: &Arc<NixTypeGraph>) -> Self {
+        match val {
+            NixValue::Option(opt) => {
+                let doc = (&opt).into();
+                Self::new_option(name, OptionType::from_nix(opt.r#type, graph), doc)
+            }
+            NixValue::Set(set) => {
+                let children = set
+                    .into_iter()
+                    .map(|(k, v)| OptionNode::new_with_graph(k, *v, graph))
+                    .collect();
+                Self::new_set(name, children)
+            }
+        }
+    }
+
+    fn new_from_submodule(
+        name: String,
+        sub: im::Vector<OptionNode>,
+        freeform_type: Option<Box<OptionType>>,
+        cfg: Option<NixGuardedValue>,
+    ) -> Self {
+        // TODO: Add documentation + value?
+        let mut node = Self::new_set(name, im::Vector::new());
+        // The options of the submodule are sorted already, sorting again would copy them
+        // instead of sharing them with the other instances
+        node.children = sub;
+        node.freeform_type = freeform_type;
+        node.add_config(cfg);
+        node
+    }
+
+    /// A value of type `t` in an `attrsOf` or `listOf`, or the extra child documenting
+    /// these values without `cfg`. Only types containing submodules give children.
+    fn new_element(name: String, t: &OptionType, cfg: Option<NixGuardedValue>) -> Self {
+        let t = match t {
+            // Values are finite, the types they need unfolded too
+            OptionType::Recursive(r) if cfg.is_some() => r.expand(),
+            _ => t.to_owned(),
+        };
+
+        match t {
+            OptionType::Submodule(sub, freeform) => {
+                Self::new_from_submodule(name, sub, freeform, cfg)
+            }
+            _ => {
+                let mut node = Self::new_set(name, im::Vector::new());
+                node.set_option_type(t);
+                node.add_config(cfg);
+                node
+            }
+        }
+    }
+
+    /// A value of a JSON/YAML/TOML `settings` option, attribute sets and lists get children
+    fn new_structured(name: String, format: ConfigFormat, value: NixGuardedValue) -> Self {
+        let mut node = Self::new_set(name, im::Vector::new());
+        node.option_type = Some(OptionType::Structured(format));
+        node.add_config(Some(value));
+        node
+    }
+
+    /// An element of the `attrsOf` or `listOf` of this node, showing its extra children
+    /// like this node
+    fn new_instance(&self, name: String, t: &OptionType, cfg: NixGuardedValue) -> Self {
+        let mut node = Self::new_element(name, t, Some(cfg));
+        node.set_extra_child_display(self.extra_child_display);
+        node
+    }
+
+    /// Show the attributes of `cfg` that aren't declared as options as children after
+    /// the declared ones, with the element type of the `freeformType`
+    fn add_freeform_config(&mut self, cfg: Option<NixGuardedValue>) {
+        let element_type = match self.freeform_type.as_deref() {
+            Some(t) => t.freeform_element_type(),
+            None => return,
+        };
+
+        if let Some(NixGuardedValue::Attrs(attrs)) = cfg {
+            let mut names: Vec<String> = attrs.keys().cloned().collect();
+            names.sort();
+            let mut attrs = attrs;
+            for name in names {
+                let val = attrs.remove(&name).map(|val| *val);
+                let doc = OptionDocumentation::freeform(element_type.to_string());
+                let mut node = OptionNode::new_option(name, element_type.clone(), doc);
+                node.freeform = true;
+                node.add_config(val);
+                node.set_extra_child_display(self.extra_child_display);
+                self.children.push_back(node);
+            }
+        }
+    }
+
+    pub fn add_config(&mut self, cfg: Option<NixGuardedValue>) {
+        if cfg.is_some() {
+            self.unfold();
+        }
+
+        match self.option_type.clone() {
+            Some(t) => self.attach(&t, cfg),
+            // This is a set
+            None => self.attach_to_children(cfg),
+        }
+    }
+
+    /// Attach a value of type `t`, which is the type of this node or nested in it.
+    /// `nullOr` and `either` are looked through, values of `attrsOf` and `listOf`
+    /// containing submodules become children, see `new_element`.
+    fn attach(&mut self, t: &OptionType, cfg: Option<NixGuardedValue>) {
+        use self::OptionType::*;
+        use NixGuardedValue::{Attrs, List, Null};
+
+        match (t, cfg) {
+            (AttrsOf(nt), Some(Attrs(attrs))) if nt.contains_submodule() => {
+                self.children = attrs
+                    .into_iter()
+                    .map(|(name, child_cfg)| self.new_instance(name, nt, *child_cfg))
+                    .collect();
+                self.children
+                    .sort_by(|left, right| left.name.cmp(&right.name));
+            }
+            (ListOf(nt), Some(List(list))) if nt.contains_submodule() => {
+                self.children = list
+                    .into_iter()
+                    .enumerate()
+                    .map(|(counter, child_cfg)| {
+                        self.new_instance(format!("Element {}", counter), nt, *child_cfg)
+                    })
+                    .collect();
+            }
+            (NullOr(_), cfg @ Some(Null(_))) => {
+                self.children = im::Vector::new();
+                self.value = cfg.map(Arc::new);
+            }
+            (NullOr(nt), cfg) => self.attach(nt, cfg),
+            (Either(l, r), Some(val)) => {
+                let side = if l.accepts(&val) { l } else { r };
+                if !side.contains_submodule() {
+                    self.children = im::Vector::new();
+                }
+                self.attach(side, Some(val));
+            }
+            (Structured(format), cfg) => {
+                let format = *format;
+                self.children = match cfg {
+                    Some(Attrs(ref attrs)) => {
+                        let mut children: im::Vector<OptionNode> = attrs
+                            .iter()
+                            .map(|(name, val)| {
+                                OptionNode::new_structured(name.clone(), format, (**val).clone())
+                            })
+                            .collect();
+                        children.sort_by(|left, right| left.name.cmp(&right.name));
+                        children
+                    }
+                    Some(List(ref list)) => list
+                        .iter()
+                        .enumerate()
+                        .map(|(counter, val)| {
+                            OptionNode::new_structured(
+                                format!("Element {}", counter),
+                                format,
+                                (**val).clone(),
+                            )
+                        })
+                        .collect(),
+                    _ => im::Vector::new(),
+                };
+                self.value = cfg.map(Arc::new);
+            }
+            // A submodule is also kind of a set
+            (Submodule(..), cfg) => self.attach_to_children(cfg),
+
+            (_, cfg) => self.value = cfg.map(Arc::new),
+        }
+    }
+
+    /// Attach the attributes of `cfg` to the children of the same name,
+    /// the ones that aren't declared as options to freeform children
+    fn attach_to_children(&mut self, mut cfg: Option<NixGuardedValue>) {
+        // e.g. an error, keep it to show it
+        if let Some(ref val) = cfg {
+            if !matches!(val, NixGuardedValue::Attrs(_)) {
+                self.value = cfg.map(Arc::new);
+                return;
+            }
+        }
+
+        // Only children getting a value are touched, the others stay shared
+        // with the other instances of the submodule
+        if let Some(NixGuardedValue::Attrs(ref mut attrs)) = cfg {
+            for i in 0..self.children.len() {
+                if let Some(child_cfg) = attrs.remove(&self.children[i].name) {
+                    self.children[i].add_config(Some(*child_cfg));
+                }
+            }
+        }
+
+        self.add_freeform_config(cfg);
+    }
+
+    /// Attach defaults evaluated in the context of a host, see `run::get_evaluated_defaults`.
+    /// Only descends into sets, submodules have no defaults of their own.
+    pub fn add_evaluated_defaults(&mut self, mut defaults: Option<NixGuardedValue>) {
+        if self.option_type.is_some() {
+            if let Some(ref mut doc) = self.documentation {
+                Arc::make_mut(doc).evaluated_default = defaults.map(Arc::new);
+            }
+        } else {
+            for ref mut c in self.children.iter_mut() {
+                let child_defaults = defaults
+                    .as_mut()
+                    .and_then(|val| {
+                        if let NixGuardedValue::Attrs(ref mut attrs) = val {
+                            attrs.remove(&c.name)
+                        } else {
+                            None
+                        }
+                    })
+                    .map(|val| *val);
+                if child_defaults.is_some() {
+                    c.add_evaluated_defaults(child_defaults);
+                }
+            }
+        }
+    }
+
+    pub fn default_comparison(&self) -> Option<DefaultComparison> {
+        DefaultComparison::compare(self.documentation.as_deref(), self.value.as_deref())
+    }
+
+    /// Attach the changes of an upgrade, `notes` is indexed by the option path as
+    /// in the documentation (i.e. with `<name>` and `*`), see `diff::OptionsDiff::notes_by_path`.
+    pub fn add_upgrade_notes(&mut self, notes: &HashMap<String, Vec<String>>) {
+        // The paths leading to notes, the other children are left alone and stay shared
+        let mut prefixes = HashSet::new();
+        for path in notes.keys() {
+            prefixes.extend(path.match_indices('.').map(|(i, _)| &path[..i]));
+            prefixes.insert(path.as_str());
+        }
+        self.add_upgrade_notes_at("", notes, &prefixes);
+    }
+
+    fn add_upgrade_notes_at(
+        &mut self,
+        path: &str,
+        notes: &HashMap<String, Vec<String>>,
+        prefixes: &HashSet<&str>,
+    ) {
+        if let (Some(doc), Some(n)) = (self.documentation.as_mut(), notes.get(path)) {
+            Arc::make_mut(doc).upgrade_notes = n.iter().cloned().collect();
+        }
+
+        // Children of `attrsOf`/`listOf` submodules are instances, not options
+        let extension = self
+            .option_type
+            .as_ref()
+            .and_then(|t| t.get_name_extension())
+            .map(str::to_string);
+        for i in 0..self.children.len() {
+            let segment = extension
+                .clone()
+                .unwrap_or_else(|| self.children[i].name.clone());
+            let child_path = if path.is_empty() {
+                segment
+            } else {
+                format!("{path}.{segment}")
+            };
+            if prefixes.contains(child_path.as_str()) {
+                self.children[i].add_upgrade_notes_at(&child_path, notes, prefixes);
+            }
+        }
+    }
+
+    /// Whether this option is set in the config and changes in the upgrade
+    pub fn upgrade_affected(&self) -> bool {
+        let has_notes = self
+            .documentation
+            .as_ref()
+            .is_some_and(|doc| !doc.upgrade_notes.is_empty());
+        let is_set =
+            self.value.is_some() && self.default_comparison() != Some(DefaultComparison::Default);
+
+        has_notes && is_set
+    }
+
+    /// Mark the renamed or removed option at `loc` as still defined in `files`,
+    /// see `run::get_deprecated_usage`
+    pub fn add_deprecated_definition(&mut self, loc: &[String], files: &[String]) {
+        match loc.split_first() {
+            None => {
+                if let Some(ref mut doc) = self.documentation {
+                    Arc::make_mut(doc).deprecated_definitions = files.iter().cloned().collect();
+                }
+            }
+            Some((name, rest)) => {
+                for c in self.children.iter_mut() {
+                    if &c.name == name {
+                        c.add_deprecated_definition(rest, files);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Set the files of the config defining the option at `loc`, see `run::get_definitions`
+    pub fn add_definition(&mut self, loc: &[String], files: &[String]) {
+        match loc.split_first() {
+            None => {
+                if let Some(ref mut doc) = self.documentation {
+                    Arc::make_mut(doc).definitions = files.iter().cloned().collect();
+                }
+            }
+            Some((name, rest)) => {
+                for c in self.children.iter_mut() {
+                    if &c.name == name {
+                        c.add_definition(rest, files);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Call `update` on the children for which `needs_update` holds. The children of
+    /// submodule instances are shared, touching only the ones that change keeps the
+    /// others shared.
+    fn update_children(
+        &mut self,
+        needs_update: impl Fn(&OptionNode) -> bool,
+        mut update: impl FnMut(&mut OptionNode),
+    ) {
+        for i in 0..self.children.len() {
+            if needs_update(&self.children[i]) {
+                update(&mut self.children[i]);
+            }
+        }
+    }
+
+    fn own_error(&self) -> bool {
+        self.value.as_ref().is_some_and(|v| !v.errors().is_empty())
+    }
+
+    /// Set `has_error` for this node and its children, call after `add_config`.
+    /// Returns whether it changed anywhere.
+    pub fn mark_errors(&mut self) -> bool {
+        match self.with_errors_marked() {
+            Some(marked) => {
+                *self = marked;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A copy with `has_error` set, `None` if it is set right everywhere already.
+    /// In a single pass, only the nodes that change are copied, the others stay shared.
+    fn with_errors_marked(&self) -> Option<OptionNode> {
+        let mut children = None;
+        for (i, c) in self.children.iter().enumerate() {
+            if let Some(marked) = c.with_errors_marked() {
+                children
+                    .get_or_insert_with(|| self.children.clone())
+                    .set(i, marked);
+            }
+        }
+
+        let has_error = self.own_error()
+            || children
+                .as_ref()
+                .unwrap_or(&self.children)
+                .iter()
+                .any(|c| c.has_error);
+        if children.is_none() && has_error == self.has_error {
+            return None;
+        }
+        let mut node = self.clone();
+        if let Some(children) = children {
+            node.children = children;
+        }
+        node.has_error = has_error;
+        Some(node)
+    }
+
+    pub fn set_only_errors(&mut self, only_errors: bool) {
+        self.only_errors = only_errors;
+        // Children without errors aren't shown at all, what they would show doesn't matter
+        self.update_children(
+            |c| c.only_errors != only_errors && (c.has_error || !only_errors),
+            |c| c.set_only_errors(only_errors),
+        );
+    }
+
+    /// Choose how the extra child documenting the elements of `attrsOf`/`listOf`
+    /// submodules is shown, for the whole tree. Nodes added later by `add_config`,
+    /// `unfold` or `merge_config` take it from their parent.
+    pub fn set_extra_child_display(&mut self, display: ExtraChildDisplay) {
+        if let Some(node) = self.with_extra_child_display(display) {
+            *self = node;
+        }
+    }
+
+    /// A copy with `display` set in the nodes using it, or `None` if none of them changes.
+    /// Like `with_errors_marked`, unchanged children stay shared.
+    fn with_extra_child_display(&self, display: ExtraChildDisplay) -> Option<OptionNode> {
+        let mut children = None;
+        for (i, c) in self.children.iter().enumerate() {
+            if let Some(set) = c.with_extra_child_display(display) {
+                children
+                    .get_or_insert_with(|| self.children.clone())
+                    .set(i, set);
+            }
+        }
+        let extra_child = self
+            .extra_child
+            .as_ref()
+            .and_then(|c| c.with_extra_child_display(display));
+
+        if children.is_none()
+            && extra_child.is_none()
+            && (!self.uses_extra_child_display() || self.extra_child_display == display)
+        {
+            return None;
+        }
+        let mut node = self.clone();
+        if let Some(children) = children {
+            node.children = children;
+        }
+        if let Some(extra_child) = extra_child {
+            node.extra_child = Some(Arc::new(extra_child));
+        }
+        node.extra_child_display = display;
+        Some(node)
+    }
+
+    /// Only nodes with an extra child, or that may get one when unfolded or given
+    /// freeform children, use the setting. The others don't keep it up to date.
+    fn uses_extra_child_display(&self) -> bool {
+        self.extra_child.is_some()
+            || self.freeform_type.is_some()
+            || self.option_type.as_ref().is_some_and(OptionType::is_folded)
+    }
+
+    /// Index in `children` of the `index`th child that is shown
+    pub fn shown_child_index(&self, index: usize) -> usize {
+        if self.only_errors {
+            self.children
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.has_error)
+                .nth(index)
+                .map(|(i, _)| i)
+                .expect("Index of shown child out of range")
+        } else {
+            index
+        }
+    }
+
+    pub fn shown_children_count(&self) -> usize {
+        if self.only_errors {
+            self.children.iter().filter(|c| c.has_error).count()
+        } else {
+            self.children.len()
+        }
+    }
+
+    /// The extra child is documentation only, it can't have errors
+    pub fn shown_extra_child(&self) -> Option<&OptionNode> {
+        self.extra_child.as_deref().filter(|_| !self.only_errors)
+    }
+
+    /// Check a value imported from JSON/YAML/TOML for this node, see `OptionType::check`
+    pub fn check_value(&self, value: &Value, path: &str, errors: &mut Vec<String>) {
+        match (self.option_type.as_ref(), value) {
+            (Some(t), _) => t.check(value, path, errors),
+            (None, Value::Object(map)) => check_children(
+                &self.children,
+                self.freeform_type.as_deref(),
+                map,
+                path,
+                errors,
+            ),
+            (None, _) => errors.push(format!("`{path}`: expected an attribute set, got {value}")),
+        }
+    }
+
+    /// Find a node by its path, e.g. `users.users.alice.shell`.
+    /// `<name>` and `*` lead to the extra child of `attrsOf`/`listOf` submodules.
+    pub fn find(&self, path: &str) -> Option<&OptionNode> {
+        path.split('.').try_fold(self, |node, segment| {
+            node.children
+                .iter()
+                .chain(node.extra_child.as_deref())
+                .find(|c| c.name == segment)
+        })
+    }
+
+    /// Whether this is a renamed or removed option that is still set in the config
+    pub fn deprecated_in_use(&self) -> bool {
+        self.documentation
+            .as_ref()
+            .is_some_and(|doc| !doc.deprecated_definitions.is_empty())
+    }
+
+    /// The indices in `children` leading to the node holding `value` itself, not a copy
+    /// of it, e.g. of the `DisplayData` of the selected option. See `node_at`.
+    pub fn path_to_value(&self, value: &NixGuardedValue) -> Option<Vec<usize>> {
+        self.children.iter().enumerate().find_map(|(i, c)| {
+            let mut path = if c.value.as_deref().is_some_and(|v| std::ptr::eq(v, value)) {
+                Vec::new()
+            } else {
+                c.path_to_value(value)?
+            };
+            path.insert(0, i);
+            Some(path)
+        })
+    }
+
+    /// The node at the indices in `children` of `path_to_value`
+    pub fn node_at(&self, path: &[usize]) -> Option<&OptionNode> {
+        path.iter()
+            .try_fold(self, |node, &index| node.children.get(index))
+    }
+
+    /// Take the config attached to `new`, a tree of the same options, e.g. after the
+    /// config was evaluated again. The documentation, the expansion of the nodes that
+    /// are still there and the shared children of unchanged submodule instances are
+    /// kept. Nodes with a new value, new nodes and nodes that lost children get
+    /// `changed` set, along with their parents. Returns whether anything changed.
+    pub fn merge_config(&mut self, mut new: OptionNode) -> bool {
+        // Expanding unfolded the old node, the new one has to match it
+        let folded =
+            |node: &OptionNode| node.option_type.as_ref().is_some_and(OptionType::is_folded);
+        if folded(&new) && !folded(self) {
+            new.unfold();
+        }
+
+        let mut changed = match (self.value.as_deref(), new.value.as_deref()) {
+            (Some(old), Some(new)) => !same_value(old, new),
+            (old, new) => old.is_some() != new.is_some(),
+        };
+        if changed {
+            self.value = new.value;
+        }
+        self.has_error = new.has_error;
+
+        let same_names = self.children.len() == new.children.len()
+            && self
+                .children
+                .iter()
+                .zip(new.children.iter())
+                .all(|(old, new)| old.name == new.name);
+        if same_names {
+            for (i, new_child) in new.children.into_iter().enumerate() {
+                // Only replace children that changed, the others stay shared
+                let mut child = self.children[i].clone();
+                if child.merge_config(new_child) {
+                    self.children[i] = child;
+                    changed = true;
+                }
+            }
+        } else {
+            let mut old: HashMap<String, OptionNode> = self
+                .children
+                .iter()
+                .map(|c| (c.name.clone(), c.clone()))
+                .collect();
+            self.children = new
+                .children
+                .into_iter()
+                .map(|new_child| match old.remove(&new_child.name) {
+                    Some(mut child) => {
+                        child.merge_config(new_child);
+                        child
+                    }
+                    None => {
+                        // The new tree shows its extra children like this one already
+                        let mut child = new_child;
+                        child.set_only_errors(self.only_errors);
+                        child.changed = true;
+                        child
+                    }
+                })
+                .collect();
+            changed = true;
+        }
+
+        self.changed |= changed;
+        changed
+    }
+
+    /// Unmark the nodes marked by `merge_config`
+    pub fn clear_changed(&mut self) {
+        if self.changed {
+            self.changed = false;
+            self.update_children(|c| c.changed, |c| c.clear_changed());
+        }
+    }
+}
+
+/// Whether a value is unchanged by a reload, see `OptionNode::merge_config`. Functions,
+/// errors and option types can't be compared, only whether they are still there.
+fn same_value(old: &NixGuardedValue, new: &NixGuardedValue) -> bool {
+    use NixGuardedValue::*;
+
+    match (old, new) {
+        (Function(_), Function(_)) | (Error(_), Error(_)) | (OptionType(_), OptionType(_)) => true,
+        (LiteralExpression(l), LiteralExpression(r)) => l.text == r.text,
+        (Attrs(l), Attrs(r)) => {
+            l.len() == r.len()
+                && l.iter()
+                    .all(|(k, v)| r.get(k).is_some_and(|other| same_value(v, other)))
+        }
+        (List(l), List(r)) => {
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| same_value(l, r))
+        }
+        _ => old.compare(new) == Some(true),
+    }
+}
+
+/// Check every attribute of `map` against the option of the same name,
+/// or the `freeformType` if there is no such option
+fn check_children(
+    children: &im::Vector<OptionNode>,
+    freeform: Option<&OptionType>,
+    map: &serde_json::Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    for (name, v) in map.iter() {
+        let child_path = format!("{path}.{name}");
+        match children.iter().find(|c| &c.name == name) {
+            Some(child) => child.check_value(v, &child_path, errors),
+            None => match freeform {
+                Some(t) => t.freeform_element_type().check(v, &child_path, errors),
+                None => errors.push(format!("`{child_path}`: no such option")),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl TreeNode for OptionNode {
+    fn get_child(&self, index: usize) -> &Self {
+        self.visible_child(index)
+    }
+
+    fn for_child_mut(&mut self, index: usize, mut cb: impl FnMut(&mut Self, usize)) {
+        cb(self.visible_child_mut(index), index)
+    }
+
+    fn children_count(&self) -> usize {
+        self.visible_children_count()
+    }
+}
+
+impl OptionTreeNode for OptionNode {
+    fn visible_children_count(&self) -> usize {
+        match (self.extra_child_display, self.shown_extra_child()) {
+            (ExtraChildDisplay::Inline, Some(c)) => c.visible_children_count(),
+            (ExtraChildDisplay::First, Some(_)) => self.shown_children_count() + 1,
+            (_, None) => self.shown_children_count(),
+        }
+    }
+
+    fn visible_child(&self, index: usize) -> &Self {
+        match (self.extra_child_display, self.shown_extra_child(), index) {
+            (ExtraChildDisplay::Inline, Some(c), i) => c.visible_child(i),
+            (ExtraChildDisplay::First, Some(c), 0) => c,
+            (ExtraChildDisplay::First, Some(_), i) => &self.children[self.shown_child_index(i - 1)],
+            (_, None, i) => &self.children[self.shown_child_index(i)],
+        }
+    }
+
+    fn visible_child_mut(&mut self, index: usize) -> &mut Self {
+        let has_extra_child = self.shown_extra_child().is_some();
+        match (self.extra_child_display, has_extra_child, index) {
+            (ExtraChildDisplay::Inline, true, i) => {
+                Arc::make_mut(self.extra_child.as_mut().unwrap()).visible_child_mut(i)
+            }
+            (ExtraChildDisplay::First, true, 0) => {
+                Arc::make_mut(self.extra_child.as_mut().unwrap())
+            }
+            (ExtraChildDisplay::First, true, i) => {
+                let child = self.shown_child_index(i - 1);
+                &mut self.children[child]
+            }
+            (_, false, i) => {
+                let child = self.shown_child_index(i);
+                &mut self.children[child]
+            }
+        }
+    }
+
+    // TODO: Nice icons
+    fn get_icon(&self) -> String {
+        let alias_kind = self.documentation.as_ref().and_then(|doc| doc.alias_kind);
+        if let Some(kind) = alias_kind {
+            return match kind {
+                NixAliasKind::Alias => "🔗",
+                NixAliasKind::Renamed => "↪️",
+                NixAliasKind::Removed => "🚫",
+            }
+            .to_owned();
+        }
+
+        if self.freeform {
+            return "🔓".to_owned();
+        }
+
+        if let Some(ref t) = self.option_type {
+            if let (OptionType::Structured(_), None) = (t, &self.documentation) {
+                // Part of the value of a `settings` option
+                "🧩"
+            } else if t.has_nested_submodule() {
+                if self.expanded {
+                    "📖"
+                } else {
+                    "📕"
+                }
+            } else {
+                match self.name.as_ref() {
+                    "enable" => "✅",
+                    _ => "⚙️",
+                }
+            }
+        } else {
+            if self.expanded {
+                "📂"
+            } else {
+                "📁"
+            }
+        }
+        .to_owned()
+    }
+
+    fn get_path_segment(&self) -> String {
+        if let Some(ref t) = self.option_type {
+            if let Some(ext) = t.get_name_extension() {
+                return format!("{}.{}", self.name, ext);
+            }
+        }
+
+        self.name.clone()
+    }
+
+    fn focused_display_data(&self) -> DisplayData {
+        DisplayData::new_with(self.documentation.as_deref().cloned(), self.value.clone())
+    }
+
+    fn is_expanded(&self) -> bool {
+        self.expanded
+    }
+
+    fn toggle_expanded(&mut self) {
+        self.expanded = !self.expanded;
+        if self.expanded {
+            self.unfold_children();
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum OptionType {
+    // Collection types
+    AttrsOf(Box<OptionType>),
+    ListOf(Box<OptionType>),
+    NullOr(Box<OptionType>),
+    Either(Box<OptionType>, Box<OptionType>),
+
+    // Leaf/simple types
+    Enum(im::Vector<String>),
+    Path,
+    Package,
+    String,
+    Float,
+    Bool,
+    /// Int with optional min/max bounds
+    Int(Option<i64>, Option<i64>),
+
+    // Miscellaneous types
+    Unknown(String),
+    /// The declared options and the `freeformType`, if any
+    Submodule(im::Vector<OptionNode>, Option<Box<OptionType>>),
+    /// The JSON/YAML/TOML value types of `pkgs.formats`, used for `settings` options
+    Structured(ConfigFormat),
+    /// A type nested in itself, see `unfold`
+    Recursive(RecursiveType),
+}
+
+/// A reference to an enclosing type, which is looked up in the type graph when needed
+#[derive(Clone)]
+pub struct RecursiveType {
+    id: String,
+    description: String,
+    graph: Arc<NixTypeGraph>,
+}
+
+impl RecursiveType {
+    /// Like `OptionType::contains_submodule`, without unfolding anything
+    pub fn contains_submodule(&self) -> bool {
+        fn contains<'a>(
+            t: &'a NixTypeValue,
+            graph: &'a NixTypeGraph,
+            seen: &mut HashSet<&'a str>,
+        ) -> bool {
+            match t {
+                NixTypeValue::Submodule(_) => true,
+                NixTypeValue::Reference(r) => {
+                    seen.insert(&r.id) && graph.get(&r.id).is_some_and(|t| contains(t, graph, seen))
+                }
+                // The wrapper types `OptionType::from_nix` knows
+                NixTypeValue::Type(t) => {
+                    !t.structured
+                        && matches!(
+                            t.name.as_str(),
+                            "nullOr" | "listOf" | "attrsOf" | "lazyAttrsOf" | "either"
+                        )
+                        && t.nestedTypes.values().any(|n| contains(n, graph, seen))
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        seen.insert(self.id.as_str());
+        self.graph
+            .get(&self.id)
+            .is_some_and(|t| contains(t, &self.graph, &mut seen))
+    }
+
+    /// The type this refers to, with references nested in it left as they are
+    pub fn expand(&self) -> OptionType {
+        match self.graph.get(&self.id) {
+            Some(t) => OptionType::from_nix(t.clone(), &self.graph),
+            None => OptionType::Unknown(self.description.clone()),
+        }
+    }
+}
+
+// The graph could be huge, and it's the same for every reference
+impl std::fmt::Debug for RecursiveType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecursiveType")
+            .field("id", &self.id)
+            .field("description", &self.description)
+            .finish()
+    }
+}
+
+impl OptionType {
+    pub fn has_nested_submodule(&self) -> bool {
+        self.contains_submodule()
+    }
+
+    /// Whether this is a submodule, or one in any of the wrapper types
+    fn contains_submodule(&self) -> bool {
+        use OptionType::*;
+
+        match self {
+            Submodule(..) => true,
+            NullOr(t) | AttrsOf(t) | ListOf(t) => t.contains_submodule(),
+            Either(l, r) => l.contains_submodule() || r.contains_submodule(),
+            Recursive(r) => r.contains_submodule(),
+            _ => false,
+        }
+    }
+
+    /// Roughly whether `val` is a value of this type, to pick the side of an `either`
+    fn accepts(&self, val: &NixGuardedValue) -> bool {
+        use OptionType::*;
+
+        let is_set = matches!(val, NixGuardedValue::Attrs(_));
+        let is_list = matches!(val, NixGuardedValue::List(_));
+        match self {
+            Submodule(..) | AttrsOf(_) => is_set,
+            ListOf(_) => is_list,
+            NullOr(t) => matches!(val, NixGuardedValue::Null(_)) || t.accepts(val),
+            Either(l, r) => l.accepts(val) || r.accepts(val),
+            Enum(_) | Path | Package | String | Float | Bool | Int(..) => !is_set && !is_list,
+            Unknown(_) | Structured(_) | Recursive(_) => true,
+        }
+    }
+
+    /// Whether this is a recursive type, possibly in a `nullOr` or `either`,
+    /// which has to be unfolded to know its options, see `OptionNode::structure`
+    fn is_folded(&self) -> bool {
+        use OptionType::*;
+
+        match self {
+            Recursive(_) => true,
+            NullOr(t) => t.is_folded(),
+            Either(l, r) => l.is_folded() || r.is_folded(),
+            _ => false,
+        }
+    }
+
+    /// The type of a single attribute allowed by this `freeformType`,
+    /// these are mostly `attrsOf` or the JSON/YAML/TOML value types
+    fn freeform_element_type(&self) -> OptionType {
+        match self {
+            OptionType::AttrsOf(t) => (**t).clone(),
+            OptionType::Structured(_) | OptionType::Unknown(_) => self.clone(),
+            _ => OptionType::Unknown(format!("attribute of {}", self)),
+        }
+    }
+
+    /// Expand the recursive type of `is_folded` one level
+    fn unfold(&self) -> OptionType {
+        use OptionType::*;
+
+        match self {
+            Recursive(r) => r.expand(),
+            NullOr(t) => NullOr(Box::new(t.unfold())),
+            Either(l, r) => Either(Box::new(l.unfold()), Box::new(r.unfold())),
+            _ => self.clone(),
+        }
+    }
+
+    /// Check a value imported from JSON/YAML/TOML against this type, errors name the path
+    pub fn check(&self, value: &Value, path: &str, errors: &mut Vec<String>) {
+        use OptionType::*;
+
+        let expected = |what: &str| format!("`{path}`: expected {what}, got {value}");
+        match (self, value) {
+            // `literalExpression`s are Nix code, they can't be checked here
+            (_, Value::Object(map)) if map.contains_key("_type") => (),
+
+            (AttrsOf(t), Value::Object(map)) => {
+                for (name, v) in map.iter() {
+                    t.check(v, &format!("{path}.{name}"), errors);
+                }
+            }
+            (AttrsOf(_), _) => errors.push(expected("an attribute set")),
+            (ListOf(t), Value::Array(list)) => {
+                for (i, v) in list.iter().enumerate() {
+                    t.check(v, &format!("{path}[{i}]"), errors);
+                }
+            }
+            (ListOf(_), _) => errors.push(expected("a list")),
+            (NullOr(_), Value::Null) => (),
+            (NullOr(t), _) => t.check(value, path, errors),
+            (Either(l, r), _) => {
+                let mut left_errors = Vec::new();
+                l.check(value, path, &mut left_errors);
+                if !left_errors.is_empty() {
+                    r.check(value, path, errors);
+                }
+            }
+
+            (Enum(values), Value::String(s)) if values.is_empty() || values.contains(s) => (),
+            (Enum(values), _) => errors.push(expected(&format!("one of {:?}", values))),
+            // Packages can be given as store paths
+            (Path | Package | String, Value::String(_)) => (),
+            (Path, _) => errors.push(expected("a path")),
+            (Package, _) => errors.push(expected("a package")),
+            (String, _) => errors.push(expected("a string")),
+            (Float, Value::Number(_)) => (),
+            (Float, _) => errors.push(expected("a float")),
+            (Bool, Value::Bool(_)) => (),
+            (Bool, _) => errors.push(expected("a boolean")),
+            (Int(min, max), Value::Number(n)) => match n.as_i64() {
+                Some(i) if min.is_some_and(|min| i < min) || max.is_some_and(|max| i > max) => {
+                    errors.push(expected(&format!(
+                        "an integer between {:?} and {:?}",
+                        min, max
+                    )))
+                }
+                Some(_) => (),
+                None => errors.push(expected("an integer")),
+            },
+            (Int(..), _) => errors.push(expected("an integer")),
+
+            (Unknown(_), _) | (Structured(_), _) => (),
+            (Submodule(children, freeform), Value::Object(map)) => {
+                check_children(children, freeform.as_deref(), map, path, errors)
+            }
+            (Submodule(..), _) => errors.push(expected("an attribute set")),
+            // Values are finite, so is the unfolding
+            (Recursive(r), _) => r.expand().check(value, path, errors),
+        }
+    }
+
+    pub fn get_name_extension(&self) -> Option<&str> {
+        use OptionType::*;
+
+        match self {
+            AttrsOf(ref t) if t.contains_submodule() => Some("<name>"),
+            ListOf(ref t) if t.contains_submodule() => Some("*"),
+            NullOr(ref t) => t.get_name_extension(),
+            Either(ref l, ref r) => l.get_name_extension().or_else(|| r.get_name_extension()),
+            _ => None,
+        }
+    }
+}
+
+/// Roughly the descriptions of the NixOS types
+impl std::fmt::Display for OptionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use OptionType::*;
+
+        match self {
+            AttrsOf(t) => write!(f, "attribute set of ({})", t),
+            ListOf(t) => write!(f, "list of ({})", t),
+            NullOr(t) => write!(f, "null or ({})", t),
+            Either(l, r) => write!(f, "({}) or ({})", l, r),
+
+            Enum(values) => write!(f, "one of {:?}", values),
+            Path => write!(f, "path"),
+            Package => write!(f, "package"),
+            String => write!(f, "string"),
+            Float => write!(f, "floating point number"),
+            Bool => write!(f, "boolean"),
+            Int(None, None) => write!(f, "signed integer"),
+            Int(min, max) => write!(f, "integer between {:?} and {:?}", min, max),
+
+            Unknown(description) => write!(f, "{}", description),
+            Submodule(_, Some(freeform)) => write!(f, "submodule with freeform {}", freeform),
+            Submodule(_, None) => write!(f, "submodule"),
+            Structured(format) => write!(f, "{} value", format),
+            Recursive(r) => write!(f, "{}", r.description),
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl Data for OptionType {
+    fn same(&self, other: &Self) -> bool {
+        use OptionType::*;
+
+        match (self, other) {
+            (AttrsOf(l), AttrsOf(r)) => l.same(r),
+            (ListOf(l), ListOf(r)) => l.same(r),
+            (NullOr(l), NullOr(r)) => l.same(r),
+            (Either(l1, l2), Either(r1, r2)) => l1.same(r1) && l2.same(r2),
+
+            (Enum(l), Enum(r)) => l.same(r),
+            (Path, Path) => true,
+            (Package, Package) => true,
+            (String, String) => true,
+            (Float, Float) => true,
+            (Bool, Bool) => true,
+            (Int(l1, l2), Int(r1, r2)) => l1.same(r1) && l2.same(r2),
+
+            (Unknown(l), Unknown(r)) => l.same(r),
+            (Submodule(l, lf), Submodule(r, rf)) => {
+                let same_freeform = match (lf, rf) {
+                    (Some(l), Some(r)) => l.same(r),
+                    (None, None) => true,
+                    _ => false,
+                };
+                l.same(r) && same_freeform
+            }
+            (Structured(l), Structured(r)) => l == r,
+            // Ids are unique in the whole option tree
+            (Recursive(l), Recursive(r)) => l.id == r.id,
+
+            _ => false,
+        }
+    }
+}
+
+impl OptionType {
+    /// Convert an exported type, references to recursive types are looked up in `graph`
+    /// when they are unfolded
+    fn from_nix(raw_type: NixTypeValue, graph: &Arc<NixTypeGraph>) -> Self {
+        use NixTypeValue::*;
+        use OptionType::*;
+
+        let from = |t: NixTypeValue| Box::new(OptionType::from_nix(t, graph));
+        match raw_type {
+            Type(t) if t.structured => match ConfigFormat::from_type_description(&t.description) {
+                Some(format) => Structured(format),
+                None => Unknown(Type(t).to_string()),
+            },
+            Type(mut t) => {
+                // Use `remove` here to avoid borrow and take ownership of value
+                if let Some(elem) = t.nestedTypes.remove("elemType") {
+                    match t.name.as_ref() {
+                        "nullOr" => NullOr(from(elem)),
+                        "listOf" => ListOf(from(elem)),
+                        "attrsOf" | "lazyAttrsOf" => AttrsOf(from(elem)),
+                        _ => Unknown(Type(t).to_string()),
+                    }
+                } else if let (Some(left), Some(right)) =
+                    (t.nestedTypes.remove("left"), t.nestedTypes.remove("right"))
+                {
+                    match t.name.as_ref() {
+                        "either" => Either(from(left), from(right)),
+                        _ => Unknown(Type(t).to_string()),
+                    }
+                } else {
+                    match t.name.as_ref() {
+                        "str" | "string" => String,
+                        // lib.types.{commas, lines} => separatedString
+                        "separatedString" => String,
+
+                        "int" => Int(None, None),
+                        "positiveInt" => Int(Some(1), None),
+                        "unsignedInt" => Int(Some(0), None),
+                        "unsignedInt8" => Int(Some(0), Some((1 << 8) - 1)),
+                        "unsignedInt16" => Int(Some(0), Some((1 << 16) - 1)),
+                        "unsignedInt32" => Int(Some(0), Some((1 << 32) - 1)),
+                        "signedInt8" => Int(Some(-(1 << 7)), Some((1 << 7) - 1)),
+                        "signedInt16" => Int(Some(-(1 << 15)), Some((1 << 15) - 1)),
+                        "signedInt32" => Int(Some(-(1 << 31)), Some((1 << 31) - 1)),
+
+                        "float" => Float,
+                        "bool" => Bool,
+                        "path" => Path,
+                        "package" => Package,
+
+                        "enum" => {
+                            let values: Option<im::Vector<_>> = t
+                                .functorPayload
+                                .iter()
+                                .map(|v| v.as_str().map(str::to_string))
+                                .collect();
+                            match values {
+                                Some(values) => Enum(values),
+                                // e.g. `enum [ 1 2 ]`, not checked
+                                None => Unknown(Type(t).to_string()),
+                            }
+                        }
+                        _ => Unknown(Type(t).to_string()),
+                    }
+                }
+            }
+            NixTypeValue::Submodule(set) => {
+                let mut children: im::Vector<OptionNode> = set
+                    .options
+                    .into_iter()
+                    .map(|(k, v)| OptionNode::new_with_graph(k, *v, graph))
+                    .collect();
+                children.sort_by(|left, right| left.name.cmp(&right.name));
+                let freeform = set.freeform_type.map(|t| from(*t));
+                OptionType::Submodule(children, freeform)
+            }
+            Reference(r) => Recursive(RecursiveType {
+                id: r.id,
+                description: r.description,
+                graph: graph.clone(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn nix_type(name: &str, nested_types: Value) -> Value {
+        json!({
+            "_type": true,
+            "name": name,
+            "description": name,
+            "functorName": name,
+            "nestedTypes": nested_types,
+        })
+    }
+
+    fn wrapped(name: &str, elem_type: Value) -> Value {
+        nix_type(name, json!({ "elemType": elem_type }))
+    }
+
+    fn either(left: Value, right: Value) -> Value {
+        nix_type("either", json!({ "left": left, "right": right }))
+    }
+
+    fn option(option_type: Value) -> Value {
+        json!({
+            "_option": true,
+            "description": "",
+            "default": null,
+            "example": null,
+            "type": option_type,
+        })
+    }
+
+    fn submodule() -> Value {
+        json!({
+            "_submodule": true,
+            "options": { "port": option(nix_type("int", json!({}))) },
+        })
+    }
+
+    /// A tree with the single option `o` of the given type, set to `cfg`
+    fn tree(option_type: Value, cfg: Value) -> OptionNode {
+        let options = serde_json::from_value(json!({ "o": option(option_type) })).unwrap();
+        let mut root = OptionNode::new("root".to_string(), options);
+        root.add_config(Some(serde_json::from_value(json!({ "o": cfg })).unwrap()));
+        root
+    }
+
+    fn value_at(root: &OptionNode, path: &str) -> Option<String> {
+        root.find(path)
+            .and_then(|node| node.value.as_deref())
+            .map(|val| val.to_string())
+    }
+
+    #[test]
+    fn null_or_submodule() {
+        let root = tree(wrapped("nullOr", submodule()), json!({ "port": 1 }));
+        assert_eq!(value_at(&root, "o.port").as_deref(), Some("1"));
+
+        let root = tree(wrapped("nullOr", submodule()), json!(null));
+        assert_eq!(value_at(&root, "o").as_deref(), Some("null"));
+        assert!(root.find("o.port").is_none());
+    }
+
+    #[test]
+    fn attrs_of_submodule() {
+        let root = tree(
+            wrapped("attrsOf", submodule()),
+            json!({ "a": { "port": 1 } }),
+        );
+        assert_eq!(value_at(&root, "o.a.port").as_deref(), Some("1"));
+        assert!(root.find("o.<name>.port").is_some());
+    }
+
+    #[test]
+    fn lazy_attrs_of_submodule() {
+        let root = tree(
+            wrapped("lazyAttrsOf", submodule()),
+            json!({ "a": { "port": 1 } }),
+        );
+        assert_eq!(value_at(&root, "o.a.port").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn attrs_of_attrs_of_submodule() {
+        let option_type = wrapped("attrsOf", wrapped("attrsOf", submodule()));
+        let root = tree(option_type, json!({ "a": { "b": { "port": 1 } } }));
+        assert_eq!(value_at(&root, "o.a.b.port").as_deref(), Some("1"));
+        assert!(root.find("o.<name>.<name>.port").is_some());
+    }
+
+    #[test]
+    fn list_of_attrs_of_submodule() {
+        let option_type = wrapped("listOf", wrapped("attrsOf", submodule()));
+        let root = tree(option_type, json!([{ "a": { "port": 1 } }]));
+        assert_eq!(value_at(&root, "o.Element 0.a.port").as_deref(), Some("1"));
+        assert!(root.find("o.*.<name>.port").is_some());
+    }
+
+    #[test]
+    fn attrs_of_null_or_submodule() {
+        let option_type = wrapped("attrsOf", wrapped("nullOr", submodule()));
+        let root = tree(option_type, json!({ "a": null, "b": { "port": 2 } }));
+        assert_eq!(value_at(&root, "o.a").as_deref(), Some("null"));
+        assert_eq!(value_at(&root, "o.b.port").as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn either_str_submodule() {
+        let option_type = either(nix_type("str", json!({})), submodule());
+        let root = tree(option_type.clone(), json!({ "port": 1 }));
+        assert_eq!(value_at(&root, "o.port").as_deref(), Some("1"));
+
+        let root = tree(option_type, json!("text"));
+        assert_eq!(value_at(&root, "o").as_deref(), Some("\"text\""));
+        assert!(root.find("o.port").is_none());
+    }
+
+    #[test]
+    fn extra_child_display() {
+        let mut root = tree(
+            wrapped("attrsOf", submodule()),
+            json!({ "a": { "port": 1 } }),
+        );
+        let names = |node: &OptionNode| -> Vec<String> {
+            (0..node.visible_children_count())
+                .map(|i| node.visible_child(i).name.clone())
+                .collect()
+        };
+
+        assert_eq!(names(root.visible_child(0)), ["port"]);
+        root.set_extra_child_display(ExtraChildDisplay::First);
+        assert_eq!(names(root.visible_child(0)), ["<name>", "a"]);
+    }
+
+    #[test]
+    fn extra_child_display_is_passed_down() {
+        let options = serde_json::from_value(json!({
+            "o": option(wrapped("attrsOf", wrapped("attrsOf", submodule()))),
+        }))
+        .unwrap();
+        let mut root = OptionNode::new("root".to_string(), options);
+        root.set_extra_child_display(ExtraChildDisplay::First);
+        let cfg = json!({ "o": { "a": { "x": { "port": 1 } } } });
+        root.add_config(Some(serde_json::from_value(cfg).unwrap()));
+
+        let a = root.visible_child(0).visible_child(1);
+        assert_eq!(a.name, "a");
+        assert_eq!(a.visible_child(0).name, "<name>");
+        assert_eq!(a.visible_child(1).name, "x");
+    }
+
+    #[test]
+    fn aliases() {
+        let alias = |kind: &str, target: Value| {
+            let mut option = option(nix_type("bool", json!({})));
+            option["aliasKind"] = json!(kind);
+            option["aliasTarget"] = target;
+            option
+        };
+        let mut removed = alias("removed", Value::Null);
+        removed["removalMessage"] = json!("Use services.new instead.");
+        let options = serde_json::from_value(json!({
+            "services": {
+                "old": { "enable": alias("renamed", json!("services.new.enable")) },
+                "gone": { "enable": removed },
+                "new": { "enable": option(nix_type("bool", json!({}))) },
+            },
+        }))
+        .unwrap();
+        let mut root = OptionNode::new("root".to_string(), options);
+
+        let renamed = root.find("services.old.enable").unwrap();
+        let doc = renamed.documentation.clone().unwrap();
+        assert_eq!(doc.alias_kind, Some(NixAliasKind::Renamed));
+        assert_eq!(doc.alias_target.as_deref(), Some("services.new.enable"));
+        assert_eq!(renamed.get_icon(), "↪️");
+        assert!(doc
+            .to_string()
+            .contains("Deprecated: renamed to `services.new.enable`"));
+
+        let removed = root.find("services.gone.enable").unwrap();
+        let doc = removed.documentation.clone().unwrap();
+        assert_eq!(doc.alias_kind, Some(NixAliasKind::Removed));
+        assert_eq!(removed.get_icon(), "🚫");
+        assert!(doc
+            .to_string()
+            .contains("Removed: Use services.new instead."));
+        let new = root.find("services.new.enable").unwrap();
+        assert!(new.documentation.as_ref().unwrap().alias_kind.is_none());
+
+        let path = |path: &str| -> Vec<String> { path.split('.').map(str::to_string).collect() };
+        let files = ["/etc/nixos/configuration.nix".to_string()];
+        root.add_deprecated_definition(&path("services.old.enable"), &files);
+        // Paths that aren't in the tree are ignored
+        root.add_deprecated_definition(&path("services.missing.enable"), &files);
+
+        let in_use = |path: &str| root.find(path).unwrap().deprecated_in_use();
+        assert!(in_use("services.old.enable"));
+        assert!(!in_use("services.gone.enable"));
+        assert!(!in_use("services.new.enable"));
+        let doc = root
+            .find("services.old.enable")
+            .unwrap()
+            .documentation
+            .clone();
+        assert!(doc
+            .unwrap()
+            .to_string()
+            .contains("Warning: still set in:\n    /etc/nixos/configuration.nix"));
+    }
+
+    #[test]
+    fn upgrade_notes() {
+        let mut root = tree(
+            wrapped("attrsOf", submodule()),
+            json!({ "a": { "port": 1 }, "b": { "port": 2 } }),
+        );
+        let notes = HashMap::from([("o.<name>.port".to_string(), vec!["removed".to_string()])]);
+        root.add_upgrade_notes(&notes);
+
+        let notes = |path: &str| {
+            let doc = root.find(path).unwrap().documentation.clone().unwrap();
+            doc.upgrade_notes.iter().cloned().collect::<Vec<_>>()
+        };
+        assert_eq!(notes("o.a.port"), ["removed"]);
+        assert_eq!(notes("o.b.port"), ["removed"]);
+        assert!(notes("o").is_empty());
+        assert!(root.find("o.a.port").unwrap().upgrade_affected());
+    }
+
+    #[test]
+    fn submodule_instances_share_options() {
+        let sub = json!({
+            "_submodule": true,
+            "options": {
+                "host": option(nix_type("str", json!({}))),
+                "port": option(nix_type("int", json!({}))),
+            },
+        });
+        let mut root = tree(
+            wrapped("attrsOf", sub),
+            json!({ "a": { "port": 1 }, "b": { "port": 2 } }),
+        );
+        root.mark_errors();
+        root.set_extra_child_display(ExtraChildDisplay::First);
+
+        let doc = |path: &str| root.find(path).unwrap().documentation.clone().unwrap();
+        assert!(Arc::ptr_eq(&doc("o.a.host"), &doc("o.b.host")));
+        assert!(Arc::ptr_eq(&doc("o.a.port"), &doc("o.<name>.port")));
+        assert_eq!(value_at(&root, "o.b.port").as_deref(), Some("2"));
+        assert!(value_at(&root, "o.<name>.port").is_none());
+    }
+
+    #[test]
+    fn display_data_shares_the_value() {
+        let mut root = tree(nix_type("str", json!({})), json!("text"));
+        let node = root.find("o").unwrap();
+        let value = node.value.clone().unwrap();
+        let shown = node.focused_display_data();
+        assert!(std::ptr::eq(shown.get_value().unwrap(), &*value));
+
+        // Attaching the config again is a change for `Data`, even with an equal value
+        root.add_config(Some(
+            serde_json::from_value(json!({ "o": "text" })).unwrap(),
+        ));
+        let reattached = root.find("o").unwrap().value.clone().unwrap();
+        assert!(!Arc::ptr_eq(&reattached, &value));
+    }
+
+    #[test]
+    fn merge_config() {
+        let option_type = wrapped("attrsOf", submodule());
+        let cfg = json!({ "a": { "port": 1 }, "b": { "port": 2 } });
+        let mut root = tree(option_type.clone(), cfg);
+        root.children[0].expanded = true;
+        let unchanged = root.find("o.a.port").unwrap().value.clone().unwrap();
+
+        let cfg = json!({ "a": { "port": 1 }, "b": { "port": 3 }, "c": { "port": 4 } });
+        assert!(root.merge_config(tree(option_type, cfg)));
+        let changed = |path: &str| root.find(path).unwrap().changed;
+        assert!(root.find("o").unwrap().expanded);
+        assert!(changed("o") && changed("o.b.port") && changed("o.c"));
+        assert!(!changed("o.a") && !changed("o.a.port"));
+        let kept = root.find("o.a.port").unwrap().value.clone().unwrap();
+        assert!(Arc::ptr_eq(&kept, &unchanged));
+        assert_eq!(value_at(&root, "o.b.port").as_deref(), Some("3"));
+
+        let port = root.find("o.b.port").unwrap().value.clone().unwrap();
+        let path = root.path_to_value(&port).unwrap();
+        assert!(std::ptr::eq(
+            root.node_at(&path).unwrap(),
+            root.find("o.b.port").unwrap()
+        ));
+
+        root.clear_changed();
+        assert!(!root.find("o.b.port").unwrap().changed);
+        assert!(!root.merge_config(root.clone()));
+    }
+
+    #[test]
+    fn merge_config_compares_values() {
+        let option_type = wrapped("attrsOf", submodule());
+        let mut root = tree(option_type.clone(), json!({ "resolv.conf": { "port": 1 } }));
+        // `find` can't reach names containing dots
+        let port = &root.find("o").unwrap().children[0].children[0];
+        let path = root.path_to_value(port.value.as_deref().unwrap()).unwrap();
+        assert_eq!(path, [0, 0, 0]);
+
+        // Equal, even though it's shown differently
+        let cfg = json!({ "resolv.conf": { "port": 1.0 } });
+        assert!(!root.merge_config(tree(option_type.clone(), cfg)));
+        let cfg = json!({ "resolv.conf": { "port": 2 } });
+        assert!(root.merge_config(tree(option_type, cfg)));
+        let node = root.node_at(&path).unwrap();
+        assert!(node.changed);
+        assert_eq!(
+            node.value.as_deref().map(|v| v.to_string()).as_deref(),
+            Some("2")
+        );
+    }
+
+    #[test]
+    fn attrs_of_str_stays_a_value() {
+        let option_type = wrapped("attrsOf", nix_type("str", json!({})));
+        let root = tree(option_type, json!({ "a": "text" }));
+        assert!(value_at(&root, "o").is_some());
+        assert!(root.find("o.a").is_none());
+    }
+
+    fn check_errors(option_type: &OptionType, value: Value) -> Vec<String> {
+        let mut errors = Vec::new();
+        option_type.check(&value, "o", &mut errors);
+        errors
+    }
+
+    #[test]
+    fn enum_type() {
+        let mut level = nix_type("enum", json!({}));
+        level["functorPayload"] = json!(["info", "debug"]);
+        let root = tree(level, json!("info"));
+        let level = root.find("o").unwrap().option_type.clone().unwrap();
+        assert!(check_errors(&level, json!("debug")).is_empty());
+        assert_eq!(
+            check_errors(&level, json!("trace")),
+            ["`o`: expected one of [\"info\", \"debug\"], got \"trace\""]
+        );
+
+        let mut number = nix_type("enum", json!({}));
+        number["functorPayload"] = json!([1, 2]);
+        let root = tree(number, json!(1));
+        let number = root.find("o").unwrap().option_type.clone().unwrap();
+        assert!(matches!(number, OptionType::Unknown(_)));
+    }
+
+    #[test]
+    fn check() {
+        use OptionType::*;
+
+        let ports = ListOf(Box::new(Int(Some(0), Some(65535))));
+        assert!(check_errors(&ports, json!([22, 80])).is_empty());
+        assert_eq!(
+            check_errors(&ports, json!([22, -1, "80"])),
+            [
+                "`o[1]`: expected an integer between Some(0) and Some(65535), got -1",
+                "`o[2]`: expected an integer, got \"80\"",
+            ]
+        );
+        assert_eq!(
+            check_errors(&ports, json!(22)),
+            ["`o`: expected a list, got 22"]
+        );
+
+        let either = NullOr(Box::new(Either(Box::new(Bool), Box::new(String))));
+        for value in [json!(null), json!(true), json!("yes")] {
+            assert!(check_errors(&either, value).is_empty());
+        }
+        assert_eq!(
+            check_errors(&either, json!(1)),
+            ["`o`: expected a string, got 1"]
+        );
+
+        let level = Enum(im::vector!["info".to_string(), "debug".to_string()]);
+        assert!(check_errors(&level, json!("debug")).is_empty());
+        assert_eq!(
+            check_errors(&level, json!("trace")),
+            ["`o`: expected one of [\"info\", \"debug\"], got \"trace\""]
+        );
+
+        // Nix code can't be checked
+        let expression = json!({ "_type": "literalExpression", "text": "pkgs.hello" });
+        assert!(check_errors(&Float, expression).is_empty());
+    }
+
+    #[test]
+    fn check_submodule() {
+        let options =
+            serde_json::from_value(json!({ "port": option(nix_type("int", json!({}))) })).unwrap();
+        let children = OptionNode::new("root".to_string(), options).children;
+
+        let strict = OptionType::Submodule(children.clone(), None);
+        assert!(check_errors(&strict, json!({ "port": 22 })).is_empty());
+        assert_eq!(
+            check_errors(&strict, json!({ "port": true, "host": "a" })),
+            [
+                "`o.host`: no such option",
+                "`o.port`: expected an integer, got true",
+            ]
+        );
+
+        let freeform = OptionType::AttrsOf(Box::new(OptionType::String));
+        let settings = OptionType::Submodule(children, Some(Box::new(freeform)));
+        assert!(check_errors(&settings, json!({ "host": "a" })).is_empty());
+        assert_eq!(
+            check_errors(&settings, json!({ "host": 1 })),
+            ["`o.host`: expected a string, got 1"]
+        );
+    }
+
+    /// A submodule with an optional `child` of its own type, like `_typeRef` exports it
+    fn recursive_submodule() -> Value {
+        let child = json!({ "_typeRef": "o", "description": "submodule", "name": "submodule" });
+        json!({
+            "_submodule": true,
+            "_id": "o",
+            "options": {
+                "port": option(nix_type("int", json!({}))),
+                "child": option(wrapped("nullOr", child)),
+            },
+        })
+    }
+
+    #[test]
+    fn recursive_types_unfold_lazily() {
+        let options =
+            serde_json::from_value(json!({ "o": option(recursive_submodule()) })).unwrap();
+        let mut root = OptionNode::new("root".to_string(), options);
+        assert!(root.find("o.child").is_some());
+        assert!(root.find("o.child.port").is_none());
+
+        // Expanding a node unfolds its children one level, not any further
+        let o = root.children.iter_mut().find(|c| c.name == "o").unwrap();
+        o.toggle_expanded();
+        assert!(root.find("o.child.port").is_some());
+        assert!(root.find("o.child.child").is_some());
+        assert!(root.find("o.child.child.port").is_none());
+
+        // A value unfolds as deep as it goes
+        let root = tree(
+            recursive_submodule(),
+            json!({ "port": 1, "child": { "port": 2, "child": { "port": 3 } } }),
+        );
+        assert_eq!(value_at(&root, "o.child.child.port").as_deref(), Some("3"));
+        assert!(root.find("o.child.child.child").is_some());
+        assert!(root.find("o.child.child.child.port").is_none());
+    }
+
+    #[test]
+    fn structured_values_are_trees() {
+        let mut settings = nix_type("oneOf", json!({}));
+        settings["_structured"] = json!(true);
+        settings["description"] = json!("JSON value");
+        let root = tree(
+            settings,
+            json!({ "server": { "port": 80, "hosts": ["a", "b"] }, "debug": true }),
+        );
+
+        let names = |path: &str| -> Vec<String> {
+            let node = root.find(path).unwrap();
+            node.children.iter().map(|c| c.name.clone()).collect()
+        };
+        assert_eq!(names("o"), ["debug", "server"]);
+        assert_eq!(names("o.server.hosts"), ["Element 0", "Element 1"]);
+        assert_eq!(value_at(&root, "o.server.port").as_deref(), Some("80"));
+        assert_eq!(value_at(&root, "o.debug").as_deref(), Some("true"));
+        assert!(matches!(
+            root.find("o.server.hosts.Element 1").unwrap().option_type,
+            Some(OptionType::Structured(ConfigFormat::Json))
+        ));
+    }
+
+    #[test]
+    fn freeform_children() {
+        let settings = json!({
+            "_submodule": true,
+            "options": { "port": option(nix_type("int", json!({}))) },
+            "freeformType": wrapped("attrsOf", nix_type("str", json!({}))),
+        });
+        let root = tree(settings, json!({ "port": 1, "log": "debug", "host": "a" }));
+
+        let o = root.find("o").unwrap();
+        let names: Vec<&str> = o.children.iter().map(|c| c.name.as_str()).collect();
+        // Declared options first, then the undeclared attributes sorted
+        assert_eq!(names, ["port", "host", "log"]);
+        assert!(!root.find("o.port").unwrap().freeform);
+        assert_eq!(value_at(&root, "o.port").as_deref(), Some("1"));
+
+        let log = root.find("o.log").unwrap();
+        assert!(log.freeform);
+        assert!(matches!(log.option_type, Some(OptionType::String)));
+        assert_eq!(value_at(&root, "o.log").as_deref(), Some("\"debug\""));
+    }
+}
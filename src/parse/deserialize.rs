@@ -0,0 +1,876 @@
+//! Deserialization of the JSON written by the Nix scripts.
+//!
+//! Values are told apart by their marker attribute (`_option`, `_type`, `_submodule`,
+//! `_typeRef`, `_derivation`, `_error`, `_function`) instead of trying every variant
+//! like `#[serde(untagged)]` does, so nothing is buffered and errors name the path of
+//! the value that doesn't fit, e.g. ``at `services.nginx.type.nestedTypes.elemType`: …``.
+//!
+//! `builtins.toJSON` sorts attribute names, so the markers starting with `_` come before
+//! the other, lowercase, attributes. Only `_id` and `_structured` may precede the marker
+//! of a type.
+
+use super::*;
+use serde::de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::cell::RefCell;
+use std::fmt;
+
+/// Where a value is in the JSON, only turned into a string for errors
+#[derive(Clone, Copy)]
+enum Path<'a> {
+    Root,
+    Key(&'a Path<'a>, &'a str),
+    Index(&'a Path<'a>, usize),
+}
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Path::Root => Ok(()),
+            Path::Key(parent, key) => {
+                if !matches!(parent, Path::Root) {
+                    write!(f, "{}.", parent)?;
+                }
+                if key.contains('.') {
+                    write!(f, "\"{}\"", key)
+                } else {
+                    write!(f, "{}", key)
+                }
+            }
+            Path::Index(parent, index) => write!(f, "{}[{}]", parent, index),
+        }
+    }
+}
+
+/// Remembers the path of the innermost value that failed, errors are passed up unchanged
+/// and get the path once they leave the deserializer, see `with_path`
+#[derive(Default)]
+struct Context {
+    failed_at: RefCell<Option<String>>,
+}
+
+impl Context {
+    fn fail<E>(&self, path: &Path, error: E) -> E {
+        let mut failed_at = self.failed_at.borrow_mut();
+        if failed_at.is_none() {
+            *failed_at = Some(path.to_string());
+        }
+        error
+    }
+}
+
+fn with_path<'de, D, T>(
+    deserializer: D,
+    deserialize: impl FnOnce(&Context, D) -> Result<T, D::Error>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let context = Context::default();
+    deserialize(&context, deserializer).map_err(|e| match context.failed_at.into_inner() {
+        Some(path) if !path.is_empty() => D::Error::custom(format_args!("at `{}`: {}", path, e)),
+        _ => e,
+    })
+}
+
+/// The value of the entry whose key was just read
+fn value<'de, A, T>(map: &mut A, context: &Context, path: &Path, key: &str) -> Result<T, A::Error>
+where
+    A: MapAccess<'de>,
+    T: Deserialize<'de>,
+{
+    map.next_value()
+        .map_err(|e| context.fail(&Path::Key(path, key), e))
+}
+
+fn skip_rest<'de, A: MapAccess<'de>>(map: &mut A) -> Result<(), A::Error> {
+    while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+    Ok(())
+}
+
+/// `null` as `None`, anything else through the inner seed
+struct Nullable<S>(S);
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Nullable<S> {
+    type Value = Option<S::Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de>> Visitor<'de> for Nullable<S> {
+    type Value = Option<S::Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an optional value")
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.0.deserialize(deserializer).map(Some)
+    }
+}
+
+// Option trees
+
+struct ValueSeed<'a> {
+    context: &'a Context,
+    path: Path<'a>,
+}
+
+impl<'de> Deserialize<'de> for NixValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        with_path(deserializer, |context, deserializer| {
+            let path = Path::Root;
+            ValueSeed { context, path }.deserialize(deserializer)
+        })
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for ValueSeed<'a> {
+    type Value = NixValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let (context, path) = (self.context, self.path);
+        deserializer
+            .deserialize_map(self)
+            .map_err(|e| context.fail(&path, e))
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for ValueSeed<'a> {
+    type Value = NixValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an option or a set of options")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (context, path) = (self.context, &self.path);
+        match map.next_key::<String>()? {
+            Some(key) if key == "_option" => {
+                map.next_value::<IgnoredAny>()?;
+                option(&mut map, context, path).map(NixValue::Option)
+            }
+            key => options(&mut map, context, path, key).map(NixValue::Set),
+        }
+    }
+}
+
+/// The options of a submodule
+struct OptionsSeed<'a> {
+    context: &'a Context,
+    path: Path<'a>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for OptionsSeed<'a> {
+    type Value = NixSet;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let (context, path) = (self.context, self.path);
+        deserializer
+            .deserialize_map(self)
+            .map_err(|e| context.fail(&path, e))
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for OptionsSeed<'a> {
+    type Value = NixSet;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a set of options")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        options(&mut map, self.context, &self.path, None)
+    }
+}
+
+/// The rest of a set of options, `first` is the name that was read already
+fn options<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+    first: Option<String>,
+) -> Result<NixSet, A::Error> {
+    let mut set = NixSet::with_capacity(map.size_hint().map_or(1, |n| n + 1));
+    let mut key = match first {
+        Some(first) => Some(first),
+        None => map.next_key()?,
+    };
+    while let Some(name) = key {
+        let val = map.next_value_seed(ValueSeed {
+            context,
+            path: Path::Key(path, &name),
+        })?;
+        set.insert(name, Box::new(val));
+        key = map.next_key()?;
+    }
+    Ok(set)
+}
+
+/// The attributes of an option after `_option`
+fn option<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+) -> Result<NixOption, A::Error> {
+    let mut description = None;
+    let mut option_type = None;
+    let (mut default, mut example) = (None, None);
+    let mut declarations = Vec::new();
+    let (mut alias_kind, mut alias_target, mut removal_message) = (None, None, None);
+
+    while let Some(key) = map.next_key::<String>()? {
+        let guarded = || {
+            Nullable(GuardedSeed {
+                context,
+                path: Path::Key(path, &key),
+            })
+        };
+        match key.as_str() {
+            "description" => description = Some(value(map, context, path, &key)?),
+            "type" => {
+                option_type = Some(map.next_value_seed(TypeSeed {
+                    context,
+                    path: Path::Key(path, &key),
+                })?)
+            }
+            "default" => default = map.next_value_seed(guarded())?,
+            "example" => example = map.next_value_seed(guarded())?,
+            "declarations" => declarations = value(map, context, path, &key)?,
+            "aliasKind" => alias_kind = value(map, context, path, &key)?,
+            "aliasTarget" => alias_target = value(map, context, path, &key)?,
+            "removalMessage" => removal_message = value(map, context, path, &key)?,
+            _ => {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+    }
+
+    Ok(NixOption {
+        _option: true,
+        description: description.ok_or_else(|| A::Error::missing_field("description"))?,
+        r#type: option_type.ok_or_else(|| A::Error::missing_field("type"))?,
+        default,
+        example,
+        declarations,
+        alias_kind,
+        alias_target,
+        removal_message,
+    })
+}
+
+// Types
+
+struct TypeSeed<'a> {
+    context: &'a Context,
+    path: Path<'a>,
+}
+
+impl<'de> Deserialize<'de> for NixTypeValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        with_path(deserializer, |context, deserializer| {
+            let path = Path::Root;
+            TypeSeed { context, path }.deserialize(deserializer)
+        })
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for TypeSeed<'a> {
+    type Value = NixTypeValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let (context, path) = (self.context, self.path);
+        deserializer
+            .deserialize_map(self)
+            .map_err(|e| context.fail(&path, e))
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for TypeSeed<'a> {
+    type Value = NixTypeValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an option type")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        const MARKERS: &str = "one of `_type`, `_submodule` or `_typeRef`";
+        let (context, path) = (self.context, &self.path);
+        let mut id = String::new();
+        let mut structured = false;
+
+        loop {
+            let key = map
+                .next_key::<String>()?
+                .ok_or_else(|| A::Error::custom(format_args!("expected {}", MARKERS)))?;
+            match key.as_str() {
+                "_id" => id = value(&mut map, context, path, &key)?,
+                "_structured" => structured = value(&mut map, context, path, &key)?,
+                "_type" => {
+                    map.next_value::<IgnoredAny>()?;
+                    return nix_type(&mut map, context, path, id, structured)
+                        .map(NixTypeValue::Type);
+                }
+                "_submodule" => {
+                    map.next_value::<IgnoredAny>()?;
+                    return submodule(&mut map, context, path, id).map(NixTypeValue::Submodule);
+                }
+                "_typeRef" => {
+                    let id = value(&mut map, context, path, &key)?;
+                    return type_ref(&mut map, context, path, id).map(NixTypeValue::Reference);
+                }
+                other => {
+                    return Err(A::Error::custom(format_args!(
+                        "expected {} before `{}`",
+                        MARKERS, other
+                    )))
+                }
+            }
+        }
+    }
+}
+
+fn nix_type<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+    id: String,
+    structured: bool,
+) -> Result<NixType, A::Error> {
+    let (mut description, mut functor_name, mut name) = (None, None, None);
+    let mut nested_types = None;
+    let mut functor_payload = Vec::new();
+
+    while let Some(key) = map.next_key::<String>()? {
+        match key.as_str() {
+            "description" => description = Some(value(map, context, path, &key)?),
+            "functorName" => functor_name = Some(value(map, context, path, &key)?),
+            "name" => name = Some(value(map, context, path, &key)?),
+            "functorPayload" => functor_payload = value(map, context, path, &key)?,
+            "nestedTypes" => {
+                nested_types = Some(map.next_value_seed(NestedTypesSeed {
+                    context,
+                    path: Path::Key(path, &key),
+                })?)
+            }
+            _ => {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+    }
+
+    Ok(NixType {
+        _type: true,
+        id,
+        description: description.ok_or_else(|| A::Error::missing_field("description"))?,
+        functorName: functor_name.ok_or_else(|| A::Error::missing_field("functorName"))?,
+        name: name.ok_or_else(|| A::Error::missing_field("name"))?,
+        nestedTypes: nested_types.ok_or_else(|| A::Error::missing_field("nestedTypes"))?,
+        functorPayload: functor_payload,
+        structured,
+    })
+}
+
+/// The `nestedTypes` of a type, e.g. `elemType`
+struct NestedTypesSeed<'a> {
+    context: &'a Context,
+    path: Path<'a>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for NestedTypesSeed<'a> {
+    type Value = HashMap<String, NixTypeValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let (context, path) = (self.context, self.path);
+        deserializer
+            .deserialize_map(self)
+            .map_err(|e| context.fail(&path, e))
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for NestedTypesSeed<'a> {
+    type Value = HashMap<String, NixTypeValue>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a set of option types")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (context, path) = (self.context, &self.path);
+        let mut types = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(key) = map.next_key::<String>()? {
+            let val = map.next_value_seed(TypeSeed {
+                context,
+                path: Path::Key(path, &key),
+            })?;
+            types.insert(key, val);
+        }
+        Ok(types)
+    }
+}
+
+fn submodule<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+    id: String,
+) -> Result<NixSubmodule, A::Error> {
+    let mut options = None;
+    let mut freeform_type = None;
+
+    while let Some(key) = map.next_key::<String>()? {
+        let path = Path::Key(path, &key);
+        match key.as_str() {
+            "options" => {
+                options = Some(map.next_value_seed(OptionsSeed { context, path })?);
+            }
+            "freeformType" => {
+                freeform_type = map
+                    .next_value_seed(Nullable(TypeSeed { context, path }))?
+                    .map(Box::new)
+            }
+            _ => {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+    }
+
+    Ok(NixSubmodule {
+        _submodule: true,
+        id,
+        options: options.ok_or_else(|| A::Error::missing_field("options"))?,
+        freeform_type,
+    })
+}
+
+fn type_ref<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+    id: String,
+) -> Result<NixTypeRef, A::Error> {
+    let (mut name, mut description) = (None, None);
+
+    while let Some(key) = map.next_key::<String>()? {
+        match key.as_str() {
+            "name" => name = Some(value(map, context, path, &key)?),
+            "description" => description = Some(value(map, context, path, &key)?),
+            _ => {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+    }
+
+    Ok(NixTypeRef {
+        id,
+        name: name.ok_or_else(|| A::Error::missing_field("name"))?,
+        description: description.ok_or_else(|| A::Error::missing_field("description"))?,
+    })
+}
+
+// Values
+
+struct GuardedSeed<'a> {
+    context: &'a Context,
+    path: Path<'a>,
+}
+
+impl<'de> Deserialize<'de> for NixGuardedValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        with_path(deserializer, |context, deserializer| {
+            let path = Path::Root;
+            GuardedSeed { context, path }.deserialize(deserializer)
+        })
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for GuardedSeed<'a> {
+    type Value = NixGuardedValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let (context, path) = (self.context, self.path);
+        deserializer
+            .deserialize_any(self)
+            .map_err(|e| context.fail(&path, e))
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for GuardedSeed<'a> {
+    type Value = NixGuardedValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value guarded by `utilities.nix`")
+    }
+
+    fn visit_bool<E: Error>(self, b: bool) -> Result<Self::Value, E> {
+        Ok(NixGuardedValue::Bool(b))
+    }
+
+    fn visit_i64<E: Error>(self, n: i64) -> Result<Self::Value, E> {
+        Ok(NixGuardedValue::Number(n))
+    }
+
+    fn visit_u64<E: Error>(self, n: u64) -> Result<Self::Value, E> {
+        Ok(match i64::try_from(n) {
+            Ok(n) => NixGuardedValue::Number(n),
+            Err(_) => NixGuardedValue::Float(n as f64),
+        })
+    }
+
+    fn visit_f64<E: Error>(self, n: f64) -> Result<Self::Value, E> {
+        Ok(NixGuardedValue::Float(n))
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<Self::Value, E> {
+        Ok(NixGuardedValue::String(s.to_owned()))
+    }
+
+    fn visit_string<E: Error>(self, s: String) -> Result<Self::Value, E> {
+        Ok(NixGuardedValue::String(s))
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(NixGuardedValue::Null(()))
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(NixGuardedValue::Null(()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let (context, path) = (self.context, &self.path);
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(val) = seq.next_element_seed(GuardedSeed {
+            context,
+            path: Path::Index(path, list.len()),
+        })? {
+            list.push(Box::new(val));
+        }
+        Ok(NixGuardedValue::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (context, path) = (self.context, &self.path);
+        let first = match map.next_key::<String>()? {
+            Some(key) => key,
+            None => return Ok(NixGuardedValue::Attrs(HashMap::new())),
+        };
+
+        match first.as_str() {
+            "_derivation" => {
+                map.next_value::<IgnoredAny>()?;
+                derivation(&mut map, context, path).map(NixGuardedValue::Derivation)
+            }
+            "_error" => {
+                map.next_value::<IgnoredAny>()?;
+                error(&mut map, context, path).map(NixGuardedValue::Error)
+            }
+            "_function" => {
+                map.next_value::<IgnoredAny>()?;
+                skip_rest(&mut map)?;
+                Ok(NixGuardedValue::Function(NixFunction { _function: true }))
+            }
+            // Option types are marked with `true`, `literalExpression`s and the like with their
+            // kind, other sets with a `_type` are kept as they are
+            "_type" => {
+                let kind = map.next_value_seed(GuardedSeed {
+                    context,
+                    path: Path::Key(path, &first),
+                })?;
+                if let NixGuardedValue::Bool(_) = kind {
+                    skip_rest(&mut map)?;
+                    return Ok(NixGuardedValue::OptionType(NixGuardedOptionType {
+                        _type: true,
+                    }));
+                }
+                let mut attrs = attrs(&mut map, context, path, None)?;
+                match (kind, attrs.remove("text")) {
+                    (NixGuardedValue::String(kind), Some(text)) if attrs.is_empty() => {
+                        match *text {
+                            NixGuardedValue::String(text) => {
+                                let literal = NixLiteralExpression { _type: kind, text };
+                                Ok(NixGuardedValue::LiteralExpression(literal))
+                            }
+                            text => {
+                                attrs.insert("text".to_string(), Box::new(text));
+                                attrs.insert(first, Box::new(NixGuardedValue::String(kind)));
+                                Ok(NixGuardedValue::Attrs(attrs))
+                            }
+                        }
+                    }
+                    (kind, text) => {
+                        if let Some(text) = text {
+                            attrs.insert("text".to_string(), text);
+                        }
+                        attrs.insert(first, Box::new(kind));
+                        Ok(NixGuardedValue::Attrs(attrs))
+                    }
+                }
+            }
+            _ => attrs(&mut map, context, path, Some(first)).map(NixGuardedValue::Attrs),
+        }
+    }
+}
+
+/// The rest of a plain attribute set, `first` is the attribute name that was read already
+fn attrs<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+    first: Option<String>,
+) -> Result<HashMap<String, Box<NixGuardedValue>>, A::Error> {
+    let mut attrs = HashMap::with_capacity(map.size_hint().map_or(1, |n| n + 1));
+    let mut key = match first {
+        Some(first) => Some(first),
+        None => map.next_key()?,
+    };
+    while let Some(name) = key {
+        let val = map.next_value_seed(GuardedSeed {
+            context,
+            path: Path::Key(path, &name),
+        })?;
+        attrs.insert(name, Box::new(val));
+        key = map.next_key()?;
+    }
+    Ok(attrs)
+}
+
+fn derivation<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+) -> Result<NixDerivation, A::Error> {
+    let mut name = None;
+    let (mut pname, mut version, mut out_path) = (None, None, None);
+    let mut meta = NixDerivationMeta::default();
+
+    while let Some(key) = map.next_key::<String>()? {
+        match key.as_str() {
+            "name" => name = Some(value(map, context, path, &key)?),
+            "pname" => pname = value(map, context, path, &key)?,
+            "version" => version = value(map, context, path, &key)?,
+            "outPath" => out_path = value(map, context, path, &key)?,
+            "meta" => meta = value(map, context, path, &key)?,
+            _ => {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+    }
+
+    Ok(NixDerivation {
+        _derivation: true,
+        name: name.ok_or_else(|| A::Error::missing_field("name"))?,
+        pname,
+        version,
+        in_local_store: super::in_local_store(out_path.as_ref()),
+        out_path,
+        meta,
+    })
+}
+
+fn error<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    context: &Context,
+    path: &Path,
+) -> Result<NixTryEvalError, A::Error> {
+    let mut error_path = Vec::new();
+    let mut details = None;
+
+    while let Some(key) = map.next_key::<String>()? {
+        match key.as_str() {
+            "path" => error_path = value(map, context, path, &key)?,
+            "details" => details = value(map, context, path, &key)?,
+            _ => {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+    }
+
+    Ok(NixTryEvalError {
+        _error: true,
+        path: error_path,
+        details,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_of<T: for<'de> Deserialize<'de>>(json: &str) -> String {
+        match serde_json::from_str::<T>(json) {
+            Ok(_) => panic!("`{}` should not parse", json),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn guarded_values() {
+        let val: NixGuardedValue = serde_json::from_str(
+            r#"{
+                "drv": { "_derivation": true, "name": "hello-2.12", "outPath": "/nix/store/x" },
+                "error": { "_error": true, "path": ["a", 0] },
+                "f": { "_function": true },
+                "literal": { "_type": "literalExpression", "text": "pkgs.hello" },
+                "list": [1, 1.5, "s", true, null, 18446744073709551615],
+                "type": { "_type": true, "name": "str" }
+            }"#,
+        )
+        .unwrap();
+
+        let shown = val.to_string();
+        assert!(shown.contains("<build of hello-2.12>"));
+        assert!(shown.contains("<function>"));
+        assert!(shown.contains("<option type>"));
+        assert!(shown.contains("```pkgs.hello```"));
+        assert_eq!(val.errors()[0].path.len(), 2);
+        match val {
+            NixGuardedValue::Attrs(attrs) => match attrs["list"].as_ref() {
+                NixGuardedValue::List(list) => {
+                    assert!(matches!(*list[0], NixGuardedValue::Number(1)));
+                    assert!(matches!(*list[4], NixGuardedValue::Null(_)));
+                    assert!(matches!(*list[5], NixGuardedValue::Float(_)));
+                }
+                other => panic!("Expected a list, got {:?}", other),
+            },
+            other => panic!("Expected attributes, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn derivations() {
+        let derivation = |json: &str| match serde_json::from_str(json).unwrap() {
+            NixGuardedValue::Derivation(d) => d,
+            other => panic!("Expected a derivation, got {:?}", other),
+        };
+
+        let hello = derivation(
+            r#"{
+                "_derivation": true,
+                "name": "hello-2.12",
+                "pname": "hello",
+                "version": "2.12",
+                "outPath": "/nix/store/00000000000000000000000000000000-hello-2.12",
+                "drvPath": "/nix/store/00000000000000000000000000000000-hello-2.12.drv",
+                "meta": {
+                    "description": "A program that produces a familiar, friendly greeting",
+                    "homepage": "https://www.gnu.org/software/hello/manual/",
+                    "license": ["GPL-3.0-or-later"],
+                    "maintainers": ["eelco"]
+                }
+            }"#,
+        );
+        assert_eq!(hello.name, "hello-2.12");
+        assert_eq!(hello.pname.as_deref(), Some("hello"));
+        assert_eq!(hello.version.as_deref(), Some("2.12"));
+        assert_eq!(
+            hello.out_path.as_deref(),
+            Some("/nix/store/00000000000000000000000000000000-hello-2.12")
+        );
+        assert_eq!(hello.meta.license, ["GPL-3.0-or-later"]);
+        assert_eq!(hello.meta.maintainers, ["eelco"]);
+        assert!(hello.meta.homepage.is_some());
+        assert!(!hello.in_local_store);
+
+        let minimal = derivation(r#"{ "_derivation": true, "name": "source" }"#);
+        assert!(minimal.pname.is_none() && minimal.out_path.is_none());
+        assert!(minimal.meta.description.is_none() && minimal.meta.license.is_empty());
+        assert!(!minimal.in_local_store);
+
+        let existing = derivation(r#"{ "_derivation": true, "name": "root", "outPath": "/" }"#);
+        assert!(existing.in_local_store);
+    }
+
+    #[test]
+    fn options() {
+        let val: NixValue = serde_json::from_str(
+            r#"{
+                "services": { "web": {
+                    "_option": true,
+                    "default": null,
+                    "description": "Web servers",
+                    "type": {
+                        "_id": "services.web",
+                        "_type": true,
+                        "description": "attribute set of submodules",
+                        "functorName": "attrsOf",
+                        "name": "attrsOf",
+                        "nestedTypes": { "elemType": {
+                            "_id": "services.web/elemType",
+                            "_submodule": true,
+                            "freeformType": null,
+                            "options": { "port": {
+                                "_option": true,
+                                "default": 80,
+                                "description": "Port",
+                                "type": { "_typeRef": "services.web", "description": "recursive", "name": "attrsOf" }
+                            } }
+                        } }
+                    }
+                } }
+            }"#,
+        )
+        .unwrap();
+
+        let web = match val {
+            NixValue::Set(mut set) => match *set.remove("services").unwrap() {
+                NixValue::Set(mut set) => set.remove("web").unwrap(),
+                other => panic!("Expected a set, got {:?}", other),
+            },
+            other => panic!("Expected a set, got {:?}", other),
+        };
+        match *web {
+            NixValue::Option(opt) => {
+                assert!(opt.default.is_none());
+                let mut refs = Vec::new();
+                opt.r#type.visit(&mut |t| {
+                    if let NixTypeValue::Reference(r) = t {
+                        refs.push(r.id.clone());
+                    }
+                });
+                assert_eq!(refs, ["services.web"]);
+                let elem = match opt.r#type {
+                    NixTypeValue::Type(t) => t.nestedTypes["elemType"].clone(),
+                    other => panic!("Expected a type, got {:?}", other),
+                };
+                let port = &elem.get_submodule().unwrap().options["port"];
+                assert!(matches!(**port, NixValue::Option(_)));
+            }
+            other => panic!("Expected an option, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_name_the_path() {
+        let e = error_of::<NixValue>(
+            r#"{ "a": { "b": { "_option": true, "description": "", "type": { "_type": true, "name": 1 } } } }"#,
+        );
+        assert!(e.starts_with("at `a.b.type.name`: invalid type"), "{}", e);
+
+        let e = error_of::<NixValue>(r#"{ "a": { "_option": true, "description": "" } }"#);
+        assert!(e.starts_with("at `a`: missing field `type`"), "{}", e);
+
+        let e = error_of::<NixTypeValue>(r#"{ "description": "", "name": "str" }"#);
+        assert!(
+            e.contains("expected one of `_type`, `_submodule` or `_typeRef` before `description`"),
+            "{}",
+            e
+        );
+
+        let e = error_of::<NixGuardedValue>(r#"{ "a.b": [ 1, { "_derivation": true } ] }"#);
+        assert!(
+            e.starts_with(r#"at `"a.b"[1]`: missing field `name`"#),
+            "{}",
+            e
+        );
+    }
+}
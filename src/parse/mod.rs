@@ -0,0 +1,602 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::boxed::Box;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+
+mod deserialize;
+pub mod snapshot;
+
+#[derive(Debug, Clone)]
+pub struct NixGuardedOptionType {
+    _type: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct NixDerivation {
+    _derivation: bool,
+    pub name: String,
+    pub pname: Option<String>,
+    pub version: Option<String>,
+    /// Store path of the default output, it doesn't have to be built
+    pub out_path: Option<String>,
+    pub meta: NixDerivationMeta,
+    /// Whether the default output is built or substituted already, checked when loaded
+    pub in_local_store: bool,
+}
+
+/// See `NixDerivation::in_local_store`
+fn in_local_store(out_path: Option<&String>) -> bool {
+    out_path.is_some_and(|path| std::path::Path::new(path).exists())
+}
+
+/// The parts of `meta` that `guardDerivation` in `utilities.nix` exports
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct NixDerivationMeta {
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    /// SPDX identifiers where available, otherwise the short name
+    #[serde(default)]
+    pub license: Vec<String>,
+    /// GitHub handles where available, otherwise the name
+    #[serde(default)]
+    pub maintainers: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NixFunction {
+    _function: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct NixTryEvalError {
+    _error: bool,
+    /// Where the error is in the evaluated value, see `catchErrors` in `utilities.nix`
+    pub path: Vec<NixPathSegment>,
+    /// Only known after evaluating `path` again, see `run::get_error_details`
+    pub details: Option<NixErrorDetails>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum NixPathSegment {
+    Index(usize),
+    Name(String),
+}
+
+/// Shows a path like `users.users.alice.extraGroups[0]`
+pub fn show_path(path: &[NixPathSegment]) -> String {
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            NixPathSegment::Index(i) => out.push_str(&format!("[{}]", i)),
+            NixPathSegment::Name(name) => {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+                if name.contains('.') {
+                    out.push_str(&format!("\"{}\"", name));
+                } else {
+                    out.push_str(name);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NixErrorDetails {
+    pub message: String,
+    /// `file:line:column` of the error, if Nix reports it
+    pub position: Option<String>,
+}
+
+impl NixErrorDetails {
+    /// Get the message and position from the error output of `nix-instantiate`.
+    /// Newer Nix versions print a trace before the message, the last position in it
+    /// is closest to where the error was thrown.
+    pub fn from_stderr(stderr: &str) -> Self {
+        let message = match stderr.rfind("error:") {
+            Some(start) => stderr[start + "error:".len()..]
+                .lines()
+                .map(str::trim)
+                .skip_while(|line| line.is_empty())
+                .take_while(|line| {
+                    !line.is_empty() && !line.starts_with("at ") && !line.starts_with("(use ")
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => stderr.trim().to_string(),
+        };
+        let position = stderr
+            .lines()
+            .map(str::trim)
+            .filter_map(|line| line.strip_prefix("at "))
+            .next_back()
+            .map(|pos| pos.trim_end_matches(':').to_string());
+
+        Self { message, position }
+    }
+}
+
+impl std::fmt::Display for NixErrorDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(ref pos) = self.position {
+            write!(f, "\nat {}", pos)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NixLiteralExpression {
+    // Needs to be "literalExpression"
+    _type: String,
+    pub text: String,
+}
+
+#[derive(Clone)]
+pub enum NixGuardedValue {
+    LiteralExpression(NixLiteralExpression),
+    Function(NixFunction),
+    Error(NixTryEvalError),
+    Derivation(NixDerivation),
+    OptionType(NixGuardedOptionType),
+
+    // Recursive variants
+    Attrs(HashMap<String, Box<NixGuardedValue>>),
+    List(Vec<Box<NixGuardedValue>>),
+
+    // Basic
+    String(String),
+    Number(i64),
+    Float(f64),
+    Bool(bool),
+    Null(()),
+
+    // Maybe turn all of the basic and recursive variants into an "other"
+    // value and keep them as a serialized JSON string?
+    // Need to handle nested derivations, etc. though
+}
+
+impl NixGuardedValue {
+    /// Compare two values, returns `None` if this cannot be decided.
+    /// This is the case for functions, errors and unevaluated `literalExpression`s.
+    pub fn compare(&self, other: &Self) -> Option<bool> {
+        use NixGuardedValue::*;
+
+        match (self, other) {
+            (LiteralExpression(_), _) | (_, LiteralExpression(_)) => None,
+            (Function(_), _) | (_, Function(_)) => None,
+            (Error(_), _) | (_, Error(_)) => None,
+            (OptionType(_), _) | (_, OptionType(_)) => None,
+
+            (Derivation(l), Derivation(r)) => Some(l.name == r.name),
+            (String(l), String(r)) => Some(l == r),
+            (Number(l), Number(r)) => Some(l == r),
+            (Float(l), Float(r)) => Some(l == r),
+            (Number(l), Float(r)) | (Float(r), Number(l)) => Some(*l as f64 == *r),
+            (Bool(l), Bool(r)) => Some(l == r),
+            (Null(_), Null(_)) => Some(true),
+
+            (Attrs(l), Attrs(r)) => {
+                if l.len() != r.len() {
+                    return Some(false);
+                }
+                Self::compare_all(l.iter().map(|(k, v)| r.get(k).map(|o| (v, o))))
+            }
+            (List(l), List(r)) => {
+                if l.len() != r.len() {
+                    return Some(false);
+                }
+                Self::compare_all(l.iter().zip(r.iter()).map(Some))
+            }
+
+            _ => Some(false),
+        }
+    }
+
+    /// All errors in this value, e.g. to get their details with `run::get_error_details`
+    pub fn errors_mut(&mut self) -> Vec<&mut NixTryEvalError> {
+        let mut errors = Vec::new();
+        self.collect_errors(&mut errors);
+        errors
+    }
+
+    fn collect_errors<'a>(&'a mut self, errors: &mut Vec<&'a mut NixTryEvalError>) {
+        use NixGuardedValue::*;
+
+        match self {
+            Error(e) => errors.push(e),
+            Attrs(attrs) => attrs.values_mut().for_each(|v| v.collect_errors(errors)),
+            List(list) => list.iter_mut().for_each(|v| v.collect_errors(errors)),
+            _ => (),
+        }
+    }
+
+    /// Set the details of the error at `path`, returns whether it had none yet
+    pub fn explain_error(&mut self, path: &[NixPathSegment], details: &NixErrorDetails) -> bool {
+        let error = self
+            .errors_mut()
+            .into_iter()
+            .find(|e| e.path == path && e.details.is_none());
+        match error {
+            Some(error) => {
+                error.details = Some(details.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn errors(&self) -> Vec<&NixTryEvalError> {
+        use NixGuardedValue::*;
+
+        match self {
+            Error(e) => vec![e],
+            Attrs(attrs) => attrs.values().flat_map(|v| v.errors()).collect(),
+            List(list) => list.iter().flat_map(|v| v.errors()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// All packages in this value, e.g. the ones of `environment.systemPackages`
+    pub fn derivations(&self) -> Vec<&NixDerivation> {
+        use NixGuardedValue::*;
+
+        match self {
+            Derivation(d) => vec![d],
+            Attrs(attrs) => attrs.values().flat_map(|v| v.derivations()).collect(),
+            List(list) => list.iter().flat_map(|v| v.derivations()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// A definite difference wins over an undecidable comparison.
+    /// `None` as item means the pair has no counterpart and is thus different.
+    fn compare_all<'a, I>(pairs: I) -> Option<bool>
+    where
+        I: Iterator<Item = Option<(&'a Box<NixGuardedValue>, &'a Box<NixGuardedValue>)>>,
+    {
+        let mut decidable = true;
+        for pair in pairs {
+            match pair.map(|(l, r)| l.compare(r)) {
+                None | Some(Some(false)) => return Some(false),
+                Some(None) => decidable = false,
+                Some(Some(true)) => (),
+            }
+        }
+
+        if decidable {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for NixGuardedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Simply use the pretty-print output of `Debug`
+        write!(f, "{}", format!("{self:#?}"))
+    }
+}
+
+impl std::fmt::Debug for NixGuardedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use NixGuardedValue::*;
+
+        match self {
+            OptionType(_) => write!(f, "<option type>"),
+            Function(_) => write!(f, "<function>"),
+            Derivation(d) => write!(f, "<build of {}>", d.name),
+            Error(NixTryEvalError {
+                details: Some(d), ..
+            }) => write!(f, "<error: {}>", d.message),
+            Error(_) => write!(f, "<error>"),
+            LiteralExpression(e) => write!(f, "```{}```", e.text),
+
+            String(s) => write!(f, "\"{}\"", s),
+            Number(n) => write!(f, "{}", n),
+            Float(n) => write!(f, "{}", n),
+            Bool(b) => write!(f, "{}", b),
+            Null(_) => write!(f, "null"),
+
+
+            Attrs(children) => {
+                f.debug_map().entries(children.iter()).finish()
+            },
+            List(children) => {
+                f.debug_list().entries(children.iter()).finish()
+            },
+
+            // other => write!(f, "{:#?}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NixSubmodule {
+    _submodule: bool,
+    /// Identity of this type, see `NixTypeRef`
+    pub id: String,
+    pub options: NixSet,
+    /// The type of attributes that aren't declared as options, e.g. for `settings` options
+    pub freeform_type: Option<Box<NixTypeValue>>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(non_snake_case)]
+pub struct NixType {
+    _type: bool,
+    /// Identity of this type, see `NixTypeRef`
+    pub id: String,
+    pub description: String,
+    functorName: String,
+    pub name: String,
+    pub nestedTypes: HashMap<String,NixTypeValue>,
+
+    /// The allowed values of an `enum`
+    pub functorPayload: Vec<Value>,
+    /// One of the JSON, YAML or TOML value types, their nested types aren't exported
+    pub structured: bool,
+}
+
+/// A type nested in itself, e.g. a submodule with an option of its own type.
+/// Instead of repeating the type this refers to the enclosing type with the same `id`,
+/// see `NixValue::type_graph`.
+#[derive(Debug, Clone)]
+pub struct NixTypeRef {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum NixTypeValue {
+    Type(NixType),
+    Submodule(NixSubmodule),
+    Reference(NixTypeRef),
+}
+
+impl NixTypeValue {
+    pub fn get_submodule(&self) -> Option<&NixSubmodule> {
+        match self {
+            NixTypeValue::Submodule(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The identity of this type, references have the identity of the type they refer to
+    pub fn id(&self) -> &str {
+        match self {
+            NixTypeValue::Type(t) => &t.id,
+            NixTypeValue::Submodule(s) => &s.id,
+            NixTypeValue::Reference(r) => &r.id,
+        }
+    }
+
+    /// Call `f` on this type and every type nested in it, including the types of submodule options
+    pub fn visit(&self, f: &mut dyn FnMut(&NixTypeValue)) {
+        f(self);
+        match self {
+            NixTypeValue::Type(t) => t.nestedTypes.values().for_each(|t| t.visit(f)),
+            NixTypeValue::Submodule(s) => {
+                s.options.values().for_each(|v| v.visit_types(f));
+                if let Some(ref t) = s.freeform_type {
+                    t.visit(f);
+                }
+            }
+            NixTypeValue::Reference(_) => (),
+        }
+    }
+}
+
+impl std::fmt::Display for NixTypeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use NixTypeValue::*;
+
+        match self {
+            Type(t) => write!(f, "{}", t.description),
+            Submodule(NixSubmodule {
+                freeform_type: Some(t),
+                ..
+            }) => write!(f, "submodule with freeform {}", t),
+            Submodule(_) => write!(f, "submodule"),
+            Reference(r) => write!(f, "{} (recursive)", r.description),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NixOption {
+    _option: bool,
+    pub description: String,
+    pub r#type: NixTypeValue,
+    pub default: Option<NixGuardedValue>,
+    pub example: Option<NixGuardedValue>,
+    /// Files of the modules declaring this option
+    pub declarations: Vec<String>,
+    /// Set for aliases and options that are renamed or removed
+    pub alias_kind: Option<NixAliasKind>,
+    /// The option an alias or renamed option refers to
+    pub alias_target: Option<String>,
+    pub removal_message: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NixAliasKind {
+    /// Made by `mkAliasOptionModule`, still fully supported
+    Alias,
+    /// Made by `mkRenamedOptionModule`, gives a warning when used
+    Renamed,
+    /// Made by `mkRemovedOptionModule`, gives an error when used
+    Removed,
+}
+
+/// An option defined in a config, e.g. a renamed or removed one that is still in use
+#[derive(Deserialize, Debug, Clone)]
+pub struct NixDefinition {
+    pub loc: Vec<String>,
+    /// The files defining the option
+    pub files: Vec<String>,
+}
+
+/// Messages of the failing `assertions` and the `warnings` of a config,
+/// either of them or single messages can be errors
+#[derive(Deserialize, Debug, Clone)]
+pub struct NixConfigChecks {
+    pub assertions: NixGuardedValue,
+    pub warnings: NixGuardedValue,
+}
+
+#[derive(Debug, Clone)]
+pub enum NixValue {
+    Option(NixOption),
+    Set(NixSet),
+}
+
+pub type NixSet = HashMap<String, Box<NixValue>>;
+
+/// The types that `NixTypeRef`s refer to, by their id
+pub type NixTypeGraph = HashMap<String, NixTypeValue>;
+
+impl NixValue {
+    /// Call `f` on the types of all options, see `NixTypeValue::visit`
+    pub fn visit_types(&self, f: &mut dyn FnMut(&NixTypeValue)) {
+        match self {
+            NixValue::Option(opt) => opt.r#type.visit(f),
+            NixValue::Set(set) => set.values().for_each(|v| v.visit_types(f)),
+        }
+    }
+
+    /// Collect the types that recursive types refer to, these are the only ones needed
+    /// to expand a `NixTypeRef` again
+    pub fn type_graph(&self) -> NixTypeGraph {
+        let mut referenced = HashSet::new();
+        self.visit_types(&mut |t| {
+            if let NixTypeValue::Reference(r) = t {
+                referenced.insert(r.id.clone());
+            }
+        });
+
+        let mut graph = NixTypeGraph::new();
+        if referenced.is_empty() {
+            return graph;
+        }
+        self.visit_types(&mut |t| match t {
+            NixTypeValue::Reference(_) => (),
+            _ if referenced.contains(t.id()) && !graph.contains_key(t.id()) => {
+                graph.insert(t.id().to_owned(), t.clone());
+            }
+            _ => (),
+        });
+        graph
+    }
+}
+
+pub fn get_root() -> NixValue {
+    let file = File::open("/tmp/nixos.json").unwrap();
+    return serde_json::from_reader(BufReader::new(file)).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn value(json: serde_json::Value) -> NixGuardedValue {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn compare(left: serde_json::Value, right: serde_json::Value) -> Option<bool> {
+        value(left).compare(&value(right))
+    }
+
+    #[test]
+    fn error_details_with_trace() {
+        let stderr = "error:
+       … while evaluating the attribute 'config.services.foo'
+
+         at /nix/store/x-source/lib/modules.nix:505:28:
+
+          504|         addErrorContext (context name)
+             |                            ^
+
+       error: The option `services.foo' does not exist.
+       Definition values:
+       - In `/etc/nixos/configuration.nix'
+";
+        let details = NixErrorDetails::from_stderr(stderr);
+        assert_eq!(
+            details.message,
+            "The option `services.foo' does not exist.\nDefinition values:\n- In `/etc/nixos/configuration.nix'"
+        );
+        assert_eq!(
+            details.position.as_deref(),
+            Some("/nix/store/x-source/lib/modules.nix:505:28")
+        );
+    }
+
+    #[test]
+    fn error_details_without_trace() {
+        let stderr = "error: cannot coerce a set to a string
+(use '--show-trace' to show detailed location information)
+";
+        let details = NixErrorDetails::from_stderr(stderr);
+        assert_eq!(details.message, "cannot coerce a set to a string");
+        assert!(details.position.is_none());
+
+        let details = NixErrorDetails::from_stderr("  killed by a signal\n");
+        assert_eq!(details.message, "killed by a signal");
+    }
+
+    #[test]
+    fn compare_values() {
+        assert_eq!(compare(json!("a"), json!("a")), Some(true));
+        assert_eq!(compare(json!("a"), json!("b")), Some(false));
+        assert_eq!(compare(json!(1), json!(1.0)), Some(true));
+        assert_eq!(compare(json!(1), json!("1")), Some(false));
+        assert_eq!(compare(json!(null), json!(null)), Some(true));
+        assert_eq!(compare(json!([1, 2]), json!([1, 2])), Some(true));
+        assert_eq!(compare(json!([1, 2]), json!([2, 1])), Some(false));
+        assert_eq!(compare(json!({ "a": 1 }), json!({ "a": 1 })), Some(true));
+        assert_eq!(compare(json!({ "a": 1 }), json!({ "b": 1 })), Some(false));
+        assert_eq!(
+            compare(json!({ "a": 1 }), json!({ "a": 1, "b": 2 })),
+            Some(false)
+        );
+
+        let drv = |name: &str| json!({ "_derivation": true, "name": name });
+        assert_eq!(compare(drv("hello-2.12"), drv("hello-2.12")), Some(true));
+        assert_eq!(compare(drv("hello-2.12"), drv("hello-2.13")), Some(false));
+    }
+
+    #[test]
+    fn compare_undecidable() {
+        let function = json!({ "_function": true });
+        let error = json!({ "_error": true, "path": ["a"] });
+        let literal = json!({ "_type": "literalExpression", "text": "pkgs.hello" });
+
+        assert_eq!(compare(function.clone(), function.clone()), None);
+        assert_eq!(compare(error.clone(), json!(1)), None);
+        assert_eq!(compare(json!("pkgs.hello"), literal), None);
+        assert_eq!(
+            compare(json!([1, function.clone()]), json!([1, function.clone()])),
+            None
+        );
+        // A definite difference elsewhere decides it anyway
+        assert_eq!(
+            compare(json!([1, function.clone()]), json!([2, function])),
+            Some(false)
+        );
+        assert_eq!(
+            compare(json!({ "a": error.clone() }), json!({ "b": error })),
+            Some(false)
+        );
+    }
+}
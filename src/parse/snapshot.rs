@@ -0,0 +1,885 @@
+//! Compact binary snapshots of parsed options and configs.
+//!
+//! Parsing a JSON cache like `/tmp/nixos.json` takes most of the startup time, so `run`
+//! saves what it parsed next to it, e.g. as `/tmp/nixos.snapshot`, and reads that on the
+//! next start. A snapshot is only used while the JSON cache it was made from is unchanged,
+//! and only by the version of this crate that saved it.
+//!
+//! A snapshot is a header, a table with every distinct string once and then the value,
+//! which refers to the strings by their index in the table. Integers are LEB128 varints,
+//! attribute sets are sorted by name so the same value always gives the same bytes.
+//! The file is read at once and the string table borrows from it until the value is built.
+
+use super::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+const MAGIC: &[u8; 8] = b"nxdsnap\0";
+/// Increase when the encoding changes
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Errors reading or writing the snapshot or the metadata of its JSON cache
+    Io(std::io::Error),
+    /// Not a snapshot, or one of a different format, crate version or kind of value
+    Incompatible(String),
+    /// The JSON cache changed since the snapshot was saved
+    Outdated,
+    /// The snapshot ends early or contains invalid data
+    Corrupt(&'static str),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Incompatible(reason) => write!(f, "incompatible snapshot, {}", reason),
+            SnapshotError::Outdated => write!(f, "the JSON cache changed since the snapshot"),
+            SnapshotError::Corrupt(reason) => write!(f, "corrupt snapshot, {}", reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+/// Identifies the content of a JSON cache without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheKey {
+    pub len: u64,
+    /// Since the Unix epoch
+    pub modified: Duration,
+}
+
+impl CacheKey {
+    pub fn of(cache_file: &Path) -> std::io::Result<Self> {
+        let metadata = fs::metadata(cache_file)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(CacheKey {
+            len: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// A parsed value that can be saved in a snapshot
+pub trait Snapshot: Sized {
+    /// Tells snapshots of different types apart
+    const KIND: u8;
+
+    fn encode<'a>(&'a self, w: &mut Writer<'a>);
+    fn decode(r: &mut Reader) -> Result<Self, SnapshotError>;
+}
+
+impl Snapshot for NixValue {
+    const KIND: u8 = 0;
+
+    fn encode<'a>(&'a self, w: &mut Writer<'a>) {
+        w.value(self)
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, SnapshotError> {
+        r.value()
+    }
+}
+
+impl Snapshot for NixGuardedValue {
+    const KIND: u8 = 1;
+
+    fn encode<'a>(&'a self, w: &mut Writer<'a>) {
+        w.guarded(self)
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, SnapshotError> {
+        r.guarded()
+    }
+}
+
+/// Where the snapshot of a JSON cache is kept, e.g. `/tmp/nixos.snapshot` for `/tmp/nixos.json`
+pub fn snapshot_file(cache_file: &Path) -> PathBuf {
+    cache_file.with_extension("snapshot")
+}
+
+/// Save `value`, parsed from `cache_file`, as its snapshot
+pub fn save<T: Snapshot>(value: &T, cache_file: &Path) -> Result<(), SnapshotError> {
+    let bytes = to_bytes(value, CacheKey::of(cache_file)?);
+    // Write to a temporary file first so a concurrent start never reads half a snapshot
+    let snapshot = snapshot_file(cache_file);
+    let partial = snapshot.with_extension("snapshot.partial");
+    fs::write(&partial, bytes)?;
+    fs::rename(&partial, &snapshot)?;
+    Ok(())
+}
+
+/// Load the snapshot of `cache_file` if there is one for its current content
+pub fn load<T: Snapshot>(cache_file: &Path) -> Result<T, SnapshotError> {
+    let key = CacheKey::of(cache_file)?;
+    let bytes = fs::read(snapshot_file(cache_file))?;
+    from_bytes(&bytes, key)
+}
+
+pub fn to_bytes<T: Snapshot>(value: &T, key: CacheKey) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.out.extend_from_slice(MAGIC);
+    w.out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    w.out.push(T::KIND);
+    w.raw_str(env!("CARGO_PKG_VERSION"));
+    w.uint(key.len);
+    w.uint(key.modified.as_secs());
+    w.uint(key.modified.subsec_nanos() as u64);
+
+    let mut out = w.out;
+    out.extend_from_slice(&encode(value));
+    out
+}
+
+pub fn from_bytes<T: Snapshot>(bytes: &[u8], key: CacheKey) -> Result<T, SnapshotError> {
+    let mut r = Reader::new(bytes);
+
+    if r.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(SnapshotError::Incompatible("not a snapshot".to_string()));
+    }
+    r.format_version()?;
+    if r.byte()? != T::KIND {
+        return Err(SnapshotError::Incompatible(
+            "it holds a different kind of value".to_string(),
+        ));
+    }
+    let crate_version = r.raw_str()?;
+    if crate_version != env!("CARGO_PKG_VERSION") {
+        return Err(SnapshotError::Incompatible(format!(
+            "saved by version {}",
+            crate_version
+        )));
+    }
+    let len = r.uint()?;
+    let (secs, nanos) = (r.uint()?, r.uint()?);
+    if nanos >= 1_000_000_000 {
+        return Err(SnapshotError::Corrupt("invalid modification time"));
+    }
+    let saved_key = CacheKey {
+        len,
+        modified: Duration::new(secs, nanos as u32),
+    };
+    if saved_key != key {
+        return Err(SnapshotError::Outdated);
+    }
+
+    decode(&bytes[r.pos..])
+}
+
+/// The string table and `value`, without a header
+pub fn encode<T: Snapshot>(value: &T) -> Vec<u8> {
+    let mut body = Writer::default();
+    value.encode(&mut body);
+
+    let mut w = Writer::default();
+    w.uint(body.table.len() as u64);
+    for s in &body.table {
+        w.raw_str(s);
+    }
+    w.out.extend_from_slice(&body.out);
+    w.out
+}
+
+/// Read what `encode` wrote
+pub fn decode<T: Snapshot>(bytes: &[u8]) -> Result<T, SnapshotError> {
+    let mut r = Reader::new(bytes);
+
+    let count = r.len()?;
+    r.strings.reserve(count);
+    for _ in 0..count {
+        let s = r.raw_str()?;
+        r.strings.push(s);
+    }
+
+    let value = T::decode(&mut r)?;
+    if r.pos != bytes.len() {
+        return Err(SnapshotError::Corrupt("trailing data after the value"));
+    }
+    Ok(value)
+}
+
+// Encoding
+
+#[derive(Default)]
+pub struct Writer<'a> {
+    out: Vec<u8>,
+    /// Index of every string in `table`
+    indices: HashMap<&'a str, u64>,
+    table: Vec<&'a str>,
+}
+
+impl<'a> Writer<'a> {
+    pub(crate) fn uint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.out.push(n as u8);
+    }
+
+    fn int(&mut self, n: i64) {
+        // Zigzag, so small negative numbers stay short
+        self.uint(((n << 1) ^ (n >> 63)) as u64)
+    }
+
+    fn float(&mut self, n: f64) {
+        self.out.extend_from_slice(&n.to_le_bytes())
+    }
+
+    pub(crate) fn bool(&mut self, b: bool) {
+        self.out.push(b as u8)
+    }
+
+    fn raw_str(&mut self, s: &str) {
+        self.uint(s.len() as u64);
+        self.out.extend_from_slice(s.as_bytes());
+    }
+
+    pub(crate) fn str(&mut self, s: &'a str) {
+        let next = self.table.len() as u64;
+        let index = *self.indices.entry(s).or_insert(next);
+        if index == next {
+            self.table.push(s);
+        }
+        self.uint(index);
+    }
+
+    pub(crate) fn opt_str(&mut self, s: &'a Option<String>) {
+        match s {
+            Some(s) => {
+                self.bool(true);
+                self.str(s);
+            }
+            None => self.bool(false),
+        }
+    }
+
+    pub(crate) fn strs(&mut self, strs: &'a [String]) {
+        self.uint(strs.len() as u64);
+        strs.iter().for_each(|s| self.str(s));
+    }
+
+    fn sorted<V>(&mut self, attrs: &'a HashMap<String, V>, mut f: impl FnMut(&mut Self, &'a V)) {
+        let mut attrs: Vec<_> = attrs.iter().collect();
+        attrs.sort_unstable_by_key(|(name, _)| *name);
+        self.uint(attrs.len() as u64);
+        for (name, val) in attrs {
+            self.str(name);
+            f(self, val);
+        }
+    }
+
+    pub(crate) fn value(&mut self, val: &'a NixValue) {
+        match val {
+            NixValue::Option(opt) => {
+                self.out.push(0);
+                self.option(opt);
+            }
+            NixValue::Set(set) => {
+                self.out.push(1);
+                self.sorted(set, |w, v| w.value(v));
+            }
+        }
+    }
+
+    fn option(&mut self, opt: &'a NixOption) {
+        self.str(&opt.description);
+        self.type_value(&opt.r#type);
+        for val in [&opt.default, &opt.example] {
+            match val {
+                Some(val) => {
+                    self.bool(true);
+                    self.guarded(val);
+                }
+                None => self.bool(false),
+            }
+        }
+        self.strs(&opt.declarations);
+        self.out.push(match opt.alias_kind {
+            None => 0,
+            Some(NixAliasKind::Alias) => 1,
+            Some(NixAliasKind::Renamed) => 2,
+            Some(NixAliasKind::Removed) => 3,
+        });
+        self.opt_str(&opt.alias_target);
+        self.opt_str(&opt.removal_message);
+    }
+
+    fn type_value(&mut self, t: &'a NixTypeValue) {
+        match t {
+            NixTypeValue::Type(t) => {
+                self.out.push(0);
+                self.str(&t.id);
+                self.str(&t.description);
+                self.str(&t.functorName);
+                self.str(&t.name);
+                self.sorted(&t.nestedTypes, |w, t| w.type_value(t));
+                self.uint(t.functorPayload.len() as u64);
+                t.functorPayload.iter().for_each(|v| self.json(v));
+                self.bool(t.structured);
+            }
+            NixTypeValue::Submodule(s) => {
+                self.out.push(1);
+                self.str(&s.id);
+                self.sorted(&s.options, |w, v| w.value(v));
+                match s.freeform_type {
+                    Some(ref t) => {
+                        self.bool(true);
+                        self.type_value(t);
+                    }
+                    None => self.bool(false),
+                }
+            }
+            NixTypeValue::Reference(r) => {
+                self.out.push(2);
+                self.str(&r.id);
+                self.str(&r.name);
+                self.str(&r.description);
+            }
+        }
+    }
+
+    fn json(&mut self, val: &'a Value) {
+        match val {
+            Value::Null => self.out.push(0),
+            Value::Bool(b) => {
+                self.out.push(1);
+                self.bool(*b);
+            }
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(n), _) => {
+                    self.out.push(2);
+                    self.int(n);
+                }
+                (None, Some(n)) => {
+                    self.out.push(3);
+                    self.uint(n);
+                }
+                (None, None) => {
+                    self.out.push(4);
+                    self.float(n.as_f64().unwrap_or(f64::NAN));
+                }
+            },
+            Value::String(s) => {
+                self.out.push(5);
+                self.str(s);
+            }
+            Value::Array(list) => {
+                self.out.push(6);
+                self.uint(list.len() as u64);
+                list.iter().for_each(|v| self.json(v));
+            }
+            Value::Object(attrs) => {
+                // Already sorted, `serde_json` keeps objects in a `BTreeMap`
+                self.out.push(7);
+                self.uint(attrs.len() as u64);
+                for (name, v) in attrs {
+                    self.str(name);
+                    self.json(v);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn guarded(&mut self, val: &'a NixGuardedValue) {
+        use NixGuardedValue::*;
+
+        match val {
+            LiteralExpression(l) => {
+                self.out.push(0);
+                self.str(&l._type);
+                self.str(&l.text);
+            }
+            Function(_) => self.out.push(1),
+            Error(e) => {
+                self.out.push(2);
+                self.uint(e.path.len() as u64);
+                for segment in &e.path {
+                    match segment {
+                        NixPathSegment::Index(i) => {
+                            self.out.push(0);
+                            self.uint(*i as u64);
+                        }
+                        NixPathSegment::Name(name) => {
+                            self.out.push(1);
+                            self.str(name);
+                        }
+                    }
+                }
+                match e.details {
+                    Some(ref details) => {
+                        self.bool(true);
+                        self.str(&details.message);
+                        self.opt_str(&details.position);
+                    }
+                    None => self.bool(false),
+                }
+            }
+            Derivation(d) => {
+                self.out.push(3);
+                self.str(&d.name);
+                self.opt_str(&d.pname);
+                self.opt_str(&d.version);
+                self.opt_str(&d.out_path);
+                self.opt_str(&d.meta.description);
+                self.opt_str(&d.meta.homepage);
+                self.strs(&d.meta.license);
+                self.strs(&d.meta.maintainers);
+            }
+            OptionType(_) => self.out.push(4),
+            Attrs(attrs) => {
+                self.out.push(5);
+                self.sorted(attrs, |w, v| w.guarded(v));
+            }
+            List(list) => {
+                self.out.push(6);
+                self.uint(list.len() as u64);
+                list.iter().for_each(|v| self.guarded(v));
+            }
+            String(s) => {
+                self.out.push(7);
+                self.str(s);
+            }
+            Number(n) => {
+                self.out.push(8);
+                self.int(*n);
+            }
+            Float(n) => {
+                self.out.push(9);
+                self.float(*n);
+            }
+            Bool(b) => {
+                self.out.push(10);
+                self.bool(*b);
+            }
+            Null(()) => self.out.push(11),
+        }
+    }
+}
+
+// Decoding
+
+/// Values nest at most as deep as the JSON they were parsed from, which serde_json limits
+/// to 128 levels. Deeper ones come from a corrupt or crafted file, e.g. a bundle someone
+/// sent, and would overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    strings: Vec<&'a str>,
+    /// Of the nested values being read, see `MAX_DEPTH`
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader {
+            bytes,
+            pos: 0,
+            strings: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    /// Read a value that may contain values of its own
+    fn nested<V>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<V, SnapshotError>,
+    ) -> Result<V, SnapshotError> {
+        if self.depth == MAX_DEPTH {
+            return Err(SnapshotError::Corrupt("values nested too deeply"));
+        }
+        self.depth += 1;
+        let val = read(self);
+        self.depth -= 1;
+        val
+    }
+
+    /// Check the `FORMAT_VERSION` following the magic bytes
+    pub(crate) fn format_version(&mut self) -> Result<(), SnapshotError> {
+        let mut version = [0; 4];
+        version.copy_from_slice(self.take(4)?);
+        let version = u32::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(SnapshotError::Incompatible(format!(
+                "format version {}, expected {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(SnapshotError::Corrupt("unexpected end"))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn uint(&mut self) -> Result<u64, SnapshotError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+        }
+        Err(SnapshotError::Corrupt("varint too long"))
+    }
+
+    /// A number of elements, each of them takes at least one byte
+    pub(crate) fn len(&mut self) -> Result<usize, SnapshotError> {
+        let len = self.uint()?;
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err(SnapshotError::Corrupt("length beyond the end"));
+        }
+        Ok(len as usize)
+    }
+
+    fn int(&mut self) -> Result<i64, SnapshotError> {
+        let n = self.uint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn float(&mut self) -> Result<f64, SnapshotError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Corrupt("invalid boolean")),
+        }
+    }
+
+    fn raw_str(&mut self) -> Result<&'a str, SnapshotError> {
+        let len = self.len()?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| SnapshotError::Corrupt("invalid UTF-8"))
+    }
+
+    pub(crate) fn str(&mut self) -> Result<String, SnapshotError> {
+        let index = self.uint()?;
+        self.strings
+            .get(index as usize)
+            .map(|s| s.to_string())
+            .ok_or(SnapshotError::Corrupt("string index out of range"))
+    }
+
+    pub(crate) fn opt_str(&mut self) -> Result<Option<String>, SnapshotError> {
+        Ok(if self.bool()? {
+            Some(self.str()?)
+        } else {
+            None
+        })
+    }
+
+    pub(crate) fn strs(&mut self) -> Result<Vec<String>, SnapshotError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.str()).collect()
+    }
+
+    fn attrs<V>(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<V, SnapshotError>,
+    ) -> Result<HashMap<String, V>, SnapshotError> {
+        let len = self.len()?;
+        let mut attrs = HashMap::with_capacity(len);
+        for _ in 0..len {
+            let name = self.str()?;
+            attrs.insert(name, f(self)?);
+        }
+        Ok(attrs)
+    }
+
+    pub(crate) fn value(&mut self) -> Result<NixValue, SnapshotError> {
+        self.nested(|r| match r.byte()? {
+            0 => r.option().map(NixValue::Option),
+            1 => r.attrs(|r| r.value().map(Box::new)).map(NixValue::Set),
+            _ => Err(SnapshotError::Corrupt("invalid option tree")),
+        })
+    }
+
+    fn option(&mut self) -> Result<NixOption, SnapshotError> {
+        let description = self.str()?;
+        let r#type = self.type_value()?;
+        let default = if self.bool()? {
+            Some(self.guarded()?)
+        } else {
+            None
+        };
+        let example = if self.bool()? {
+            Some(self.guarded()?)
+        } else {
+            None
+        };
+        let declarations = self.strs()?;
+        let alias_kind = match self.byte()? {
+            0 => None,
+            1 => Some(NixAliasKind::Alias),
+            2 => Some(NixAliasKind::Renamed),
+            3 => Some(NixAliasKind::Removed),
+            _ => return Err(SnapshotError::Corrupt("invalid alias kind")),
+        };
+
+        Ok(NixOption {
+            _option: true,
+            description,
+            r#type,
+            default,
+            example,
+            declarations,
+            alias_kind,
+            alias_target: self.opt_str()?,
+            removal_message: self.opt_str()?,
+        })
+    }
+
+    fn type_value(&mut self) -> Result<NixTypeValue, SnapshotError> {
+        self.nested(Self::type_value_unnested)
+    }
+
+    fn type_value_unnested(&mut self) -> Result<NixTypeValue, SnapshotError> {
+        match self.byte()? {
+            0 => {
+                let id = self.str()?;
+                let description = self.str()?;
+                let functor_name = self.str()?;
+                let name = self.str()?;
+                let nested_types = self.attrs(Self::type_value)?;
+                let len = self.len()?;
+                let functor_payload = (0..len).map(|_| self.json()).collect::<Result<_, _>>()?;
+                Ok(NixTypeValue::Type(NixType {
+                    _type: true,
+                    id,
+                    description,
+                    functorName: functor_name,
+                    name,
+                    nestedTypes: nested_types,
+                    functorPayload: functor_payload,
+                    structured: self.bool()?,
+                }))
+            }
+            1 => {
+                let id = self.str()?;
+                let options = self.attrs(|r| r.value().map(Box::new))?;
+                let freeform_type = if self.bool()? {
+                    Some(Box::new(self.type_value()?))
+                } else {
+                    None
+                };
+                Ok(NixTypeValue::Submodule(NixSubmodule {
+                    _submodule: true,
+                    id,
+                    options,
+                    freeform_type,
+                }))
+            }
+            2 => Ok(NixTypeValue::Reference(NixTypeRef {
+                id: self.str()?,
+                name: self.str()?,
+                description: self.str()?,
+            })),
+            _ => Err(SnapshotError::Corrupt("invalid option type")),
+        }
+    }
+
+    fn json(&mut self) -> Result<Value, SnapshotError> {
+        self.nested(Self::json_unnested)
+    }
+
+    fn json_unnested(&mut self) -> Result<Value, SnapshotError> {
+        Ok(match self.byte()? {
+            0 => Value::Null,
+            1 => Value::Bool(self.bool()?),
+            2 => Value::from(self.int()?),
+            3 => Value::from(self.uint()?),
+            4 => Value::from(self.float()?),
+            5 => Value::String(self.str()?),
+            6 => {
+                let len = self.len()?;
+                Value::Array((0..len).map(|_| self.json()).collect::<Result<_, _>>()?)
+            }
+            7 => {
+                let len = self.len()?;
+                let mut attrs = serde_json::Map::new();
+                for _ in 0..len {
+                    let name = self.str()?;
+                    attrs.insert(name, self.json()?);
+                }
+                Value::Object(attrs)
+            }
+            _ => return Err(SnapshotError::Corrupt("invalid JSON value")),
+        })
+    }
+
+    pub(crate) fn guarded(&mut self) -> Result<NixGuardedValue, SnapshotError> {
+        self.nested(Self::guarded_unnested)
+    }
+
+    fn guarded_unnested(&mut self) -> Result<NixGuardedValue, SnapshotError> {
+        use NixGuardedValue::*;
+
+        Ok(match self.byte()? {
+            0 => LiteralExpression(NixLiteralExpression {
+                _type: self.str()?,
+                text: self.str()?,
+            }),
+            1 => Function(NixFunction { _function: true }),
+            2 => {
+                let len = self.len()?;
+                let path = (0..len)
+                    .map(|_| match self.byte()? {
+                        0 => Ok(NixPathSegment::Index(self.uint()? as usize)),
+                        1 => Ok(NixPathSegment::Name(self.str()?)),
+                        _ => Err(SnapshotError::Corrupt("invalid path segment")),
+                    })
+                    .collect::<Result<_, _>>()?;
+                let details = if self.bool()? {
+                    Some(NixErrorDetails {
+                        message: self.str()?,
+                        position: self.opt_str()?,
+                    })
+                } else {
+                    None
+                };
+                Error(NixTryEvalError {
+                    _error: true,
+                    path,
+                    details,
+                })
+            }
+            3 => {
+                let (name, pname, version) = (self.str()?, self.opt_str()?, self.opt_str()?);
+                let out_path = self.opt_str()?;
+                Derivation(NixDerivation {
+                    _derivation: true,
+                    name,
+                    pname,
+                    version,
+                    // Of this machine, the snapshot may come from another one
+                    in_local_store: super::in_local_store(out_path.as_ref()),
+                    out_path,
+                    meta: NixDerivationMeta {
+                        description: self.opt_str()?,
+                        homepage: self.opt_str()?,
+                        license: self.strs()?,
+                        maintainers: self.strs()?,
+                    },
+                })
+            }
+            4 => OptionType(NixGuardedOptionType { _type: true }),
+            5 => Attrs(self.attrs(|r| r.guarded().map(Box::new))?),
+            6 => {
+                let len = self.len()?;
+                List(
+                    (0..len)
+                        .map(|_| self.guarded().map(Box::new))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            7 => String(self.str()?),
+            8 => Number(self.int()?),
+            9 => Float(self.float()?),
+            10 => Bool(self.bool()?),
+            11 => Null(()),
+            _ => return Err(SnapshotError::Corrupt("invalid value")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: CacheKey = CacheKey {
+        len: 1234,
+        modified: Duration::from_secs(1_700_000_000),
+    };
+
+    #[test]
+    fn round_trip() {
+        let options: NixValue = serde_json::from_str(
+            r#"{ "a": { "b": {
+                "_option": true,
+                "aliasKind": "renamed",
+                "declarations": ["/nix/store/x/a.nix"],
+                "default": { "_derivation": true, "license": ["MIT"], "name": "hello" },
+                "description": "B",
+                "example": { "_type": "literalExpression", "text": "-1" },
+                "type": {
+                    "_type": true, "description": "one of", "functorName": "enum", "name": "enum",
+                    "functorPayload": [1, -2, 1.5, "x", null, [true], { "k": 18446744073709551615 }],
+                    "nestedTypes": { "e": { "_submodule": true, "options": {} } }
+                }
+            } } }"#,
+        )
+        .unwrap();
+        let config: NixGuardedValue = serde_json::from_str(
+            r#"{ "l": [-7, 2.5, "s", false, null, { "_function": true }, { "_type": true }],
+                 "e": { "_error": true, "path": ["e", 0] } }"#,
+        )
+        .unwrap();
+
+        let bytes = to_bytes(&options, KEY);
+        let decoded: NixValue = from_bytes(&bytes, KEY).unwrap();
+        assert_eq!(to_bytes(&decoded, KEY), bytes);
+        assert_eq!(decoded.type_graph().len(), options.type_graph().len());
+
+        let bytes = to_bytes(&config, KEY);
+        let decoded: NixGuardedValue = from_bytes(&bytes, KEY).unwrap();
+        assert_eq!(to_bytes(&decoded, KEY), bytes);
+        assert_eq!(show_path(&decoded.errors()[0].path), "e[0]");
+    }
+
+    #[test]
+    fn rejects_other_snapshots() {
+        let bytes = to_bytes(&NixGuardedValue::Number(1), KEY);
+
+        let changed = CacheKey { len: 1235, ..KEY };
+        let outdated = from_bytes::<NixGuardedValue>(&bytes, changed);
+        assert!(matches!(outdated, Err(SnapshotError::Outdated)));
+        let wrong_kind = from_bytes::<NixValue>(&bytes, KEY);
+        assert!(matches!(wrong_kind, Err(SnapshotError::Incompatible(_))));
+        let truncated = from_bytes::<NixGuardedValue>(&bytes[..bytes.len() - 1], KEY);
+        assert!(matches!(truncated, Err(SnapshotError::Corrupt(_))));
+        let json = from_bytes::<NixGuardedValue>(b"{}", KEY);
+        assert!(matches!(json, Err(SnapshotError::Incompatible(_))));
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        // Lists of one element each, around a `null`
+        let nested = |depth: usize| {
+            let mut body = vec![0];
+            body.extend([6, 1].repeat(depth));
+            body.push(11);
+            decode::<NixGuardedValue>(&body)
+        };
+        assert!(nested(MAX_DEPTH - 1).is_ok());
+        assert!(matches!(nested(MAX_DEPTH), Err(SnapshotError::Corrupt(_))));
+        assert!(matches!(nested(1_000_000), Err(SnapshotError::Corrupt(_))));
+    }
+}
@@ -1,12 +1,23 @@
+use crate::parse::snapshot::{self, Snapshot, SnapshotError};
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::{BufReader, Error, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
 
 const EXTRACT_NIX: &'static str = include_str!("../nix-scripts/extract.nix");
 const UTILITIES_NIX: &'static str = include_str!("../nix-scripts/utilities.nix");
 const EXTRACT_CONFIG_NIX: &'static str = include_str!("../nix-scripts/extractConfig.nix");
+const NIXPKGS_INFO_NIX: &str = include_str!("../nix-scripts/nixpkgsInfo.nix");
+const EXTRACT_DEFAULTS_NIX: &str = include_str!("../nix-scripts/extractDefaults.nix");
+const EXTRACT_CHECKS_NIX: &str = include_str!("../nix-scripts/extractChecks.nix");
+const EXTRACT_ERROR_NIX: &str = include_str!("../nix-scripts/extractError.nix");
+const EXTRACT_DEFINITIONS_NIX: &str = include_str!("../nix-scripts/extractDefinitions.nix");
+const EXTRACT_DEPRECATED_USAGE_NIX: &str =
+    include_str!("../nix-scripts/extractDeprecatedUsage.nix");
 
 #[derive(Debug)]
 pub enum LoadJsonError {
@@ -18,6 +29,245 @@ pub enum LoadJsonError {
     FailedEval(String),
 }
 
+/// Which nixpkgs to take the NixOS modules from
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum NixpkgsSource {
+    /// `<nixpkgs>` as found on `NIX_PATH`
+    #[default]
+    NixPath,
+    /// A local checkout or a (pinned) nixpkgs already in the store
+    Path(PathBuf),
+    /// An input of a flake, e.g. the `nixpkgs` input of the system flake
+    FlakeInput { flake: String, input: String },
+}
+
+impl NixpkgsSource {
+    /// Nix expression for the `nixpkgs` argument of the scripts, `None` means the default
+    fn nix_expression(&self) -> Option<String> {
+        use NixpkgsSource::*;
+
+        match self {
+            NixPath => None,
+            // Pass it as a string to avoid copying a local checkout to the store
+            Path(path) => Some(nix_string(&path.to_string_lossy())),
+            FlakeInput { flake, input } => Some(format!(
+                "(builtins.getFlake {}).inputs.{}",
+                nix_string(flake),
+                nix_string(input)
+            )),
+        }
+    }
+}
+
+/// Parses `<nixpkgs>`, `<flake>#<input>` or a path
+impl std::str::FromStr for NixpkgsSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "<nixpkgs>" {
+            Ok(NixpkgsSource::NixPath)
+        } else if let Some((flake, input)) = s.rsplit_once('#') {
+            Ok(NixpkgsSource::FlakeInput {
+                flake: flake.to_string(),
+                input: input.to_string(),
+            })
+        } else {
+            Ok(NixpkgsSource::Path(PathBuf::from(s)))
+        }
+    }
+}
+
+impl std::fmt::Display for NixpkgsSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use NixpkgsSource::*;
+
+        match self {
+            NixPath => write!(f, "<nixpkgs>"),
+            Path(path) => write!(f, "{}", path.display()),
+            FlakeInput { flake, input } => write!(f, "{flake}#inputs.{input}"),
+        }
+    }
+}
+
+/// Where to get the options to browse from
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum OptionSource {
+    /// The NixOS options of a nixpkgs together with extra module files
+    Nixpkgs {
+        nixpkgs: NixpkgsSource,
+        modules: Vec<PathBuf>,
+    },
+    /// `nixosConfigurations.<hostname>.options` of a flake
+    NixosConfiguration { flake: String, hostname: String },
+    /// `homeConfigurations.<name>.options` of a flake
+    HomeConfiguration { flake: String, name: String },
+}
+
+impl OptionSource {
+    /// Arguments for `extract.nix`, besides `utilities`
+    fn extract_args(&self) -> Vec<(bool, &'static str, String)> {
+        use OptionSource::*;
+
+        match self {
+            Nixpkgs { nixpkgs, modules } => {
+                let mut args = Vec::new();
+                if let Some(expr) = nixpkgs.nix_expression() {
+                    args.push((false, "nixpkgs", expr));
+                }
+                if !modules.is_empty() {
+                    let modules: Vec<String> = modules
+                        .iter()
+                        .map(|p| nix_string(&p.to_string_lossy()))
+                        .collect();
+                    args.push((false, "extraModules", format!("[ {} ]", modules.join(" "))));
+                }
+                args
+            }
+            NixosConfiguration { .. } | HomeConfiguration { .. } => {
+                let configuration = self.configuration_expression().unwrap();
+                vec![
+                    (false, "options", format!("{configuration}.options")),
+                    (false, "lib", format!("{configuration}.pkgs.lib")),
+                ]
+            }
+        }
+    }
+
+    /// Arguments for `nixpkgsInfo.nix`
+    fn info_args(&self) -> Vec<(bool, &'static str, String)> {
+        use OptionSource::*;
+
+        match self {
+            Nixpkgs { nixpkgs, .. } => nixpkgs
+                .nix_expression()
+                .map(|expr| (false, "nixpkgs", expr))
+                .into_iter()
+                .collect(),
+            NixosConfiguration { .. } => {
+                let configuration = self.configuration_expression().unwrap();
+                vec![
+                    (false, "nixpkgs", "null".to_string()),
+                    (false, "lib", format!("{configuration}.pkgs.lib")),
+                    (
+                        false,
+                        "revision",
+                        format!("{configuration}.config.system.nixos.revision"),
+                    ),
+                ]
+            }
+            HomeConfiguration { .. } => {
+                let configuration = self.configuration_expression().unwrap();
+                vec![
+                    (false, "nixpkgs", "null".to_string()),
+                    (false, "lib", format!("{configuration}.pkgs.lib")),
+                ]
+            }
+        }
+    }
+
+    /// Expression for the configuration of a flake, `None` for plain nixpkgs
+    fn configuration_expression(&self) -> Option<String> {
+        use OptionSource::*;
+
+        let (flake, output, name) = match self {
+            Nixpkgs { .. } => return None,
+            NixosConfiguration { flake, hostname } => (flake, "nixosConfigurations", hostname),
+            HomeConfiguration { flake, name } => (flake, "homeConfigurations", name),
+        };
+
+        Some(format!(
+            "(builtins.getFlake {}).{output}.{}",
+            nix_string(flake),
+            nix_string(name)
+        ))
+    }
+
+    fn cache_file(&self) -> PathBuf {
+        use OptionSource::*;
+
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        let hash = hasher.finish();
+        match self {
+            Nixpkgs { nixpkgs, modules }
+                if *nixpkgs == NixpkgsSource::NixPath && modules.is_empty() =>
+            {
+                PathBuf::from("/tmp/nixos.json")
+            }
+            Nixpkgs { .. } => PathBuf::from(format!("/tmp/nixos-{hash:x}.json")),
+            // The hash covers the flake, hosts of different flakes may share their name
+            NixosConfiguration { hostname, .. } => {
+                PathBuf::from(format!("/tmp/nixos-options-{hostname}-{hash:x}.json"))
+            }
+            HomeConfiguration { name, .. } => {
+                PathBuf::from(format!("/tmp/home-options-{name}-{hash:x}.json"))
+            }
+        }
+    }
+}
+
+impl Default for OptionSource {
+    fn default() -> Self {
+        OptionSource::Nixpkgs {
+            nixpkgs: NixpkgsSource::default(),
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for OptionSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use OptionSource::*;
+
+        match self {
+            Nixpkgs { nixpkgs, modules } if modules.is_empty() => write!(f, "{nixpkgs}"),
+            Nixpkgs { nixpkgs, modules } => {
+                write!(f, "{nixpkgs} with {} extra module(s)", modules.len())
+            }
+            NixosConfiguration { flake, hostname } => {
+                write!(f, "{flake}#nixosConfigurations.{hostname}")
+            }
+            HomeConfiguration { flake, name } => write!(f, "{flake}#homeConfigurations.{name}"),
+        }
+    }
+}
+
+/// Version information of the nixpkgs the options come from
+#[derive(Deserialize, Debug, Clone)]
+pub struct NixpkgsInfo {
+    pub version: String,
+    pub revision: Option<String>,
+}
+
+impl NixpkgsInfo {
+    /// Version with an abbreviated revision, e.g. for a window title
+    pub fn short(&self) -> String {
+        match self.revision {
+            Some(ref rev) => format!("nixpkgs {} ({})", self.version, &rev[..rev.len().min(7)]),
+            None => format!("nixpkgs {}", self.version),
+        }
+    }
+}
+
+impl std::fmt::Display for NixpkgsInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.revision {
+            Some(ref rev) => write!(f, "nixpkgs {} ({})", self.version, rev),
+            None => write!(f, "nixpkgs {}", self.version),
+        }
+    }
+}
+
+/// Quote a string for use in a Nix expression
+fn nix_string(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("${", "\\${")
+    )
+}
+
 pub fn run_nix_file(file: &Path) -> Result<Output, Error> {
     Command::new("nix-instantiate")
         .args([
@@ -77,17 +327,75 @@ where
     B: ToString,
     I: IntoIterator<Item = (bool, A, B)>,
 {
-    if let Ok(content) = load_json_file(cache_file) {
-        if let Ok(parsed) = serde_json::from_str(&content) {
-            return Ok(parsed);
-        } else {
+    match load_cache(cache_file) {
+        Some(parsed) => Ok(parsed),
+        None => eval_json(nix_code, args),
+    }
+}
+
+/// Like `load_from_cache_or_eval`, but uses the snapshot of the cache when it is up to date
+/// and saves one after parsing the cache
+fn load_from_snapshot_or_cache_or_eval<V, A, B, I>(
+    nix_code: &str,
+    args: I,
+    cache_file: &Path,
+) -> Result<V, LoadJsonError>
+where
+    V: DeserializeOwned + Snapshot,
+    A: ToString,
+    B: ToString,
+    I: IntoIterator<Item = (bool, A, B)>,
+{
+    match snapshot::load(cache_file) {
+        Ok(value) => return Ok(value),
+        // No cache or no snapshot of it yet
+        Err(SnapshotError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+        // Saved again below
+        Err(SnapshotError::Outdated) => {}
+        Err(e) => eprintln!(
+            "Reading the snapshot of `{}` failed: {}",
+            cache_file.display(),
+            e
+        ),
+    }
+
+    match load_cache(cache_file) {
+        Some(parsed) => {
+            if let Err(e) = snapshot::save(&parsed, cache_file) {
+                eprintln!(
+                    "Saving a snapshot of `{}` failed: {}",
+                    cache_file.display(),
+                    e
+                );
+            }
+            Ok(parsed)
+        }
+        None => eval_json(nix_code, args),
+    }
+}
+
+fn load_cache<V: DeserializeOwned>(cache_file: &Path) -> Option<V> {
+    let content = load_json_file(cache_file).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
             eprintln!(
-                "Parsing json produced by cached `{}` failed, ignoring cache",
-                cache_file.display()
+                "Parsing json produced by cached `{}` failed, ignoring cache: {}",
+                cache_file.display(),
+                e
             );
+            None
         }
     }
+}
 
+fn eval_json<V, A, B, I>(nix_code: &str, args: I) -> Result<V, LoadJsonError>
+where
+    V: DeserializeOwned,
+    A: ToString,
+    B: ToString,
+    I: IntoIterator<Item = (bool, A, B)>,
+{
     let command = run_nix_str_with_args(nix_code, args);
     match command {
         Ok(output) => {
@@ -114,10 +422,18 @@ pub fn get_available_nixos_configurations(flake_path: &str) -> Option<Vec<String
 }
 
 pub fn get_options() -> Result<super::parse::NixValue, LoadJsonError> {
-    let cache_file = Path::new("/tmp/nixos.json");
+    get_options_from(&OptionSource::default())
+}
+
+pub fn get_options_from(source: &OptionSource) -> Result<super::parse::NixValue, LoadJsonError> {
+    let mut args = vec![(false, "utilities", UTILITIES_NIX.to_string())];
+    args.extend(source.extract_args());
+    load_from_snapshot_or_cache_or_eval(EXTRACT_NIX, args, &source.cache_file())
+}
 
-    let args = [(false, "utilities", UTILITIES_NIX)];
-    load_from_cache_or_eval(EXTRACT_NIX, args, cache_file)
+/// Returns the version and, if known, the revision of the nixpkgs used by `source`
+pub fn get_nixpkgs_info(source: &OptionSource) -> Result<NixpkgsInfo, LoadJsonError> {
+    eval_json(NIXPKGS_INFO_NIX, source.info_args())
 }
 
 pub fn get_config(
@@ -131,5 +447,247 @@ pub fn get_config(
         (true, "flakePath", flake),
         (true, "hostname", hostname),
     ];
-    load_from_cache_or_eval(EXTRACT_CONFIG_NIX, args, cache_file)
+    load_from_snapshot_or_cache_or_eval(EXTRACT_CONFIG_NIX, args, cache_file)
+}
+
+/// Evaluates the config of a host like `get_config`, but never reads the cache,
+/// e.g. again after the files of the flake changed
+pub fn eval_config(
+    flake: &str,
+    hostname: &str,
+) -> Result<super::parse::NixGuardedValue, LoadJsonError> {
+    let args = [
+        (false, "utilities", UTILITIES_NIX),
+        (true, "flakePath", flake),
+        (true, "hostname", hostname),
+    ];
+    eval_json(EXTRACT_CONFIG_NIX, args)
+}
+
+/// Evaluates only the config below `path` of a host, e.g. `["services", "nginx"]`.
+/// Not cached and, unlike `get_config`, including the parts too expensive to evaluate as a whole.
+pub fn get_config_at(
+    flake: &str,
+    hostname: &str,
+    path: &[&str],
+) -> Result<super::parse::NixGuardedValue, LoadJsonError> {
+    let path = serde_json::to_string(path).map_err(LoadJsonError::ParseRelated)?;
+    let args = [
+        (false, "utilities", UTILITIES_NIX),
+        (true, "flakePath", flake),
+        (true, "hostname", hostname),
+        (true, "path", path.as_str()),
+    ];
+    eval_json(EXTRACT_CONFIG_NIX, args)
+}
+
+/// Evaluates the defaults given as a `literalExpression` in the context of a host.
+/// The result has the same (nested) structure as the option tree.
+pub fn get_evaluated_defaults(
+    flake: &str,
+    hostname: &str,
+) -> Result<super::parse::NixGuardedValue, LoadJsonError> {
+    let cache_file = Path::new("/tmp/nixosDefaults.json");
+
+    let args = [
+        (false, "utilities", UTILITIES_NIX),
+        (true, "flakePath", flake),
+        (true, "hostname", hostname),
+    ];
+    load_from_cache_or_eval(EXTRACT_DEFAULTS_NIX, args, cache_file)
+}
+
+/// Lists the renamed and removed options that are still defined in the config of a host
+pub fn get_deprecated_usage(
+    flake: &str,
+    hostname: &str,
+) -> Result<Vec<super::parse::NixDefinition>, LoadJsonError> {
+    let cache_file = Path::new("/tmp/nixosDeprecated.json");
+
+    let args = [
+        (false, "utilities", UTILITIES_NIX),
+        (true, "flakePath", flake),
+        (true, "hostname", hostname),
+    ];
+    load_from_cache_or_eval(EXTRACT_DEPRECATED_USAGE_NIX, args, cache_file)
+}
+
+/// Lists every option defined in the config of a host with the files defining it
+pub fn get_definitions(
+    flake: &str,
+    hostname: &str,
+) -> Result<Vec<super::parse::NixDefinition>, LoadJsonError> {
+    let cache_file = Path::new("/tmp/nixosDefinitions.json");
+
+    let args = [(true, "flakePath", flake), (true, "hostname", hostname)];
+    load_from_cache_or_eval(EXTRACT_DEFINITIONS_NIX, args, cache_file)
+}
+
+/// Evaluates the failing `assertions` and the `warnings` of a host
+pub fn get_config_checks(
+    flake: &str,
+    hostname: &str,
+) -> Result<super::parse::NixConfigChecks, LoadJsonError> {
+    let cache_file = Path::new("/tmp/nixosChecks.json");
+
+    let args = [
+        (false, "utilities", UTILITIES_NIX),
+        (true, "flakePath", flake),
+        (true, "hostname", hostname),
+    ];
+    load_from_cache_or_eval(EXTRACT_CHECKS_NIX, args, cache_file)
+}
+
+/// Evaluates the config value at `path` of a host again to get the message and position
+/// of its error, which `tryEval` drops. Returns `None` when it evaluates fine this time.
+pub fn get_error_details(
+    flake: &str,
+    hostname: &str,
+    path: &[super::parse::NixPathSegment],
+) -> Result<Option<super::parse::NixErrorDetails>, LoadJsonError> {
+    let path = serde_json::to_string(path).map_err(LoadJsonError::ParseRelated)?;
+    let args = [
+        (true, "flakePath", flake),
+        (true, "hostname", hostname),
+        (true, "path", path.as_str()),
+    ];
+
+    let output =
+        run_nix_str_with_args(EXTRACT_ERROR_NIX, args).map_err(LoadJsonError::FailedCommand)?;
+    if output.status.success() {
+        Ok(None)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Ok(Some(super::parse::NixErrorDetails::from_stderr(&stderr)))
+    }
+}
+
+/// Store path of `config.system.build.toplevel` of a host, it doesn't have to be built
+pub fn get_toplevel_path(flake: &str, hostname: &str) -> Result<String, LoadJsonError> {
+    let nix_code = format!(
+        "with builtins; unsafeDiscardStringContext (getAttr {} (getFlake {}).nixosConfigurations).config.system.build.toplevel.outPath",
+        nix_string(hostname),
+        nix_string(flake)
+    );
+    eval_json::<_, &str, &str, _>(&nix_code, [])
+}
+
+/// Reads the closure of a path from the local store with `nix path-info -rS`,
+/// the path has to be built or substituted already
+pub fn get_closure(path: &str) -> Result<super::closure::Closure, LoadJsonError> {
+    let output = Command::new("nix")
+        .args(["--extra-experimental-features", "nix-command", "--offline"])
+        .args(["path-info", "--json", "--recursive", "--closure-size", path])
+        .output()
+        .map_err(LoadJsonError::FailedCommand)?;
+
+    if output.status.success() {
+        let parsed = serde_json::from_slice(&output.stdout).map_err(LoadJsonError::ParseRelated)?;
+        Ok(super::closure::Closure::new(path.to_string(), parsed))
+    } else {
+        Err(LoadJsonError::FailedEval(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nixpkgs_sources() {
+        let parse = |s: &str| s.parse::<NixpkgsSource>().unwrap();
+        assert_eq!(parse("<nixpkgs>"), NixpkgsSource::NixPath);
+        assert_eq!(
+            parse("/etc/nixos#nixpkgs"),
+            NixpkgsSource::FlakeInput {
+                flake: "/etc/nixos".to_string(),
+                input: "nixpkgs".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("/home/me/nixpkgs"),
+            NixpkgsSource::Path(PathBuf::from("/home/me/nixpkgs"))
+        );
+        assert_eq!(NixpkgsSource::default(), NixpkgsSource::NixPath);
+    }
+
+    #[test]
+    fn nixpkgs_arguments() {
+        let args = |nixpkgs: NixpkgsSource| {
+            let source = OptionSource::Nixpkgs {
+                nixpkgs,
+                modules: Vec::new(),
+            };
+            (source.extract_args(), source.info_args())
+        };
+
+        assert_eq!(args(NixpkgsSource::NixPath), (Vec::new(), Vec::new()));
+
+        let path = NixpkgsSource::Path(PathBuf::from("/src/nix\"pkgs"));
+        let expected = vec![(false, "nixpkgs", r#""/src/nix\"pkgs""#.to_string())];
+        assert_eq!(args(path), (expected.clone(), expected));
+
+        let input = NixpkgsSource::FlakeInput {
+            flake: "/etc/nixos".to_string(),
+            input: "nixpkgs".to_string(),
+        };
+        let expected = vec![(
+            false,
+            "nixpkgs",
+            r#"(builtins.getFlake "/etc/nixos").inputs."nixpkgs""#.to_string(),
+        )];
+        assert_eq!(args(input), (expected.clone(), expected));
+    }
+
+    #[test]
+    fn flake_arguments() {
+        let home = OptionSource::HomeConfiguration {
+            flake: "/home/me/config".to_string(),
+            name: "me@laptop".to_string(),
+        };
+        let configuration =
+            r#"(builtins.getFlake "/home/me/config").homeConfigurations."me@laptop""#;
+        assert_eq!(
+            home.extract_args(),
+            [
+                (false, "options", format!("{configuration}.options")),
+                (false, "lib", format!("{configuration}.pkgs.lib")),
+            ]
+        );
+
+        let modules = OptionSource::Nixpkgs {
+            nixpkgs: NixpkgsSource::NixPath,
+            modules: vec![PathBuf::from("/etc/a.nix"), PathBuf::from("/etc/b.nix")],
+        };
+        assert_eq!(
+            modules.extract_args(),
+            [(
+                false,
+                "extraModules",
+                r#"[ "/etc/a.nix" "/etc/b.nix" ]"#.to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn cache_files() {
+        let host = |flake: &str| OptionSource::NixosConfiguration {
+            flake: flake.to_string(),
+            hostname: "server".to_string(),
+        };
+        assert_ne!(
+            host("/etc/nixos").cache_file(),
+            host("/srv/other").cache_file()
+        );
+        assert_eq!(
+            host("/etc/nixos").cache_file(),
+            host("/etc/nixos").cache_file()
+        );
+        assert_eq!(
+            OptionSource::default().cache_file(),
+            PathBuf::from("/tmp/nixos.json")
+        );
+    }
 }
@@ -0,0 +1,449 @@
+//! Generate a JSON Schema (draft 2020-12) from the option tree,
+//! e.g. to validate JSON configuration or for completion in editors.
+//!
+//! Types that can't be expressed in JSON (functions, packages as derivations)
+//! are kept as permissive as possible, a schema should never reject a valid config.
+//! Recursive types refer to the enclosing type by its `$anchor`.
+
+use crate::parse::{NixGuardedValue, NixOption, NixSet, NixType, NixTypeValue, NixValue};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::Path;
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// The schema of a whole configuration, i.e. the root of the option tree
+pub fn json_schema(root: &NixValue, title: &str) -> Value {
+    let mut schema = value_schema(root);
+    let mut referenced = HashSet::new();
+    collect_refs(&schema, &mut referenced);
+    prune_anchors(&mut schema, &referenced);
+    if let Value::Object(ref mut map) = schema {
+        map.insert("$schema".to_string(), json!(DRAFT));
+        map.insert("title".to_string(), json!(title));
+    }
+    schema
+}
+
+pub fn write_json_schema(root: &NixValue, title: &str, path: &Path) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, &json_schema(root, title))?;
+    writeln!(out)?;
+    out.flush()
+}
+
+fn value_schema(val: &NixValue) -> Value {
+    match val {
+        NixValue::Option(opt) => option_schema(opt),
+        NixValue::Set(set) => set_schema(set),
+    }
+}
+
+fn set_schema(set: &NixSet) -> Value {
+    let properties: Map<String, Value> = set
+        .iter()
+        .map(|(name, val)| (name.clone(), value_schema(val)))
+        .collect();
+
+    json!({ "type": "object", "properties": properties })
+}
+
+fn option_schema(opt: &NixOption) -> Value {
+    let mut schema = type_schema(&opt.r#type);
+
+    if let Value::Object(ref mut map) = schema {
+        if !opt.description.is_empty() {
+            map.insert("description".to_string(), json!(opt.description));
+        }
+        if let Some(default) = opt.default.as_ref().and_then(guarded_to_json) {
+            map.insert("default".to_string(), default);
+        }
+        if let Some(example) = opt.example.as_ref().and_then(guarded_to_json) {
+            map.insert("examples".to_string(), json!([example]));
+        }
+        if opt.alias_kind.is_some() {
+            map.insert("deprecated".to_string(), json!(true));
+        }
+    }
+    schema
+}
+
+fn type_schema(t: &NixTypeValue) -> Value {
+    let mut schema = match t {
+        NixTypeValue::Submodule(sub) => {
+            let mut schema = set_schema(&sub.options);
+            if let (Some(freeform), Value::Object(ref mut map)) = (&sub.freeform_type, &mut schema)
+            {
+                map.insert("additionalProperties".to_owned(), freeform_schema(freeform));
+            }
+            schema
+        }
+        NixTypeValue::Type(t) => nix_type_schema(t),
+        NixTypeValue::Reference(r) => return json!({ "$ref": format!("#{}", anchor(&r.id)) }),
+    };
+
+    // Every type gets an anchor, the ones no reference uses are removed afterwards
+    if let (Value::Object(ref mut map), false) = (&mut schema, t.id().is_empty()) {
+        map.insert("$anchor".to_owned(), json!(anchor(t.id())));
+    }
+    schema
+}
+
+/// The schema of a single undeclared attribute, the `freeformType` is the type of
+/// the whole set, mostly an `attrsOf` or a JSON/YAML/TOML value type
+fn freeform_schema(freeform: &NixTypeValue) -> Value {
+    match freeform {
+        NixTypeValue::Type(t)
+            if !t.structured && matches!(t.name.as_str(), "attrsOf" | "lazyAttrsOf") =>
+        {
+            t.nestedTypes
+                .get("elemType")
+                .map(type_schema)
+                .unwrap_or(json!({}))
+        }
+        NixTypeValue::Type(t) if t.structured => type_schema(freeform),
+        _ => json!({}),
+    }
+}
+
+/// Turn a type id like `users.users/elemType` into a valid, unique `$anchor`,
+/// these may only contain letters, digits, `-`, `.` and `_`
+fn anchor(id: &str) -> String {
+    let mut anchor = "type_".to_owned();
+    for b in id.bytes() {
+        match b {
+            b'_' => anchor.push_str("__"),
+            b'-' | b'.' => anchor.push(b as char),
+            _ if b.is_ascii_alphanumeric() => anchor.push(b as char),
+            _ => anchor.push_str(&format!("_{:02x}", b)),
+        }
+    }
+    anchor
+}
+
+fn collect_refs(schema: &Value, referenced: &mut HashSet<String>) {
+    match schema {
+        Value::Object(map) => {
+            if let Some(Value::String(r)) = map.get("$ref") {
+                referenced.insert(r.trim_start_matches('#').to_owned());
+            }
+            map.values().for_each(|v| collect_refs(v, referenced));
+        }
+        Value::Array(list) => list.iter().for_each(|v| collect_refs(v, referenced)),
+        _ => (),
+    }
+}
+
+fn prune_anchors(schema: &mut Value, referenced: &HashSet<String>) {
+    match schema {
+        Value::Object(map) => {
+            let unused = match map.get("$anchor") {
+                Some(Value::String(a)) => !referenced.contains(a),
+                _ => false,
+            };
+            if unused {
+                map.remove("$anchor");
+            }
+            map.values_mut().for_each(|v| prune_anchors(v, referenced));
+        }
+        Value::Array(list) => list.iter_mut().for_each(|v| prune_anchors(v, referenced)),
+        _ => (),
+    }
+}
+
+fn nix_type_schema(t: &NixType) -> Value {
+    let nested = |key: &str| t.nestedTypes.get(key).map(type_schema).unwrap_or(json!({}));
+
+    // JSON/YAML/TOML values: anything goes
+    if t.structured {
+        return json!({ "$comment": t.description });
+    }
+
+    match t.name.as_str() {
+        "str" | "string" | "separatedString" | "singleLineStr" | "path" | "package" => {
+            json!({ "type": "string" })
+        }
+        "nonEmptyStr" => json!({ "type": "string", "minLength": 1 }),
+        name if name.starts_with("strMatching") => {
+            // `builtins.match` has to match the whole string, a JSON Schema pattern doesn't
+            match t.description.strip_prefix("string matching the pattern ") {
+                Some(pattern) => json!({ "type": "string", "pattern": format!("^(?:{pattern})$") }),
+                None => json!({ "type": "string" }),
+            }
+        }
+        "bool" => json!({ "type": "boolean" }),
+        "float" => json!({ "type": "number" }),
+        "number" | "numberBetween" | "numberPositive" | "numberNonnegative" => {
+            with_bounds(json!({ "type": "number" }), t)
+        }
+        "int" | "intBetween" | "positiveInt" | "unsignedInt" => {
+            with_bounds(json!({ "type": "integer" }), t)
+        }
+        name if name.starts_with("unsignedInt") || name.starts_with("signedInt") => {
+            with_bounds(json!({ "type": "integer" }), t)
+        }
+        "enum" => json!({ "enum": t.functorPayload }),
+
+        "nullOr" => json!({ "anyOf": [nested("elemType"), { "type": "null" }] }),
+        "listOf" => json!({ "type": "array", "items": nested("elemType") }),
+        "attrsOf" | "lazyAttrsOf" => {
+            json!({ "type": "object", "additionalProperties": nested("elemType") })
+        }
+        "attrs" => json!({ "type": "object" }),
+        "either" => json!({ "anyOf": [nested("left"), nested("right")] }),
+        "coercedTo" => json!({ "anyOf": [nested("coercedType"), nested("finalType")] }),
+        "uniq" | "unique" => nested("elemType"),
+
+        // Functions and the like, nothing to check
+        _ => json!({ "$comment": t.description }),
+    }
+}
+
+/// Add `minimum`/`maximum` of the bounded integer and number types
+fn with_bounds(mut schema: Value, t: &NixType) -> Value {
+    let (min, max) = match t.name.as_str() {
+        "positiveInt" => (Some(json!(1)), None),
+        "unsignedInt" | "numberNonnegative" => (Some(json!(0)), None),
+        "numberPositive" => {
+            schema["exclusiveMinimum"] = json!(0);
+            return schema;
+        }
+        // Others describe themselves like `8 bit unsigned integer; between 0 and 255 (both inclusive)`
+        _ => bounds_from_description(&t.description),
+    };
+
+    if let Some(min) = min {
+        schema["minimum"] = min;
+    }
+    if let Some(max) = max {
+        schema["maximum"] = max;
+    }
+    schema
+}
+
+fn bounds_from_description(description: &str) -> (Option<Value>, Option<Value>) {
+    let parse = |s: &str| -> Option<Value> {
+        s.parse::<i64>()
+            .map(Value::from)
+            .ok()
+            .or_else(|| s.parse::<f64>().ok().map(Value::from))
+    };
+
+    let between = description
+        .split_once("between ")
+        .and_then(|(_, rest)| rest.split_once(" (both inclusive)"))
+        .and_then(|(range, _)| range.split_once(" and "));
+    match between {
+        Some((min, max)) => (parse(min.trim()), parse(max.trim())),
+        None => (None, None),
+    }
+}
+
+/// Defaults and examples as JSON, `None` for what JSON can't express
+/// like functions and `literalExpression`s
+fn guarded_to_json(val: &NixGuardedValue) -> Option<Value> {
+    use NixGuardedValue::*;
+
+    match val {
+        LiteralExpression(_) | Function(_) | Error(_) | OptionType(_) => None,
+        Derivation(d) => d.out_path.as_ref().map(|path| json!(path)),
+        Attrs(attrs) => attrs
+            .iter()
+            .map(|(k, v)| guarded_to_json(v).map(|v| (k.clone(), v)))
+            .collect::<Option<Map<_, _>>>()
+            .map(Value::Object),
+        List(list) => list
+            .iter()
+            .map(|v| guarded_to_json(v))
+            .collect::<Option<Vec<Value>>>()
+            .map(Value::Array),
+        String(s) => Some(json!(s)),
+        Number(n) => Some(json!(n)),
+        Float(n) => Some(json!(n)),
+        Bool(b) => Some(json!(b)),
+        Null(_) => Some(Value::Null),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchored_pattern() {
+        let options = json!({
+            "hostName": {
+                "_option": true,
+                "description": "",
+                "type": {
+                    "_type": true,
+                    "name": "strMatching \"[a-z]+|[0-9]+\"",
+                    "description": "string matching the pattern [a-z]+|[0-9]+",
+                    "functorName": "strMatching",
+                    "nestedTypes": {},
+                },
+            },
+        });
+        let schema = json_schema(&serde_json::from_value(options).unwrap(), "test");
+        assert_eq!(
+            schema["properties"]["hostName"],
+            json!({ "type": "string", "pattern": "^(?:[a-z]+|[0-9]+)$" })
+        );
+    }
+
+    fn nix_type(name: &str, description: &str, nested_types: Value) -> Value {
+        json!({
+            "_type": true,
+            "name": name,
+            "description": description,
+            "functorName": name,
+            "nestedTypes": nested_types,
+        })
+    }
+
+    fn option(option_type: Value) -> Value {
+        json!({ "_option": true, "description": "", "type": option_type })
+    }
+
+    fn str_type() -> Value {
+        nix_type("str", "string", json!({}))
+    }
+
+    fn int_type() -> Value {
+        nix_type("int", "signed integer", json!({}))
+    }
+
+    /// The schema of the single option `o` of the given type
+    fn schema_of(option_type: Value) -> Value {
+        let options = json!({ "o": option(option_type) });
+        let schema = json_schema(&serde_json::from_value(options).unwrap(), "test");
+        schema["properties"]["o"].clone()
+    }
+
+    #[test]
+    fn enums() {
+        let mut level = nix_type("enum", "one of \"info\", \"debug\"", json!({}));
+        level["functorPayload"] = json!(["info", "debug"]);
+        assert_eq!(schema_of(level), json!({ "enum": ["info", "debug"] }));
+    }
+
+    #[test]
+    fn int_bounds() {
+        let byte = nix_type(
+            "unsignedInt8",
+            "8 bit unsigned integer; between 0 and 255 (both inclusive)",
+            json!({}),
+        );
+        assert_eq!(
+            schema_of(byte),
+            json!({ "type": "integer", "minimum": 0, "maximum": 255 })
+        );
+
+        let between = nix_type(
+            "intBetween",
+            "integer between -5 and 5 (both inclusive)",
+            json!({}),
+        );
+        assert_eq!(
+            schema_of(between),
+            json!({ "type": "integer", "minimum": -5, "maximum": 5 })
+        );
+
+        let positive = nix_type("positiveInt", "positive integer, meaning >0", json!({}));
+        assert_eq!(
+            schema_of(positive),
+            json!({ "type": "integer", "minimum": 1 })
+        );
+        assert_eq!(schema_of(int_type()), json!({ "type": "integer" }));
+    }
+
+    #[test]
+    fn wrapped_types() {
+        let attrs = nix_type(
+            "attrsOf",
+            "attribute set of string",
+            json!({ "elemType": str_type() }),
+        );
+        assert_eq!(
+            schema_of(attrs),
+            json!({ "type": "object", "additionalProperties": { "type": "string" } })
+        );
+
+        let null_or = nix_type(
+            "nullOr",
+            "null or string",
+            json!({ "elemType": str_type() }),
+        );
+        assert_eq!(
+            schema_of(null_or),
+            json!({ "anyOf": [{ "type": "string" }, { "type": "null" }] })
+        );
+    }
+
+    #[test]
+    fn freeform_submodule() {
+        let freeform = nix_type(
+            "attrsOf",
+            "attribute set of string",
+            json!({ "elemType": str_type() }),
+        );
+        let settings = json!({
+            "_submodule": true,
+            "options": { "port": option(int_type()) },
+            "freeformType": freeform,
+        });
+        assert_eq!(
+            schema_of(settings),
+            json!({
+                "type": "object",
+                "properties": { "port": { "type": "integer" } },
+                "additionalProperties": { "type": "string" },
+            })
+        );
+    }
+
+    #[test]
+    fn recursive_references() {
+        let child = json!({ "_typeRef": "o", "description": "submodule", "name": "submodule" });
+        let node = json!({
+            "_id": "o",
+            "_submodule": true,
+            "options": {
+                "child": option(nix_type("nullOr", "null or submodule", json!({ "elemType": child }))),
+                "names": option(json!({
+                    "_id": "o/names",
+                    "_type": true,
+                    "name": "listOf",
+                    "description": "list of string",
+                    "functorName": "listOf",
+                    "nestedTypes": { "elemType": str_type() },
+                })),
+            },
+        });
+        let schema = schema_of(node);
+
+        assert_eq!(schema["$anchor"], json!("type_o"));
+        assert_eq!(
+            schema["properties"]["child"]["anyOf"][0],
+            json!({ "$ref": "#type_o" })
+        );
+        // Nothing refers to the list
+        assert_eq!(
+            schema["properties"]["names"],
+            json!({ "type": "array", "items": { "type": "string" } })
+        );
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(
+            anchor("users.users/elemType"),
+            "type_users.users_2felemType"
+        );
+        assert_eq!(anchor("a-b_c"), "type_a-b__c");
+        // `_` is escaped as well, so ids never share an anchor
+        assert_ne!(anchor("a/b"), anchor("a_2fb"));
+    }
+}
@@ -1,10 +1,26 @@
-use druid_widget_nursery::TreeNode;
 use crate::data::DisplayData;
+#[cfg(feature = "gui")]
+use druid_widget_nursery::TreeNode;
+
+/// A tree of options as the user sees it, independent of any GUI toolkit
+pub trait OptionTreeNode: Sized {
+    /// Number of children currently shown, e.g. only the ones with errors
+    fn visible_children_count(&self) -> usize;
+    fn visible_child(&self, index: usize) -> &Self;
+    fn visible_child_mut(&mut self, index: usize) -> &mut Self;
 
-pub trait TreeOptionNode: TreeNode {
     fn get_icon(&self) -> String;
+    /// Name of this node as part of an option path, e.g. `users.<name>` for `users.users`
+    fn get_path_segment(&self) -> String;
     fn focused_display_data(&self) -> DisplayData;
 
     fn is_expanded(&self) -> bool;
     fn toggle_expanded(&mut self);
 }
+
+/// An `OptionTreeNode` the druid `Tree` widget can show
+#[cfg(feature = "gui")]
+pub trait TreeOptionNode: OptionTreeNode + TreeNode {}
+
+#[cfg(feature = "gui")]
+impl<T: OptionTreeNode + TreeNode> TreeOptionNode for T {}
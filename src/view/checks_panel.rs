@@ -0,0 +1,54 @@
+use crate::checks::{CheckKind, ConfigCheck};
+use crate::data::DisplayData;
+use crate::delegate::FOCUS_OPTION;
+use druid::widget::{Button, CrossAxisAlignment, Flex, Label, LineBreaking, Scroll};
+use druid::{Color, Data, Widget, WidgetExt};
+
+/// A check together with the options named in its message
+pub struct LinkedCheck {
+    pub check: ConfigCheck,
+    /// Path and display data of each option, the button of each focuses it
+    pub options: Vec<(String, DisplayData)>,
+}
+
+/// Lists failing assertions and warnings, see `checks::collect_checks`.
+/// The data is unused, the checks don't change while browsing.
+pub fn checks_panel<T: Data>(checks: Vec<LinkedCheck>) -> impl Widget<T> {
+    let failed = checks
+        .iter()
+        .filter(|c| c.check.kind == CheckKind::FailedAssertion)
+        .count();
+    let title = format!(
+        "Failed assertions: {}, warnings: {}",
+        failed,
+        checks.len() - failed
+    );
+
+    let mut panel = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(title).with_text_size(18.0));
+
+    for LinkedCheck { check, options } in checks {
+        let color = match check.kind {
+            CheckKind::FailedAssertion => Color::rgb8(0xe0, 0x40, 0x40),
+            CheckKind::Warning => Color::rgb8(0xe0, 0xa0, 0x20),
+        };
+        panel.add_spacer(8.0);
+        panel.add_child(
+            Label::new(check.to_string())
+                .with_text_color(color)
+                .with_line_break_mode(LineBreaking::WordWrap),
+        );
+
+        let mut links = Flex::row();
+        for (path, display) in options {
+            links.add_child(Button::new(path).on_click(move |ctx, _data, _env| {
+                ctx.submit_command(FOCUS_OPTION.with(display.clone()))
+            }));
+            links.add_default_spacer();
+        }
+        panel.add_child(links);
+    }
+
+    Scroll::new(panel.padding(8.0)).vertical()
+}
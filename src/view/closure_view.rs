@@ -0,0 +1,91 @@
+use crate::closure::{format_size, short_name, Closure};
+use crate::data::DisplayData;
+use druid::widget::{CrossAxisAlignment, Flex, Label, LineBreaking, Scroll};
+use druid::{Widget, WidgetExt};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// How many of the largest paths of the closure to list
+const LARGEST_COUNT: usize = 25;
+
+/// Summary of the system closure, with why the system depends on the packages
+/// of the focused option. `closure` is an error message if it couldn't be read.
+pub fn closure_view(closure: Result<Closure, String>) -> impl Widget<DisplayData> {
+    let closure = match closure {
+        Ok(closure) => Arc::new(closure),
+        Err(e) => {
+            let message = format!("The system closure is not available:\n\n{e}");
+            return Flex::column().with_child(
+                Label::new(message)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(8.0),
+            );
+        }
+    };
+
+    let mut summary = format!(
+        "System: {}\nClosure size: {} in {} paths\n\nLargest paths:",
+        closure.root,
+        format_size(closure.closure_size()),
+        closure.paths.len()
+    );
+    for info in closure.largest(LARGEST_COUNT) {
+        write!(
+            summary,
+            "\n{:>10}  {}",
+            format_size(info.nar_size),
+            info.path
+        )
+        .unwrap();
+    }
+
+    let focused = Label::dynamic(move |data: &DisplayData, _| focused_packages(&closure, data))
+        .with_line_break_mode(LineBreaking::WordWrap);
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(focused.padding(8.0))
+        .with_flex_child(
+            Scroll::new(Label::new(summary).padding(8.0)).vertical(),
+            1.0,
+        )
+}
+
+fn focused_packages(closure: &Closure, data: &DisplayData) -> String {
+    let packages = data
+        .get_value()
+        .map(|v| v.derivations())
+        .unwrap_or_default();
+    if packages.is_empty() {
+        return "Select an option containing packages to see why the system depends on them."
+            .to_string();
+    }
+
+    let mut out = String::new();
+    for package in packages {
+        let path = match package.out_path {
+            Some(ref path) => path,
+            None => continue,
+        };
+        match (closure.paths.get(path), closure.why_depends(path)) {
+            (Some(info), Some(chain)) => {
+                let closure_size = info
+                    .closure_size
+                    .map(|size| format!(", closure {}", format_size(size)))
+                    .unwrap_or_default();
+                let chain: Vec<&str> = chain.into_iter().map(short_name).collect();
+                write!(
+                    out,
+                    "{}: {}{}\n    {}\n",
+                    short_name(path),
+                    format_size(info.nar_size),
+                    closure_size,
+                    chain.join(" → ")
+                )
+                .unwrap();
+            }
+            _ => writeln!(out, "{}: not part of the system closure", short_name(path)).unwrap(),
+        }
+    }
+    out
+}
@@ -0,0 +1,32 @@
+use crate::diff::{DiffCategory, OptionsDiff};
+use druid::commands::SHOW_SAVE_PANEL;
+use druid::widget::{Button, CrossAxisAlignment, Flex, Label, LineBreaking, Scroll};
+use druid::{FileDialogOptions, Widget, WidgetExt};
+
+/// Categorized report of an `OptionsDiff`, the button asks where to export it as Markdown.
+/// Saving is left to `delegate::DiffDelegate`.
+pub fn diff_report(diff: &OptionsDiff) -> impl Widget<()> {
+    let export_button = Button::new("Export as Markdown…").on_click(|ctx, _data, _env| {
+        let options = FileDialogOptions::new().title("Export options diff as Markdown");
+        ctx.submit_command(SHOW_SAVE_PANEL.with(options))
+    });
+
+    let mut report = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(export_button);
+
+    for category in DiffCategory::ALL {
+        let lines: Vec<String> = diff
+            .in_category(category)
+            .map(|entry| format!("{}: {}", entry.path, entry.change))
+            .collect();
+
+        report.add_spacer(12.0);
+        report
+            .add_child(Label::new(format!("{} ({})", category, lines.len())).with_text_size(20.0));
+        // A single label per category, there may be thousands of added options
+        report.add_child(Label::new(lines.join("\n")).with_line_break_mode(LineBreaking::WordWrap));
+    }
+
+    Scroll::new(report.padding(8.0)).vertical()
+}
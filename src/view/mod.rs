@@ -1,3 +1,9 @@
+mod checks_panel;
+mod closure_view;
+mod diff_report;
 mod opener;
 
+pub use checks_panel::{checks_panel, LinkedCheck};
+pub use closure_view::closure_view;
+pub use diff_report::diff_report;
 pub use opener::Opener;
@@ -0,0 +1,140 @@
+//! Watching the files of a local flake, so its config can be evaluated again when they
+//! change. Uses inotify on Linux through `notify`.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How long the files have to stay unchanged before `FlakeWatcher::wait` returns,
+/// editors and `git checkout` write several files (or one file several times) at once
+pub const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+pub struct FlakeWatcher {
+    /// Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    changes: Receiver<PathBuf>,
+}
+
+impl FlakeWatcher {
+    /// Watch the directory of a flake and everything below it
+    pub fn new(dir: &Path) -> notify::Result<Self> {
+        let root = dir.to_path_buf();
+        let (sender, changes) = channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Watching the flake failed: {}", e);
+                    return;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            for path in event.paths {
+                if is_relevant(&root, &path) {
+                    // Only fails once the receiving `FlakeWatcher` is dropped
+                    let _ = sender.send(path);
+                }
+            }
+        })?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Block until files changed and then stayed unchanged for `SETTLE_TIME`,
+    /// returns the changed files. `None` if watching stopped.
+    pub fn wait(&self) -> Option<Vec<PathBuf>> {
+        let mut changed = vec![self.changes.recv().ok()?];
+        loop {
+            match self.changes.recv_timeout(SETTLE_TIME) {
+                Ok(path) => changed.push(path),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+        changed.sort();
+        changed.dedup();
+        Some(changed)
+    }
+}
+
+/// The directory of a flake given as a path, `None` for other flake references,
+/// e.g. `github:owner/repo`
+pub fn flake_dir(flake: &str) -> Option<PathBuf> {
+    let path = Path::new(flake.strip_prefix("path:").unwrap_or(flake));
+    path.is_dir().then(|| path.to_path_buf())
+}
+
+/// Whether a change to `path` below the flake at `root` can change the config.
+/// Hidden files and directories (`.git`, `.direnv`), the `result` links of `nix build`
+/// and the temporary files of editors are left out.
+pub fn is_relevant(root: &Path, path: &Path) -> bool {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative,
+        Err(_) => return false,
+    };
+    let first = match relative.iter().next() {
+        Some(first) => first.to_string_lossy(),
+        // The flake directory itself
+        None => return false,
+    };
+    if first == "result" || first.starts_with("result-") {
+        return false;
+    }
+
+    let file_name = relative
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    // `4913` is the file vim creates to check whether it may write to a directory
+    let temporary = file_name.ends_with('~')
+        || file_name.ends_with(".swp")
+        || file_name.ends_with(".swx")
+        || file_name == "4913";
+
+    !temporary
+        && relative
+            .iter()
+            .all(|name| !name.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relevant_paths() {
+        let root = Path::new("/etc/nixos");
+        for path in [
+            "/etc/nixos/flake.nix",
+            "/etc/nixos/flake.lock",
+            "/etc/nixos/hosts/laptop/default.nix",
+            "/etc/nixos/secrets/wifi.json",
+        ] {
+            assert!(is_relevant(root, Path::new(path)), "{} is relevant", path);
+        }
+        for path in [
+            "/etc/nixos",
+            "/etc/nixos/.git/index",
+            "/etc/nixos/.direnv/flake-profile",
+            "/etc/nixos/result",
+            "/etc/nixos/result-laptop/activate",
+            "/etc/nixos/hosts/.default.nix.swp",
+            "/etc/nixos/hosts/default.nix~",
+            "/etc/nixos/hosts/4913",
+            "/etc/other/flake.nix",
+        ] {
+            assert!(
+                !is_relevant(root, Path::new(path)),
+                "{} is irrelevant",
+                path
+            );
+        }
+    }
+}